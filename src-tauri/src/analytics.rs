@@ -0,0 +1,249 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, Result};
+use tauri::{command, Window};
+use chrono::NaiveDate;
+
+use crate::cart::get_db_path;
+use crate::reports::day_bounds;
+use crate::sales::round_money;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProductSales {
+    pub product_id: i64,
+    pub name: String,
+    pub category: String,
+    pub quantity: f64,
+    pub revenue: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TimeBucket {
+    pub bucket: i64, // hour 0-23, or weekday 0 = Sunday .. 6 = Saturday
+    pub invoice_count: i64,
+    pub revenue: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Margin {
+    pub key: String, // product name or category
+    pub product_id: Option<i64>,
+    pub revenue: f64,
+    pub cost: f64,
+    pub margin: f64,
+    pub margin_pct: Option<f64>,
+    pub missing_cost_lines: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PurchasingTypeMix {
+    pub purchasing_type: String,
+    pub line_count: i64,
+    pub quantity: f64,
+    pub revenue: f64,
+    pub revenue_share: f64,
+}
+
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_invoices_status_paid_at ON invoices(status, paid_at)", params![])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_invoice_items_product_id ON invoice_items(product_id)", params![])?;
+    Ok(())
+}
+
+/// Inclusive local business days converted to a half-open UTC range on
+/// `invoices.paid_at`, so every query can use the (status, paid_at) index.
+fn range(from: &str, to: &str) -> Result<(String, String), String> {
+    let from = NaiveDate::parse_from_str(from, "%Y-%m-%d").map_err(|_| format!("Invalid start date: {}", from))?;
+    let to = NaiveDate::parse_from_str(to, "%Y-%m-%d").map_err(|_| format!("Invalid end date: {}", to))?;
+    if to < from {
+        return Err("End date is before start date".to_string());
+    }
+    Ok((day_bounds(from).0, day_bounds(to).1))
+}
+
+// Paid lines in the range with revenue net of VAT (`tax_amount` is inside the
+// price when prices include tax) and net of anything returned from the line
+// since, so quantity and revenue shrink by the returned share.
+const NET_LINES: &str = "SELECT invoice_id, paid_at, product_id, purchasing_type,
+        quantity - returned AS quantity, net * (quantity - returned) / NULLIF(quantity, 0) AS revenue
+    FROM (SELECT i.invoice_id, i.paid_at, it.product_id, it.purchasing_type, it.quantity,
+                 it.quantity * it.price - it.discount - CASE WHEN i.prices_include_tax THEN it.tax_amount ELSE 0 END AS net,
+                 (SELECT COALESCE(SUM(ri.quantity), 0) FROM returns r JOIN return_items ri ON ri.return_id = r.return_id
+                  WHERE r.invoice_id = it.invoice_id AND ri.line_no = it.line_no) AS returned
+          FROM invoices i JOIN invoice_items it ON it.invoice_id = i.invoice_id
+          WHERE i.status = 'completed' AND i.paid_at >= ?1 AND i.paid_at < ?2)";
+
+// Cost is stored per single unit; a bulk line consumes `Bulk_single_conversion` of them.
+const LINE_COST: &str = "l.quantity * p.Cost * CASE WHEN l.purchasing_type = 'bulk' THEN COALESCE(p.Bulk_single_conversion, 1) ELSE 1 END";
+
+#[command]
+pub fn top_sellers(window: Window, from: String, to: String, order_by: Option<String>, limit: Option<i64>) -> Result<Vec<ProductSales>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let (start, end) = range(&from, &to)?;
+    let order = match order_by.as_deref() {
+        None | Some("revenue") => "revenue",
+        Some("quantity") => "quantity",
+        Some(other) => return Err(format!("Cannot order by {}", other)),
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT l.product_id, COALESCE(p.Item_name, ''), COALESCE(p.Category, ''),
+                SUM(l.quantity) AS quantity, SUM(l.revenue) AS revenue
+         FROM ({}) l
+         LEFT JOIN products p ON p.rowid = l.product_id
+         GROUP BY l.product_id HAVING SUM(l.quantity) > 0 ORDER BY {} DESC LIMIT ?3",
+        NET_LINES, order
+    ))
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let rows = stmt.query_map(params![start, end, limit.unwrap_or(20)], |row| {
+        Ok(ProductSales {
+            product_id: row.get(0)?,
+            name: row.get(1)?,
+            category: row.get(2)?,
+            quantity: row.get(3)?,
+            revenue: round_money(row.get(4)?),
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(rows)
+}
+
+fn time_buckets(conn: &Connection, start: &str, end: &str, format: &str, size: i64) -> Result<Vec<TimeBucket>, String> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT CAST(strftime('{}', l.paid_at, 'localtime') AS INTEGER) AS bucket, COUNT(DISTINCT l.invoice_id), SUM(l.revenue)
+         FROM ({}) l GROUP BY bucket",
+        format, NET_LINES
+    ))
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let mut buckets: Vec<TimeBucket> = (0..size)
+        .map(|bucket| TimeBucket { bucket, invoice_count: 0, revenue: 0.0 })
+        .collect();
+    let rows = stmt.query_map(params![start, end], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, f64>(2)?)))
+        .map_err(|e| format!("Failed to query: {}", e))?;
+    for (bucket, count, revenue) in rows.filter_map(|r| r.ok()) {
+        if let Some(b) = buckets.get_mut(bucket as usize) {
+            b.invoice_count = count;
+            b.revenue = round_money(revenue);
+        }
+    }
+    Ok(buckets)
+}
+
+/// Sales per local hour of day, always 24 buckets.
+#[command]
+pub fn sales_by_hour(window: Window, from: String, to: String) -> Result<Vec<TimeBucket>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let (start, end) = range(&from, &to)?;
+    time_buckets(&conn, &start, &end, "%H", 24)
+}
+
+/// Sales per local weekday, always 7 buckets starting on Sunday.
+#[command]
+pub fn sales_by_weekday(window: Window, from: String, to: String) -> Result<Vec<TimeBucket>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let (start, end) = range(&from, &to)?;
+    time_buckets(&conn, &start, &end, "%w", 7)
+}
+
+fn margins(conn: &Connection, start: &str, end: &str, by_category: bool) -> Result<Vec<Margin>, String> {
+    let (key, product_id, group) = if by_category {
+        let category = "COALESCE(NULLIF(p.Category, ''), '(none)')";
+        (category, "NULL", category)
+    } else {
+        ("COALESCE(p.Item_name, '#' || l.product_id)", "l.product_id", "l.product_id")
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {key}, {product_id},
+                SUM(l.revenue) AS revenue,
+                SUM(COALESCE({cost}, 0)),
+                SUM(CASE WHEN p.Cost IS NULL THEN 1 ELSE 0 END)
+         FROM ({lines}) l
+         LEFT JOIN products p ON p.rowid = l.product_id
+         GROUP BY {group_expr} ORDER BY revenue DESC",
+        key = key,
+        product_id = product_id,
+        cost = LINE_COST,
+        lines = NET_LINES,
+        group_expr = group,
+    ))
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let rows = stmt.query_map(params![start, end], |row| {
+        let revenue: f64 = row.get(2)?;
+        let cost: f64 = row.get(3)?;
+        Ok(Margin {
+            key: row.get(0)?,
+            product_id: row.get(1)?,
+            revenue: round_money(revenue),
+            cost: round_money(cost),
+            margin: round_money(revenue - cost),
+            margin_pct: if revenue != 0.0 { Some(round_money((revenue - cost) / revenue * 100.0)) } else { None },
+            missing_cost_lines: row.get(4)?,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(rows)
+}
+
+/// Gross margin from the products' `Cost`. Lines whose product has no cost
+/// count as zero cost and are reported in `missing_cost_lines`.
+#[command]
+pub fn product_margins(window: Window, from: String, to: String) -> Result<Vec<Margin>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let (start, end) = range(&from, &to)?;
+    margins(&conn, &start, &end, false)
+}
+
+#[command]
+pub fn category_margins(window: Window, from: String, to: String) -> Result<Vec<Margin>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let (start, end) = range(&from, &to)?;
+    margins(&conn, &start, &end, true)
+}
+
+#[command]
+pub fn purchasing_type_mix(window: Window, from: String, to: String) -> Result<Vec<PurchasingTypeMix>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let (start, end) = range(&from, &to)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT l.purchasing_type, COUNT(*), SUM(l.quantity), SUM(l.revenue)
+         FROM ({}) l GROUP BY l.purchasing_type ORDER BY l.purchasing_type",
+        NET_LINES
+    ))
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let mut mix: Vec<PurchasingTypeMix> = stmt.query_map(params![start, end], |row| {
+        Ok(PurchasingTypeMix {
+            purchasing_type: row.get(0)?,
+            line_count: row.get(1)?,
+            quantity: row.get(2)?,
+            revenue: round_money(row.get(3)?),
+            revenue_share: 0.0,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    let total: f64 = mix.iter().map(|m| m.revenue).sum();
+    if total != 0.0 {
+        for m in mix.iter_mut() {
+            m.revenue_share = round_money(m.revenue / total * 100.0);
+        }
+    }
+    Ok(mix)
+}
+
+commands![
+    top_sellers,
+    sales_by_hour,
+    sales_by_weekday,
+    product_margins,
+    category_margins,
+    purchasing_type_mix,
+];
@@ -0,0 +1,325 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::backup::Backup;
+use rusqlite::{params, Connection, OpenFlags};
+use chrono::{NaiveDateTime, Utc};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{command, AppHandle, Manager, Window};
+
+use crate::cart::{db_path_from_config, get_db_path};
+use crate::settings::Settings;
+
+const FILE_PREFIX: &str = "inventory-";
+const FILE_TIME_FORMAT: &str = "%Y%m%d-%H%M%S-%3f";
+const CHECK_INTERVAL: Duration = Duration::from_secs(600);
+// Pages copied per step; the pause between steps lets sales keep writing.
+const PAGES_PER_STEP: i32 = 256;
+const STEP_PAUSE: Duration = Duration::from_millis(20);
+
+/// When backups run and how many are kept.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BackupPolicy {
+    pub enabled: bool,        // scheduled backups; backup_now always works
+    pub interval_hours: u32,
+    pub keep_count: u32,      // newest files kept
+    pub keep_days: u32,       // older files are deleted; 0 keeps them by count only
+    pub dir: Option<String>,  // None uses "backups" next to the database
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        BackupPolicy {
+            enabled: true,
+            interval_hours: 24,
+            keep_count: 14,
+            keep_days: 0,
+            dir: None,
+        }
+    }
+}
+
+impl BackupPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=168).contains(&self.interval_hours) {
+            return Err("Backup interval must be between 1 and 168 hours".to_string());
+        }
+        if self.keep_count == 0 {
+            return Err("At least one backup must be kept".to_string());
+        }
+        if self.dir.as_ref().is_some_and(|d| d.trim().is_empty()) {
+            return Err("Backup directory is required".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn dir_for(&self, db_path: &Path) -> PathBuf {
+        match &self.dir {
+            Some(dir) => PathBuf::from(dir),
+            None => db_path.with_file_name("backups"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupFile {
+    pub path: String,
+    pub file_name: String,
+    pub size: u64,
+    pub created_at: String,
+}
+
+/// Problems reported by `PRAGMA integrity_check`; empty when the file is sound.
+pub fn integrity_problems(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check").map_err(|e| format!("Failed to check integrity: {}", e))?;
+    let rows: Vec<String> = stmt.query_map(params![], |row| row.get(0))
+        .map_err(|e| format!("Failed to check integrity: {}", e))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows.into_iter().filter(|r| r != "ok").collect())
+}
+
+fn backup_time(file_name: &str) -> Option<NaiveDateTime> {
+    let stamp = file_name.strip_prefix(FILE_PREFIX)?.strip_suffix(".db")?;
+    NaiveDateTime::parse_from_str(stamp, FILE_TIME_FORMAT).ok()
+}
+
+/// Backups in `dir`, newest first. Other files in the directory are ignored.
+pub fn list_backup_files(dir: &Path) -> Result<Vec<BackupFile>, String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {}: {}", dir.display(), e)),
+    };
+    let mut files: Vec<(NaiveDateTime, BackupFile)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let time = backup_time(&file_name)?;
+            Some((time, BackupFile {
+                path: entry.path().to_string_lossy().to_string(),
+                size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                created_at: time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                file_name,
+            }))
+        })
+        .collect();
+    files.sort_by_key(|(time, _)| std::cmp::Reverse(*time));
+    Ok(files.into_iter().map(|(_, file)| file).collect())
+}
+
+pub fn latest_backup(db_path: &Path, policy: &BackupPolicy) -> Result<Option<BackupFile>, String> {
+    Ok(list_backup_files(&policy.dir_for(db_path))?.into_iter().next())
+}
+
+fn prune(dir: &Path, policy: &BackupPolicy) -> Result<(), String> {
+    let cutoff = Utc::now().naive_utc() - chrono::Duration::days(policy.keep_days as i64);
+    for (index, file) in list_backup_files(dir)?.iter().enumerate() {
+        let expired = policy.keep_days > 0 && backup_time(&file.file_name).is_some_and(|t| t < cutoff);
+        // The newest backup survives any age limit.
+        if index > 0 && (index >= policy.keep_count as usize || expired) {
+            std::fs::remove_file(&file.path).map_err(|e| format!("Failed to delete {}: {}", file.path, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies the live database and applies the retention policy.
+pub fn run_backup(db_path: &Path, policy: &BackupPolicy) -> Result<BackupFile, String> {
+    let file = write_backup(db_path, policy)?;
+    prune(&policy.dir_for(db_path), policy)?;
+    Ok(file)
+}
+
+/// Copies the live database with SQLite's online backup API and checks the
+/// copy. The copy is written under a temporary name so a half-written file
+/// is never taken for a backup.
+fn write_backup(db_path: &Path, policy: &BackupPolicy) -> Result<BackupFile, String> {
+    let dir = policy.dir_for(db_path);
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let mut stamp = Utc::now().naive_utc();
+    let mut target = dir.join(format!("{}{}.db", FILE_PREFIX, stamp.format(FILE_TIME_FORMAT)));
+    while target.exists() {
+        stamp += chrono::Duration::milliseconds(1);
+        target = dir.join(format!("{}{}.db", FILE_PREFIX, stamp.format(FILE_TIME_FORMAT)));
+    }
+    let partial = target.with_extension("db.partial");
+
+    let src = Connection::open(db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    {
+        let mut dst = Connection::open(&partial).map_err(|e| format!("Failed to create backup: {}", e))?;
+        Backup::new(&src, &mut dst)
+            .and_then(|backup| backup.run_to_completion(PAGES_PER_STEP, STEP_PAUSE, None))
+            .map_err(|e| format!("Failed to back up database: {}", e))?;
+        let problems = integrity_problems(&dst)?;
+        if !problems.is_empty() {
+            drop(dst);
+            let _ = std::fs::remove_file(&partial);
+            return Err(format!("Backup failed its integrity check: {}", problems.join("; ")));
+        }
+    }
+    std::fs::rename(&partial, &target).map_err(|e| format!("Failed to save backup: {}", e))?;
+
+    let file_name = target.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    Ok(BackupFile {
+        path: target.to_string_lossy().to_string(),
+        size: std::fs::metadata(&target).map(|m| m.len()).unwrap_or(0),
+        created_at: stamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+        file_name,
+    })
+}
+
+/// Refuses files that are damaged or are not an AnPOS database.
+pub fn verify_database(source: &Path) -> Result<(), String> {
+    let check = Connection::open_with_flags(source, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open {}: {}", source.display(), e))?;
+    let problems = integrity_problems(&check)?;
+    if !problems.is_empty() {
+        return Err(format!("{} is damaged: {}", source.display(), problems.join("; ")));
+    }
+    let has_products: bool = check
+        .query_row("SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'products')", params![], |row| row.get(0))
+        .map_err(|e| format!("Failed to read {}: {}", source.display(), e))?;
+    if !has_products {
+        return Err(format!("{} is not an AnPOS database", source.display()));
+    }
+    Ok(())
+}
+
+/// Copies `source` over the live database page by page. Connections that
+/// are already open see the restored data on their next statement.
+pub fn overwrite_with(db_path: &Path, source: &Path) -> Result<(), String> {
+    let src = Connection::open_with_flags(source, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open {}: {}", source.display(), e))?;
+    let mut dst = Connection::open(db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    Backup::new(&src, &mut dst)
+        .and_then(|backup| backup.run_to_completion(PAGES_PER_STEP, STEP_PAUSE, None))
+        .map_err(|e| format!("Failed to restore database: {}", e))
+}
+
+/// Replaces the live database with `source` after checking it. The current
+/// data is backed up first, so a wrong pick can itself be undone. That backup
+/// is not pruned: `source` may be the oldest one kept.
+pub fn restore_from(db_path: &Path, source: &Path, policy: &BackupPolicy) -> Result<BackupFile, String> {
+    verify_database(source)?;
+    let safety = write_backup(db_path, policy)?;
+    overwrite_with(db_path, source)?;
+    Ok(safety)
+}
+
+fn backup_due(db_path: &Path, policy: &BackupPolicy) -> Result<bool, String> {
+    let last = latest_backup(db_path, policy)?.and_then(|file| backup_time(&file.file_name));
+    Ok(match last {
+        Some(last) => Utc::now().naive_utc() - last >= chrono::Duration::hours(policy.interval_hours as i64),
+        None => true,
+    })
+}
+
+/// Checks every ten minutes whether a scheduled backup is due. The policy is
+/// re-read each time, so settings changes apply without a restart.
+pub fn spawn_backup_worker(app: AppHandle) {
+    std::thread::spawn(move || loop {
+        let db_path = db_path_from_config(&app.config());
+        let result = Connection::open(&db_path)
+            .map_err(|e| format!("Failed to open DB: {}", e))
+            .and_then(|conn| Settings::load(&conn))
+            .and_then(|settings| {
+                let policy = settings.backup;
+                if policy.enabled && backup_due(&db_path, &policy)? {
+                    run_backup(&db_path, &policy).map(Some)
+                } else {
+                    Ok(None)
+                }
+            });
+        match result {
+            Ok(Some(file)) => {
+                let _ = app.emit_all("backup-completed", file);
+            }
+            Ok(None) => {}
+            Err(e) => println!("[backup] {}", e),
+        }
+        std::thread::sleep(CHECK_INTERVAL);
+    });
+}
+
+fn load_policy(db_path: &Path) -> Result<BackupPolicy, String> {
+    let conn = Connection::open(db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    Ok(Settings::load(&conn)?.backup)
+}
+
+#[command]
+pub fn backup_now(window: Window) -> Result<BackupFile, String> {
+    let db_path = get_db_path(&window);
+    run_backup(&db_path, &load_policy(&db_path)?)
+}
+
+#[command]
+pub fn list_backups(window: Window) -> Result<Vec<BackupFile>, String> {
+    let db_path = get_db_path(&window);
+    list_backup_files(&load_policy(&db_path)?.dir_for(&db_path))
+}
+
+/// Restores a backup (or any checked AnPOS database file) and returns the
+/// backup taken of the data it replaced. Restart the app afterwards so every
+/// window reloads its state.
+#[command]
+pub fn restore_backup(window: Window, path: String) -> Result<BackupFile, String> {
+    let db_path = get_db_path(&window);
+    let safety = restore_from(&db_path, Path::new(&path), &load_policy(&db_path)?)?;
+    let _ = window.emit("database-restored", safety.clone());
+    Ok(safety)
+}
+
+commands![
+    backup_now,
+    list_backups,
+    restore_backup,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(db_path: &Path) -> f64 {
+        let conn = Connection::open(db_path).unwrap();
+        conn.query_row("SELECT Retail_price FROM products", params![], |row| row.get(0)).unwrap()
+    }
+
+    fn set_price(db_path: &Path, price: f64) {
+        let conn = Connection::open(db_path).unwrap();
+        conn.execute("UPDATE products SET Retail_price = ?1", params![price]).unwrap();
+    }
+
+    #[test]
+    fn restores_the_oldest_kept_backup() {
+        let dir = std::env::temp_dir().join(format!("anpos-backup-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("inventory.db");
+        {
+            let conn = Connection::open(&db_path).unwrap();
+            conn.execute_batch("CREATE TABLE products (Item_name VARCHAR, Retail_price FLOAT); INSERT INTO products VALUES ('Trà', 1000);").unwrap();
+        }
+        let policy = BackupPolicy { keep_count: 2, ..BackupPolicy::default() };
+
+        let first = run_backup(&db_path, &policy).unwrap();
+        set_price(&db_path, 2000.0);
+        run_backup(&db_path, &policy).unwrap();
+        set_price(&db_path, 3000.0);
+        let newest = run_backup(&db_path, &policy).unwrap();
+        // The oldest copy is pruned once two newer ones exist.
+        let kept = list_backup_files(&policy.dir_for(&db_path)).unwrap();
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].file_name, newest.file_name);
+        assert!(!Path::new(&first.path).exists());
+
+        // Restoring the oldest kept backup must not prune it away first.
+        let oldest = kept[1].clone();
+        set_price(&db_path, 4000.0);
+        let safety = restore_from(&db_path, Path::new(&oldest.path), &policy).unwrap();
+        assert_eq!(price(&db_path), 2000.0);
+        assert_eq!(price(Path::new(&safety.path)), 4000.0);
+        assert!(Path::new(&oldest.path).exists());
+
+        assert!(restore_from(&db_path, &dir.join("missing.db"), &policy).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
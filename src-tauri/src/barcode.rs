@@ -0,0 +1,165 @@
+use serde::{Serialize, Deserialize};
+use tauri::command;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Symbology {
+    Ean8,
+    Ean13,
+    UpcA,
+    UpcE,
+    Code128,
+}
+
+/// A validated barcode. `code` is the canonical form used for storage and
+/// lookups: UPC-E is expanded to UPC-A and UPC-A is padded to 13 digits.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Barcode {
+    pub symbology: Symbology,
+    pub code: String,
+}
+
+impl Barcode {
+    /// Forms the same code may have been stored in by older builds.
+    pub fn lookup_keys(&self) -> Vec<String> {
+        let mut keys = vec![self.code.clone()];
+        if self.code.len() == 13 && self.code.starts_with('0') {
+            keys.push(self.code[1..].to_string());
+        }
+        keys
+    }
+}
+
+/// GS1 mod-10 check digit over the digits before it (EAN-8/13, UPC-A).
+/// Callers make sure `body` is all digits.
+pub fn gs1_check_digit(body: &str) -> u32 {
+    let sum: u32 = body
+        .chars()
+        .rev()
+        .enumerate()
+        .map(|(i, c)| c.to_digit(10).unwrap_or(0) * if i % 2 == 0 { 3 } else { 1 })
+        .sum();
+    (10 - sum % 10) % 10
+}
+
+fn check(code: &str, name: &str) -> Result<(), String> {
+    let (body, digit) = code.split_at(code.len() - 1);
+    let expected = gs1_check_digit(body);
+    if digit.parse::<u32>().ok() != Some(expected) {
+        return Err(format!("Invalid barcode {}: {} check digit should be {}", code, name, expected));
+    }
+    Ok(())
+}
+
+/// Expands an 8-digit UPC-E (number system, six digits, check) to UPC-A.
+pub fn upce_to_upca(upce: &str) -> Option<String> {
+    if upce.len() != 8 || !upce.chars().all(|c| c.is_ascii_digit()) || !matches!(&upce[..1], "0" | "1") {
+        return None;
+    }
+    let d: Vec<&str> = (1..7).map(|i| &upce[i..i + 1]).collect();
+    let body = match d[5] {
+        "0" | "1" | "2" => format!("{}{}{}0000{}{}{}", d[0], d[1], d[5], d[2], d[3], d[4]),
+        "3" => format!("{}{}{}00000{}{}", d[0], d[1], d[2], d[3], d[4]),
+        "4" => format!("{}{}{}{}00000{}", d[0], d[1], d[2], d[3], d[4]),
+        _ => format!("{}{}{}{}{}0000{}", d[0], d[1], d[2], d[3], d[4], d[5]),
+    };
+    Some(format!("{}{}{}", &upce[..1], body, &upce[7..]))
+}
+
+/// Validates a scanned or typed barcode. Numeric codes of EAN/UPC length must
+/// carry a correct check digit; anything else printable is taken as Code128,
+/// whose checksum the scanner has already verified.
+pub fn normalize(input: &str) -> Result<Barcode, String> {
+    let code: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.is_empty() {
+        return Err("Invalid barcode: empty".to_string());
+    }
+    if code.chars().all(|c| c.is_ascii_digit()) {
+        match code.len() {
+            8 => {
+                if check(&code, "EAN-8").is_ok() {
+                    return Ok(Barcode { symbology: Symbology::Ean8, code });
+                }
+                if let Some(upca) = upce_to_upca(&code) {
+                    if check(&upca, "UPC-E").is_ok() {
+                        return Ok(Barcode { symbology: Symbology::UpcE, code: format!("0{}", upca) });
+                    }
+                }
+                return Err(format!("Invalid barcode {}: neither a valid EAN-8 nor UPC-E", code));
+            }
+            12 => {
+                check(&code, "UPC-A")?;
+                return Ok(Barcode { symbology: Symbology::UpcA, code: format!("0{}", code) });
+            }
+            13 => {
+                check(&code, "EAN-13")?;
+                let symbology = if code.starts_with('0') { Symbology::UpcA } else { Symbology::Ean13 };
+                return Ok(Barcode { symbology, code });
+            }
+            _ => {}
+        }
+    }
+    if code.len() > 80 || !code.chars().all(|c| (' '..='~').contains(&c)) {
+        return Err(format!("Invalid barcode {}: not a Code128 value", code));
+    }
+    Ok(Barcode { symbology: Symbology::Code128, code })
+}
+
+#[command]
+pub fn validate_barcode(barcode: String) -> Result<Barcode, String> {
+    normalize(&barcode)
+}
+
+commands![
+    validate_barcode,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_gs1_check_digits() {
+        assert_eq!(gs1_check_digit("590123412345"), 7);
+        assert_eq!(gs1_check_digit("03600029145"), 2);
+        assert_eq!(gs1_check_digit("9638507"), 4);
+        assert_eq!(gs1_check_digit("893458801211"), 2);
+    }
+
+    #[test]
+    fn normalizes_ean_and_upc() {
+        assert_eq!(normalize("5901234123457"), Ok(Barcode { symbology: Symbology::Ean13, code: "5901234123457".to_string() }));
+        assert_eq!(normalize("9638 5074"), Ok(Barcode { symbology: Symbology::Ean8, code: "96385074".to_string() }));
+        // UPC-A is stored as 13 digits with a leading zero.
+        assert_eq!(normalize("036000291452"), Ok(Barcode { symbology: Symbology::UpcA, code: "0036000291452".to_string() }));
+        assert_eq!(normalize("0036000291452").map(|b| b.symbology), Ok(Symbology::UpcA));
+    }
+
+    #[test]
+    fn expands_upce() {
+        assert_eq!(upce_to_upca("04252614").as_deref(), Some("042100005264"));
+        assert_eq!(normalize("04252614"), Ok(Barcode { symbology: Symbology::UpcE, code: "0042100005264".to_string() }));
+        assert_eq!(upce_to_upca("24252614"), None);
+    }
+
+    #[test]
+    fn rejects_wrong_check_digits() {
+        assert!(normalize("5901234123458").unwrap_err().contains("check digit should be 7"));
+        assert!(normalize("036000291453").is_err());
+        assert!(normalize("12345678").is_err());
+        assert!(normalize("  ").is_err());
+    }
+
+    #[test]
+    fn falls_back_to_code128() {
+        assert_eq!(normalize("SKU-001"), Ok(Barcode { symbology: Symbology::Code128, code: "SKU-001".to_string() }));
+        assert_eq!(normalize("12345").map(|b| b.symbology), Ok(Symbology::Code128));
+        assert!(normalize("Cà phê").is_err());
+    }
+
+    #[test]
+    fn lookup_keys_include_the_short_upc_form() {
+        let barcode = normalize("036000291452").unwrap();
+        assert_eq!(barcode.lookup_keys(), vec!["0036000291452".to_string(), "036000291452".to_string()]);
+    }
+}
@@ -1,16 +1,30 @@
 use serde::{Serialize, Deserialize};
-use rusqlite::{params, Connection, Result};
-use tauri::{command, Window, Manager}; // Ensure Manager is imported
-use chrono::{Utc, Duration};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::{command, Config, Window, Manager}; // Ensure Manager is imported
+use chrono::Utc;
 use tauri::api::path::app_data_dir;
 use std::path::PathBuf;
 
+use crate::cart_expiry;
+use crate::credit;
+use crate::customers;
+use crate::db::add_column_if_missing;
+use crate::promotions;
+use crate::reports;
+use crate::scale;
+use crate::search;
+use crate::sales;
+use crate::settings::Settings;
+use crate::shift;
+use crate::tax;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Cart {
     pub cart_id: i64,
     pub cart_name: String,
     pub status: String,
     pub added_at: String,
+    pub customer_phone: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -18,23 +32,30 @@ pub struct CartItem {
     pub cart_id: i64,
     pub product_id: i64, // Changed to i64 for 5-digit IDs
     pub scanned_barcode: Option<String>,
-    pub quantity: i32,
+    pub quantity: f64, // fractional for weighed and measured units
     pub price: f64,
     pub purchasing_type: String,
     pub discount: f64,
+    pub tax_rate: Option<f64>, // percent, fixed when the item is added
 }
 
-fn get_db_path(window: &Window) -> PathBuf {
-    // Use the window to get the app handle and config
-    let config = window.app_handle().config();
-    app_data_dir(&config)
+pub fn db_path_from_config(config: &Config) -> PathBuf {
+    app_data_dir(config)
         .map(|mut dir| { dir.push("inventory.db"); dir })
         .unwrap_or_else(|| PathBuf::from("inventory.db"))
 }
 
+pub fn get_db_path(window: &Window) -> PathBuf {
+    // Use the window to get the app handle and config
+    let config = window.app_handle().config();
+    db_path_from_config(&config)
+}
+
+pub fn now_string() -> String {
+    Utc::now().naive_utc().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
 pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
-    println!("Initializing tables..."); // Debug output
-    std::thread::sleep(std::time::Duration::from_secs(1)); // Pause to observe
     conn.execute(
         "CREATE TABLE IF NOT EXISTS carts (
             cart_id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -57,16 +78,9 @@ pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
         )",
         params![],
     )?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS products (
-            product_id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name VARCHAR(100) NOT NULL,
-            barcode VARCHAR(255),
-            price FLOAT NOT NULL
-        )",
-        params![],
-    )?;
-    println!("Tables initialized.");
+    add_column_if_missing(conn, "carts", "status_changed_at", "DATETIME")?;
+    add_column_if_missing(conn, "carts", "last_activity_at", "DATETIME")?;
+    add_column_if_missing(conn, "products", "Auto_bulk", "BOOLEAN NOT NULL DEFAULT 1")?;
     Ok(())
 }
 
@@ -76,7 +90,7 @@ pub fn create_cart(window: Window, cart_name: String) -> Result<Cart, String> {
     let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
     let now = Utc::now().naive_utc();
     let added_at = now.format("%Y-%m-%d %H:%M:%S").to_string();
-    let sql = "INSERT INTO carts (cart_name, status, added_at) VALUES (?1, ?2, ?3)";
+    let sql = "INSERT INTO carts (cart_name, status, added_at, status_changed_at) VALUES (?1, ?2, ?3, ?3)";
     conn.execute(sql, params![cart_name, "active", added_at])
         .map_err(|e| format!("Failed to create cart: {}", e))?;
     let cart_id = conn.last_insert_rowid();
@@ -85,6 +99,7 @@ pub fn create_cart(window: Window, cart_name: String) -> Result<Cart, String> {
         cart_name,
         status: "active".to_string(),
         added_at,
+        customer_phone: None,
     };
     Ok(cart)
 }
@@ -99,8 +114,26 @@ pub fn update_cart_name(window: Window, cart_id: i64, cart_name: String) -> Resu
     Ok(())
 }
 
+/// The unit a line is sold in: the product's `Unit`, or `Bulk_unit` for bulk lines.
+fn line_unit(conn: &Connection, product_id: i64, purchasing_type: &str) -> Result<Option<String>, String> {
+    let column = if purchasing_type == "bulk" { "Bulk_unit" } else { "Unit" };
+    let unit: Option<Option<String>> = conn
+        .query_row(&format!("SELECT {} FROM products WHERE rowid = ?1", column), params![product_id], |row| row.get(0))
+        .optional()
+        .map_err(|e| format!("Failed to load product: {}", e))?;
+    Ok(unit.flatten())
+}
+
+/// Marks the cart as in use; idle carts expire from the later of this and
+/// their last status change.
+fn touch_cart(conn: &Connection, cart_id: i64) -> Result<(), String> {
+    conn.execute("UPDATE carts SET last_activity_at = ?1 WHERE cart_id = ?2", params![now_string(), cart_id])
+        .map_err(|e| format!("Failed to update cart: {}", e))?;
+    Ok(())
+}
+
 #[command]
-pub fn add_cart_item(window: Window, cart_id: i64, product_id: i64, scanned_barcode: Option<String>, quantity: i32, price: f64, purchasing_type: String, discount: f64) -> Result<CartItem, String> {
+pub fn add_cart_item(window: Window, cart_id: i64, product_id: i64, scanned_barcode: Option<String>, quantity: f64, price: f64, purchasing_type: String, discount: f64) -> Result<CartItem, String> {
     let db_path = get_db_path(&window);
     let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
     let mut stmt = conn.prepare("SELECT status FROM carts WHERE cart_id = ?1")
@@ -110,19 +143,163 @@ pub fn add_cart_item(window: Window, cart_id: i64, product_id: i64, scanned_barc
     if status != "active" {
         return Err("Cart is not active".to_string());
     }
-    let sql = "INSERT INTO cart_items (cart_id, product_id, scanned_barcode, quantity, price, purchasing_type, discount) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
-    conn.execute(sql, params![cart_id, product_id, scanned_barcode, quantity, price, purchasing_type, discount])
+    let quantity = scale::round_quantity(quantity);
+    scale::validate_quantity(quantity, line_unit(&conn, product_id, &purchasing_type)?.as_deref())?;
+    let settings = Settings::load(&conn)?;
+    let tax_rate = tax::resolve_rate(&conn, product_id, settings.tax_rate)?;
+    let sql = "INSERT INTO cart_items (cart_id, product_id, scanned_barcode, quantity, price, purchasing_type, discount, tax_rate) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)";
+    conn.execute(sql, params![cart_id, product_id, scanned_barcode, quantity, price, purchasing_type, discount, tax_rate])
         .map_err(|e| format!("Failed to add cart item: {}", e))?;
-    let item = CartItem {
+    let mut rowid = conn.last_insert_rowid();
+    touch_cart(&conn, cart_id)?;
+    if purchasing_type == "single" {
+        // The added units may now sit on the bulk line instead.
+        if let Some(bulk_line) = convert_to_bulk(&conn, cart_id, product_id, price)? {
+            let kept: bool = conn.query_row("SELECT EXISTS(SELECT 1 FROM cart_items WHERE rowid = ?1)", params![rowid], |row| row.get(0))
+                .map_err(|e| format!("Failed to load cart item: {}", e))?;
+            if !kept {
+                rowid = bulk_line;
+            }
+        }
+    }
+    promotions::apply_promotions(&conn, cart_id)?;
+    load_cart_item(&conn, rowid)
+}
+
+/// Adds the product behind a scanned barcode. A `Bulk_code` adds the bulk
+/// unit at `Bulk_price`, an in-store code its embedded weight; the scanned
+/// code is kept on the line.
+#[command]
+pub fn add_scanned_item(window: Window, cart_id: i64, barcode: String, quantity: Option<f64>) -> Result<CartItem, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let found = search::resolve_barcode(&conn, &barcode)?
+        .ok_or_else(|| format!("No product with barcode {}", barcode.trim()))?;
+    drop(conn);
+    add_cart_item(
+        window,
         cart_id,
-        product_id,
-        scanned_barcode,
-        quantity,
-        price,
-        purchasing_type,
-        discount,
+        found.product_id,
+        Some(found.scanned_barcode),
+        found.quantity.or(quantity).unwrap_or(1.0),
+        found.price,
+        found.purchasing_type,
+        0.0,
+    )
+}
+
+// Single lines a bulk conversion may merge: same price, no manual discount.
+const MERGEABLE_SINGLES: &str = "cart_id = ?1 AND product_id = ?2 AND purchasing_type = 'single' AND ABS(price - ?3) < 0.005 AND COALESCE(discount, 0) = 0";
+
+/// Moves whole multiples of `Bulk_single_conversion` from the product's single
+/// lines at `price` onto its bulk line at `Bulk_price`, e.g. 25 cans become 1
+/// carton of 24 plus 1 can. Lines at another price or with a manual discount
+/// are left as they are. Products opt out with `Auto_bulk = 0`. Returns the
+/// rowid of the bulk line when units were moved.
+fn convert_to_bulk(conn: &Connection, cart_id: i64, product_id: i64, price: f64) -> Result<Option<i64>, String> {
+    let product: Option<(Option<f64>, Option<f64>, Option<String>, bool)> = conn.query_row(
+        "SELECT Bulk_single_conversion, Bulk_price, Bulk_code, Auto_bulk FROM products WHERE rowid = ?1",
+        params![product_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to load product: {}", e))?;
+    let (conversion, bulk_price, bulk_code) = match product {
+        // The legacy catalog stores the conversion as REAL.
+        Some((Some(conversion), Some(bulk_price), bulk_code, true)) if conversion >= 2.0 && bulk_price > 0.0 => {
+            (conversion.floor(), bulk_price, bulk_code)
+        }
+        _ => return Ok(None),
+    };
+
+    let (scanned_barcode, tax_rate): (Option<String>, Option<f64>) = match conn.query_row(
+        &format!("SELECT scanned_barcode, tax_rate FROM cart_items WHERE {} ORDER BY rowid LIMIT 1", MERGEABLE_SINGLES),
+        params![cart_id, product_id, price],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to load cart items: {}", e))?
+    {
+        Some(line) => line,
+        None => return Ok(None),
     };
-    Ok(item)
+    let quantity: f64 = conn.query_row(
+        &format!("SELECT COALESCE(SUM(quantity), 0) FROM cart_items WHERE {}", MERGEABLE_SINGLES),
+        params![cart_id, product_id, price],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to load cart items: {}", e))?;
+    let bulks = (quantity / conversion).floor();
+    if bulks < 1.0 {
+        return Ok(None);
+    }
+    let singles = scale::round_quantity(quantity - bulks * conversion);
+
+    conn.execute(&format!("DELETE FROM cart_items WHERE {}", MERGEABLE_SINGLES), params![cart_id, product_id, price])
+        .map_err(|e| format!("Failed to convert cart items: {}", e))?;
+    if singles > 0.0 {
+        conn.execute(
+            "INSERT INTO cart_items (cart_id, product_id, scanned_barcode, quantity, price, purchasing_type, discount, tax_rate)
+             VALUES (?1, ?2, ?3, ?4, ?5, 'single', 0, ?6)",
+            params![cart_id, product_id, scanned_barcode, singles, price, tax_rate],
+        )
+        .map_err(|e| format!("Failed to convert cart items: {}", e))?;
+    }
+    let bulk_line: Option<i64> = conn.query_row(
+        "SELECT rowid FROM cart_items
+         WHERE cart_id = ?1 AND product_id = ?2 AND purchasing_type = 'bulk' AND ABS(price - ?3) < 0.005 AND COALESCE(discount, 0) = 0
+         ORDER BY rowid LIMIT 1",
+        params![cart_id, product_id, bulk_price],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to load cart items: {}", e))?;
+    if let Some(rowid) = bulk_line {
+        conn.execute("UPDATE cart_items SET quantity = quantity + ?1 WHERE rowid = ?2", params![bulks, rowid])
+            .map_err(|e| format!("Failed to convert cart items: {}", e))?;
+        return Ok(Some(rowid));
+    }
+    // Missing codes were imported from the spreadsheet as the text "nan".
+    let bulk_code = bulk_code.filter(|code| !code.trim().is_empty() && !code.eq_ignore_ascii_case("nan"));
+    conn.execute(
+        "INSERT INTO cart_items (cart_id, product_id, scanned_barcode, quantity, price, purchasing_type, discount, tax_rate)
+         VALUES (?1, ?2, ?3, ?4, ?5, 'bulk', 0, ?6)",
+        params![cart_id, product_id, bulk_code, bulks, bulk_price, tax_rate],
+    )
+    .map_err(|e| format!("Failed to convert cart items: {}", e))?;
+    Ok(Some(conn.last_insert_rowid()))
+}
+
+fn load_cart_item(conn: &Connection, rowid: i64) -> Result<CartItem, String> {
+    conn.query_row(
+        "SELECT cart_id, product_id, scanned_barcode, quantity, price, purchasing_type, discount, tax_rate FROM cart_items WHERE rowid = ?1",
+        params![rowid],
+        |row| {
+            Ok(CartItem {
+                cart_id: row.get(0)?,
+                product_id: row.get(1)?,
+                scanned_barcode: row.get(2)?,
+                quantity: row.get(3)?,
+                price: row.get(4)?,
+                purchasing_type: row.get(5)?,
+                discount: row.get::<_, Option<f64>>(6)?.unwrap_or(0.0),
+                tax_rate: row.get(7)?,
+            })
+        },
+    )
+    .map_err(|e| format!("Failed to load cart item: {}", e))
+}
+
+#[command]
+pub fn set_product_auto_bulk(window: Window, product_id: i64, enabled: bool) -> Result<(), String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let rows = conn.execute("UPDATE products SET Auto_bulk = ?1 WHERE rowid = ?2", params![enabled, product_id])
+        .map_err(|e| format!("Failed to update product: {}", e))?;
+    if rows == 0 {
+        return Err("Product not found".to_string());
+    }
+    Ok(())
 }
 
 #[command]
@@ -132,18 +309,39 @@ pub fn remove_cart_item(window: Window, cart_id: i64, product_id: i64, purchasin
     let sql = "DELETE FROM cart_items WHERE cart_id = ?1 AND product_id = ?2 AND purchasing_type = ?3";
     conn.execute(sql, params![cart_id, product_id, purchasing_type])
         .map_err(|e| format!("Failed to remove cart item: {}", e))?;
+    touch_cart(&conn, cart_id)?;
+    promotions::apply_promotions(&conn, cart_id)?;
     Ok(())
 }
 
 #[command]
-pub fn update_cart_item_quantity(window: Window, cart_id: i64, product_id: i64, purchasing_type: String, quantity: i32) -> Result<(), String> {
+pub fn update_cart_item_quantity(window: Window, cart_id: i64, product_id: i64, purchasing_type: String, quantity: f64) -> Result<(), String> {
     let db_path = get_db_path(&window);
     let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let quantity = scale::round_quantity(quantity);
+    if quantity != 0.0 {
+        scale::validate_quantity(quantity, line_unit(&conn, product_id, &purchasing_type)?.as_deref())?;
+    }
     let sql = "UPDATE cart_items SET quantity = ?1 WHERE cart_id = ?2 AND product_id = ?3 AND purchasing_type = ?4";
     conn.execute(sql, params![quantity, cart_id, product_id, purchasing_type])
         .map_err(|e| format!("Failed to update quantity: {}", e))?;
-    if quantity == 0 {
+    if quantity == 0.0 {
         remove_cart_item(window, cart_id, product_id, purchasing_type)?;
+    } else {
+        touch_cart(&conn, cart_id)?;
+        if purchasing_type == "single" {
+            let price: Option<f64> = conn.query_row(
+                "SELECT price FROM cart_items WHERE cart_id = ?1 AND product_id = ?2 AND purchasing_type = 'single' ORDER BY rowid LIMIT 1",
+                params![cart_id, product_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to load cart items: {}", e))?;
+            if let Some(price) = price {
+                convert_to_bulk(&conn, cart_id, product_id, price)?;
+            }
+        }
+        promotions::apply_promotions(&conn, cart_id)?;
     }
     Ok(())
 }
@@ -152,8 +350,8 @@ pub fn update_cart_item_quantity(window: Window, cart_id: i64, product_id: i64,
 pub fn park_cart(window: Window, cart_id: i64, cart_name: String) -> Result<(), String> {
     let db_path = get_db_path(&window);
     let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
-    let sql = "UPDATE carts SET status = 'parked', cart_name = ?1 WHERE cart_id = ?2 AND status = 'active'";
-    let rows = conn.execute(sql, params![cart_name, cart_id])
+    let sql = "UPDATE carts SET status = 'parked', cart_name = ?1, status_changed_at = ?2 WHERE cart_id = ?3 AND status = 'active'";
+    let rows = conn.execute(sql, params![cart_name, now_string(), cart_id])
         .map_err(|e| format!("Failed to park cart: {}", e))?;
     if rows == 0 {
         Err("No active cart found with that ID".to_string())
@@ -166,10 +364,11 @@ pub fn park_cart(window: Window, cart_id: i64, cart_name: String) -> Result<(),
 pub fn activate_cart(window: Window, cart_id: i64) -> Result<(), String> {
     let db_path = get_db_path(&window);
     let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
-    conn.execute("UPDATE carts SET status = 'parked' WHERE status = 'active'", params![])
+    let now = now_string();
+    conn.execute("UPDATE carts SET status = 'parked', status_changed_at = ?1 WHERE status = 'active'", params![now])
         .map_err(|e| format!("Failed to park other carts: {}", e))?;
-    let sql = "UPDATE carts SET status = 'active' WHERE cart_id = ?1 AND status = 'parked'";
-    let rows = conn.execute(sql, params![cart_id])
+    let sql = "UPDATE carts SET status = 'active', status_changed_at = ?1 WHERE cart_id = ?2 AND status = 'parked'";
+    let rows = conn.execute(sql, params![now, cart_id])
         .map_err(|e| format!("Failed to activate cart: {}", e))?;
     if rows == 0 {
         Err("No parked cart found with that ID".to_string())
@@ -179,37 +378,54 @@ pub fn activate_cart(window: Window, cart_id: i64) -> Result<(), String> {
 }
 
 #[command]
-pub fn checkout_cart(window: Window, cart_id: i64, store_id: String, storeman_id: String) -> Result<String, String> {
+pub fn checkout_cart(window: Window, cart_id: i64) -> Result<String, String> {
     let db_path = get_db_path(&window);
-    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
-    conn.execute("UPDATE carts SET status = 'pending checkout' WHERE cart_id = ?1", params![cart_id])
+    let mut conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let shift = shift::require_open_shift(&tx)?;
+    // Checking out again returns the invoice already opened for the cart.
+    let status: Option<String> = tx.query_row("SELECT status FROM carts WHERE cart_id = ?1", params![cart_id], |row| row.get(0))
+        .optional()
+        .map_err(|e| format!("Failed to load cart: {}", e))?;
+    match status.as_deref() {
+        None => return Err(format!("Cart {} not found", cart_id)),
+        Some("active") | Some("pending checkout") => {}
+        Some(other) => return Err(format!("Cart {} is {}; only the active cart can be checked out", cart_id, other)),
+    }
+    let item_count: i64 = tx.query_row("SELECT COUNT(*) FROM cart_items WHERE cart_id = ?1", params![cart_id], |row| row.get(0))
+        .map_err(|e| format!("Failed to count cart items: {}", e))?;
+    if item_count == 0 {
+        return Err("Cart is empty".to_string());
+    }
+    // Happy hours may have started or ended since the last change.
+    promotions::apply_promotions(&tx, cart_id)?;
+    tx.execute("UPDATE carts SET status = 'pending checkout' WHERE cart_id = ?1", params![cart_id])
         .map_err(|e| format!("Failed to set pending checkout: {}", e))?;
-    let now = Utc::now();
-    let date_str = now.format("%Y%m%d").to_string();
-    let mut stmt = conn.prepare("SELECT COUNT(*) FROM carts WHERE added_at LIKE ?1 AND status = 'pending checkout'")
-        .map_err(|e| format!("Failed to prepare count: {}", e))?;
-    let like_pattern = format!("{}%", date_str);
-    let count: i64 = stmt.query_row(params![like_pattern], |row| row.get(0))
-        .map_err(|e| format!("Failed to count carts: {}", e))?;
-    let sequence = format!("{:03}", count + 1);
-    let _timestamp = now.format("%H%M").to_string(); // Prefixed with _ to silence warning
-    let invoice_id = format!("{}_{}_{}_{}", store_id, storeman_id, date_str, sequence);
+    let invoice_id = sales::open_invoice(&tx, cart_id, &shift.store_id, &shift.storeman_id, shift.shift_id)?;
+    tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
     Ok(invoice_id)
 }
 
 #[command]
-pub fn confirm_payment(window: Window, cart_id: i64) -> Result<(), String> {
+pub fn confirm_payment(window: Window, cart_id: i64, tenders: Option<Vec<sales::Tender>>) -> Result<String, String> {
     let db_path = get_db_path(&window);
     let mut conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
-    conn.execute("UPDATE carts SET status = 'processed' WHERE cart_id = ?1", params![cart_id])
-        .map_err(|e| format!("Failed to set processed: {}", e))?;
     let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let shift = shift::require_open_shift(&tx)?;
+    reports::ensure_day_open(&tx)?;
+    tx.execute("UPDATE carts SET status = 'processed' WHERE cart_id = ?1", params![cart_id])
+        .map_err(|e| format!("Failed to set processed: {}", e))?;
+    let invoice_id = sales::complete_invoice(&tx, cart_id, tenders, shift.shift_id)?;
+    customers::settle_loyalty(&tx, cart_id, &invoice_id)?;
+    credit::settle_on_account(&tx, &invoice_id)?;
+    tx.execute("DELETE FROM cart_discounts WHERE cart_id = ?1", params![cart_id])
+        .map_err(|e| format!("Failed to delete cart discounts: {}", e))?;
     tx.execute("DELETE FROM cart_items WHERE cart_id = ?1", params![cart_id])
         .map_err(|e| format!("Failed to delete cart items: {}", e))?;
     tx.execute("DELETE FROM carts WHERE cart_id = ?1", params![cart_id])
         .map_err(|e| format!("Failed to delete cart: {}", e))?;
     tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
-    Ok(())
+    Ok(invoice_id)
 }
 
 #[command]
@@ -217,6 +433,9 @@ pub fn cancel_cart(window: Window, cart_id: i64) -> Result<(), String> {
     let db_path = get_db_path(&window);
     let mut conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
     let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+    sales::void_pending_invoice(&tx, cart_id)?;
+    tx.execute("DELETE FROM cart_discounts WHERE cart_id = ?1", params![cart_id])
+        .map_err(|e| format!("Failed to delete cart discounts: {}", e))?;
     tx.execute("DELETE FROM cart_items WHERE cart_id = ?1", params![cart_id])
         .map_err(|e| format!("Failed to delete cart items: {}", e))?;
     tx.execute("DELETE FROM carts WHERE cart_id = ?1", params![cart_id])
@@ -229,7 +448,7 @@ pub fn cancel_cart(window: Window, cart_id: i64) -> Result<(), String> {
 pub fn list_active_cart(window: Window) -> Result<Option<Cart>, String> {
     let db_path = get_db_path(&window);
     let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
-    let mut stmt = conn.prepare("SELECT cart_id, cart_name, status, added_at FROM carts WHERE status = 'active' LIMIT 1")
+    let mut stmt = conn.prepare("SELECT cart_id, cart_name, status, added_at, customer_phone FROM carts WHERE status = 'active' LIMIT 1")
         .map_err(|e| format!("Failed to prepare: {}", e))?;
     let cart = stmt.query_map(params![], |row| {
         Ok(Cart {
@@ -237,6 +456,7 @@ pub fn list_active_cart(window: Window) -> Result<Option<Cart>, String> {
             cart_name: row.get(1)?,
             status: row.get(2)?,
             added_at: row.get(3)?,
+            customer_phone: row.get(4)?,
         })
     })
     .map_err(|e| format!("Failed to query: {}", e))?
@@ -249,7 +469,7 @@ pub fn list_active_cart(window: Window) -> Result<Option<Cart>, String> {
 pub fn list_parked_carts(window: Window) -> Result<Vec<Cart>, String> {
     let db_path = get_db_path(&window);
     let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
-    let mut stmt = conn.prepare("SELECT cart_id, cart_name, status, added_at FROM carts WHERE status = 'parked'")
+    let mut stmt = conn.prepare("SELECT cart_id, cart_name, status, added_at, customer_phone FROM carts WHERE status = 'parked'")
         .map_err(|e| format!("Failed to prepare: {}", e))?;
     let carts = stmt.query_map(params![], |row| {
         Ok(Cart {
@@ -257,6 +477,7 @@ pub fn list_parked_carts(window: Window) -> Result<Vec<Cart>, String> {
             cart_name: row.get(1)?,
             status: row.get(2)?,
             added_at: row.get(3)?,
+            customer_phone: row.get(4)?,
         })
     })
     .map_err(|e| format!("Failed to query: {}", e))?
@@ -269,7 +490,7 @@ pub fn list_parked_carts(window: Window) -> Result<Vec<Cart>, String> {
 pub fn list_cart_items(window: Window, cart_id: i64) -> Result<Vec<CartItem>, String> {
     let db_path = get_db_path(&window);
     let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
-    let mut stmt = conn.prepare("SELECT cart_id, product_id, scanned_barcode, quantity, price, purchasing_type, discount FROM cart_items WHERE cart_id = ?1")
+    let mut stmt = conn.prepare("SELECT cart_id, product_id, scanned_barcode, quantity, price, purchasing_type, discount, tax_rate FROM cart_items WHERE cart_id = ?1")
         .map_err(|e| format!("Failed to prepare: {}", e))?;
     let items = stmt.query_map(params![cart_id], |row| {
         Ok(CartItem {
@@ -280,6 +501,7 @@ pub fn list_cart_items(window: Window, cart_id: i64) -> Result<Vec<CartItem>, St
             price: row.get(4)?,
             purchasing_type: row.get(5)?,
             discount: row.get(6)?,
+            tax_rate: row.get(7)?,
         })
     })
     .map_err(|e| format!("Failed to query: {}", e))?
@@ -288,16 +510,35 @@ pub fn list_cart_items(window: Window, cart_id: i64) -> Result<Vec<CartItem>, St
     Ok(items)
 }
 
+/// Expires stale carts right away. Without `ttl_minutes` the stored settings
+/// are used; an explicit value overrides the active-cart TTL and must lie in
+/// the allowed range. Returns the deleted cart ids.
 #[command]
-pub fn cleanup_expired_carts(window: Window, ttl_minutes: i64) -> Result<(), String> {
+pub fn cleanup_expired_carts(window: Window, ttl_minutes: Option<i64>) -> Result<Vec<i64>, String> {
     let db_path = get_db_path(&window);
     let mut conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
-    let now = Utc::now().naive_utc();
-    let cutoff = now - Duration::minutes(ttl_minutes);
-    let cutoff_str = cutoff.format("%Y-%m-%d %H:%M:%S").to_string();
-    let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
-    tx.execute("DELETE FROM carts WHERE status = 'active' AND added_at < ?1", params![cutoff_str])
-        .map_err(|e| format!("Failed to cleanup expired carts: {}", e))?;
-    tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
-    Ok(())
-}
\ No newline at end of file
+    let mut ttl = cart_expiry::load_ttl(&conn)?;
+    if let Some(minutes) = ttl_minutes {
+        ttl.active_minutes = minutes;
+        cart_expiry::validate_ttl(&ttl)?;
+    }
+    cart_expiry::expire_carts(&mut conn, &ttl)
+}
+
+commands![
+    create_cart,
+    add_cart_item,
+    remove_cart_item,
+    update_cart_item_quantity,
+    park_cart,
+    activate_cart,
+    checkout_cart,
+    confirm_payment,
+    cancel_cart,
+    list_active_cart,
+    list_parked_carts,
+    list_cart_items,
+    cleanup_expired_carts,
+    set_product_auto_bulk,
+    add_scanned_item,
+];
@@ -0,0 +1,132 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection};
+use tauri::{command, AppHandle, Manager, Window};
+use chrono::{Duration, Utc};
+
+use crate::cart::{db_path_from_config, get_db_path};
+use crate::sales;
+use crate::settings::{store_settings, Settings};
+
+pub const DEFAULT_ACTIVE_TTL_MINUTES: i64 = 10;
+pub const MAX_ACTIVE_TTL_MINUTES: i64 = 30;
+pub const DEFAULT_PARKED_TTL_MINUTES: i64 = 60;
+pub const MAX_PARKED_TTL_MINUTES: i64 = 480;
+const SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CartTtl {
+    pub active_minutes: i64,
+    pub parked_minutes: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExpiredCarts {
+    pub cart_ids: Vec<i64>,
+}
+
+pub fn validate_ttl(ttl: &CartTtl) -> Result<(), String> {
+    if !(1..=MAX_ACTIVE_TTL_MINUTES).contains(&ttl.active_minutes) {
+        return Err(format!("Active cart TTL must be between 1 and {} minutes", MAX_ACTIVE_TTL_MINUTES));
+    }
+    if !(1..=MAX_PARKED_TTL_MINUTES).contains(&ttl.parked_minutes) {
+        return Err(format!("Parked cart TTL must be between 1 and {} minutes", MAX_PARKED_TTL_MINUTES));
+    }
+    Ok(())
+}
+
+/// The TTLs from the stored settings, falling back to the defaults when out of range.
+pub fn load_ttl(conn: &Connection) -> Result<CartTtl, String> {
+    let settings = Settings::load(conn)?;
+    let ttl = CartTtl {
+        active_minutes: settings.cart_ttl_active_minutes,
+        parked_minutes: settings.cart_ttl_parked_minutes,
+    };
+    if validate_ttl(&ttl).is_err() {
+        return Ok(CartTtl {
+            active_minutes: DEFAULT_ACTIVE_TTL_MINUTES,
+            parked_minutes: DEFAULT_PARKED_TTL_MINUTES,
+        });
+    }
+    Ok(ttl)
+}
+
+/// Deletes active and parked carts idle for longer than their TTL together
+/// with their items, voiding any invoice left unpaid. Idle time runs from the
+/// last item change or status change, so a cart being rung up or re-activated
+/// from the parked area gets a fresh TTL.
+pub fn expire_carts(conn: &mut Connection, ttl: &CartTtl) -> Result<Vec<i64>, String> {
+    let now = Utc::now().naive_utc();
+    let active_cutoff = (now - Duration::minutes(ttl.active_minutes)).format("%Y-%m-%d %H:%M:%S").to_string();
+    let parked_cutoff = (now - Duration::minutes(ttl.parked_minutes)).format("%Y-%m-%d %H:%M:%S").to_string();
+    let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let cart_ids: Vec<i64> = {
+        let mut stmt = tx.prepare(
+            "SELECT cart_id FROM carts
+             WHERE (status = 'active' AND MAX(COALESCE(status_changed_at, added_at), COALESCE(last_activity_at, added_at)) < ?1)
+                OR (status = 'parked' AND MAX(COALESCE(status_changed_at, added_at), COALESCE(last_activity_at, added_at)) < ?2)",
+        )
+        .map_err(|e| format!("Failed to prepare: {}", e))?;
+        let ids = stmt.query_map(params![active_cutoff, parked_cutoff], |row| row.get(0))
+            .map_err(|e| format!("Failed to query: {}", e))?
+            .filter_map(|r| r.ok())
+            .collect();
+        ids
+    };
+    for cart_id in &cart_ids {
+        sales::void_pending_invoice(&tx, *cart_id)?;
+        tx.execute("DELETE FROM cart_discounts WHERE cart_id = ?1", params![cart_id])
+            .map_err(|e| format!("Failed to delete cart discounts: {}", e))?;
+        tx.execute("DELETE FROM cart_items WHERE cart_id = ?1", params![cart_id])
+            .map_err(|e| format!("Failed to delete cart items: {}", e))?;
+        tx.execute("DELETE FROM carts WHERE cart_id = ?1", params![cart_id])
+            .map_err(|e| format!("Failed to delete cart: {}", e))?;
+    }
+    tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+    Ok(cart_ids)
+}
+
+/// Sweeps expired carts once a minute and tells the windows which carts
+/// disappeared so they can drop them from the Active/Park areas.
+pub fn spawn_expiry_worker(app: AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(SWEEP_INTERVAL);
+        let db_path = db_path_from_config(&app.config());
+        let result = Connection::open(&db_path)
+            .map_err(|e| format!("Failed to open DB: {}", e))
+            .and_then(|mut conn| {
+                let ttl = load_ttl(&conn)?;
+                expire_carts(&mut conn, &ttl)
+            });
+        match result {
+            Ok(cart_ids) if !cart_ids.is_empty() => {
+                let _ = app.emit_all("carts-expired", ExpiredCarts { cart_ids });
+            }
+            Ok(_) => {}
+            Err(e) => println!("[cart_expiry] {}", e),
+        }
+    });
+}
+
+#[command]
+pub fn get_cart_ttl(window: Window) -> Result<CartTtl, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    load_ttl(&conn)
+}
+
+#[command]
+pub fn set_cart_ttl(window: Window, ttl: CartTtl) -> Result<CartTtl, String> {
+    validate_ttl(&ttl)?;
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut settings = Settings::load(&conn)?;
+    settings.cart_ttl_active_minutes = ttl.active_minutes;
+    settings.cart_ttl_parked_minutes = ttl.parked_minutes;
+    store_settings(&window, &settings)?;
+    Ok(ttl)
+}
+
+commands![
+    get_cart_ttl,
+    set_cart_ttl,
+];
@@ -0,0 +1,327 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::{command, Window};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{Duration, NaiveDateTime, Utc};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde_json::value::RawValue;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+
+use crate::cart::{get_db_path, now_string, Cart};
+use crate::customers::{ensure_accounts_here, load_customer};
+use crate::db::add_column_if_missing;
+use crate::promotions;
+use crate::search::resolve_barcode;
+use crate::settings::Settings;
+use crate::tax;
+
+const TRANSFER_VERSION: u32 = 1;
+const QR_PREFIX: &str = "ANPOS1:";
+// Binary QR version 40 at error correction level L holds 2953 bytes.
+const MAX_QR_LEN: usize = 2900;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransferItem {
+    pub barcode: Option<String>, // re-resolved on the receiving terminal
+    pub name: String,
+    pub purchasing_type: String,
+    pub quantity: f64,
+    pub price: f64,
+    pub discount: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CartTransfer {
+    pub version: u32,
+    pub transfer_id: String,
+    pub store_id: String,
+    pub cart_name: String,
+    pub customer_phone: Option<String>,
+    pub exported_at: String,
+    pub items: Vec<TransferItem>,
+}
+
+/// The `cart` JSON is kept exactly as signed, so verifying it never depends
+/// on a re-encoding of the parsed values.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SignedTransfer {
+    pub cart: Box<RawValue>,
+    pub signature: String, // hex HMAC-SHA256 of the `cart` JSON
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportedCart {
+    pub json: String,
+    pub qr: Option<String>, // None when the cart is too large for one QR code
+}
+
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS cart_transfers (
+            transfer_id VARCHAR(64) PRIMARY KEY,
+            cart_id INTEGER NOT NULL,
+            imported_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        params![],
+    )?;
+    // Set on a cart this till exported; the cart is kept until it is back.
+    add_column_if_missing(conn, "carts", "transfer_id", "VARCHAR(64)")?;
+    Ok(())
+}
+
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    const BLOCK: usize = 64;
+    let mut key = if key.len() > BLOCK { Sha256::digest(key).to_vec() } else { key.to_vec() };
+    key.resize(BLOCK, 0);
+    let inner: Vec<u8> = key.iter().map(|b| b ^ 0x36).collect();
+    let outer: Vec<u8> = key.iter().map(|b| b ^ 0x5c).collect();
+    let inner_hash = Sha256::new().chain_update(&inner).chain_update(message).finalize();
+    Sha256::new().chain_update(&outer).chain_update(inner_hash).finalize().to_vec()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sign(body: &[u8], secret: &str) -> String {
+    to_hex(&hmac_sha256(secret.as_bytes(), body))
+}
+
+/// Compares two signatures in constant time.
+pub fn signatures_match(expected: &str, received: &str) -> bool {
+    expected.len() == received.len()
+        && expected.bytes().zip(received.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Terminals of one store share `terminal_secret`; a payload signed with any
+/// other key, or edited on the way, is refused. The signature covers the
+/// received `cart` bytes, which are only parsed once it matches.
+fn verify(signed: &SignedTransfer, secret: &str) -> Result<CartTransfer, String> {
+    if !signatures_match(&sign(signed.cart.get().as_bytes(), secret), &signed.signature) {
+        return Err("Cart transfer signature is invalid".to_string());
+    }
+    serde_json::from_str(signed.cart.get()).map_err(|e| format!("Not a cart transfer: {}", e))
+}
+
+fn require_secret(settings: &Settings) -> Result<&str, String> {
+    let secret = settings.terminal_secret.trim();
+    if secret.is_empty() {
+        return Err("Set the shared terminal secret in settings before moving carts".to_string());
+    }
+    Ok(secret)
+}
+
+fn encode_qr(json: &str) -> Result<Option<String>, String> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(json.as_bytes()).map_err(|e| format!("Failed to compress cart: {}", e))?;
+    let compressed = encoder.finish().map_err(|e| format!("Failed to compress cart: {}", e))?;
+    let payload = format!("{}{}", QR_PREFIX, URL_SAFE_NO_PAD.encode(compressed));
+    Ok(if payload.len() <= MAX_QR_LEN { Some(payload) } else { None })
+}
+
+fn decode_payload(payload: &str) -> Result<SignedTransfer, String> {
+    let payload = payload.trim();
+    let json = match payload.strip_prefix(QR_PREFIX) {
+        Some(encoded) => {
+            let compressed = URL_SAFE_NO_PAD.decode(encoded).map_err(|_| "Cart QR code is damaged".to_string())?;
+            let mut json = String::new();
+            DeflateDecoder::new(&compressed[..]).read_to_string(&mut json).map_err(|_| "Cart QR code is damaged".to_string())?;
+            json
+        }
+        None => payload.to_string(),
+    };
+    serde_json::from_str(&json).map_err(|e| format!("Not a cart transfer: {}", e))
+}
+
+/// Serializes a parked cart into a signed JSON document and, when it fits,
+/// a QR payload. The cart is kept here as 'transferred' until the other till
+/// has it; importing the payload here again brings it back at any time.
+#[command]
+pub fn export_parked_cart(window: Window, cart_id: i64) -> Result<ExportedCart, String> {
+    let db_path = get_db_path(&window);
+    let mut conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let settings = Settings::load(&conn)?;
+    let secret = require_secret(&settings)?;
+    let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let cart: Option<(String, Option<String>)> = tx.query_row(
+        "SELECT cart_name, customer_phone FROM carts WHERE cart_id = ?1 AND status = 'parked'",
+        params![cart_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to load cart: {}", e))?;
+    let (cart_name, customer_phone) = cart.ok_or("Only parked carts can be moved to another till")?;
+
+    // Lines added by search carry no scanned code, so send the product's own.
+    let items: Vec<TransferItem> = {
+        let mut stmt = tx.prepare(
+            "SELECT COALESCE(ci.scanned_barcode,
+                        CASE WHEN ci.purchasing_type = 'bulk' THEN NULLIF(p.Bulk_code, 'nan') END,
+                        NULLIF(TRIM(SUBSTR(p.Barcode, 1, INSTR(p.Barcode || ',', ',') - 1)), 'nan')),
+                    p.Item_name, ci.purchasing_type, ci.quantity, ci.price, COALESCE(ci.discount, 0)
+             FROM cart_items ci JOIN products p ON p.rowid = ci.product_id
+             WHERE ci.cart_id = ?1 ORDER BY ci.rowid",
+        )
+        .map_err(|e| format!("Failed to prepare: {}", e))?;
+        let rows = stmt.query_map(params![cart_id], |row| {
+            Ok(TransferItem {
+                barcode: row.get::<_, Option<String>>(0)?.filter(|b| !b.is_empty()),
+                name: row.get(1)?,
+                purchasing_type: row.get(2)?,
+                quantity: row.get(3)?,
+                price: row.get(4)?,
+                discount: row.get(5)?,
+            })
+        })
+        .map_err(|e| format!("Failed to query: {}", e))?
+        .filter_map(|r| r.ok())
+        .collect();
+        rows
+    };
+    if items.is_empty() {
+        return Err("Cart is empty".to_string());
+    }
+    if let Some(item) = items.iter().find(|i| i.barcode.is_none()) {
+        return Err(format!("{} has no barcode and cannot be moved", item.name));
+    }
+
+    let transfer = CartTransfer {
+        version: TRANSFER_VERSION,
+        transfer_id: uuid::Uuid::new_v4().to_string(),
+        store_id: settings.store_id.clone(),
+        cart_name,
+        customer_phone,
+        exported_at: now_string(),
+        items,
+    };
+    let body = serde_json::to_string(&transfer).map_err(|e| format!("Failed to encode cart: {}", e))?;
+    let signature = sign(body.as_bytes(), secret);
+    let cart = RawValue::from_string(body).map_err(|e| format!("Failed to encode cart: {}", e))?;
+    let json = serde_json::to_string(&SignedTransfer { cart, signature })
+        .map_err(|e| format!("Failed to encode cart: {}", e))?;
+    let qr = encode_qr(&json)?;
+
+    tx.execute(
+        "UPDATE carts SET status = 'transferred', status_changed_at = ?1, transfer_id = ?2 WHERE cart_id = ?3",
+        params![now_string(), transfer.transfer_id, cart_id],
+    )
+    .map_err(|e| format!("Failed to mark cart transferred: {}", e))?;
+    tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+    Ok(ExportedCart { json, qr })
+}
+
+/// Parks again a cart this till exported as `transfer_id`, if it still has it.
+fn reclaim_cart(conn: &Connection, transfer_id: &str) -> Result<Option<Cart>, String> {
+    let cart = conn.query_row(
+        "SELECT cart_id, cart_name, added_at, customer_phone FROM carts WHERE transfer_id = ?1 AND status = 'transferred'",
+        params![transfer_id],
+        |row| Ok(Cart { cart_id: row.get(0)?, cart_name: row.get(1)?, status: "parked".to_string(), added_at: row.get(2)?, customer_phone: row.get(3)? }),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to look up cart: {}", e))?;
+    if let Some(cart) = &cart {
+        conn.execute(
+            "UPDATE carts SET status = 'parked', status_changed_at = ?1, transfer_id = NULL WHERE cart_id = ?2",
+            params![now_string(), cart.cart_id],
+        )
+        .map_err(|e| format!("Failed to restore cart: {}", e))?;
+    }
+    Ok(cart)
+}
+
+/// Verifies a cart exported by another till and parks it here. Each item is
+/// looked up again by barcode, since product ids differ between terminals.
+#[command]
+pub fn import_parked_cart(window: Window, payload: String) -> Result<Cart, String> {
+    let signed = decode_payload(&payload)?;
+    let db_path = get_db_path(&window);
+    let mut conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let settings = Settings::load(&conn)?;
+    let transfer = verify(&signed, require_secret(&settings)?)?;
+    if transfer.version != TRANSFER_VERSION {
+        return Err(format!("Unsupported cart transfer version {}", transfer.version));
+    }
+    if transfer.store_id != settings.store_id {
+        return Err(format!("Cart belongs to store {}", transfer.store_id));
+    }
+    // A cart that never made it to the other till comes back whatever its age.
+    if let Some(cart) = reclaim_cart(&conn, &transfer.transfer_id)? {
+        return Ok(cart);
+    }
+    let exported_at = NaiveDateTime::parse_from_str(&transfer.exported_at, "%Y-%m-%d %H:%M:%S")
+        .map_err(|_| "Cart transfer has an invalid date".to_string())?;
+    if Utc::now().naive_utc() - exported_at > Duration::minutes(settings.cart_ttl_parked_minutes) {
+        return Err("Cart transfer has expired".to_string());
+    }
+
+    let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let imported: bool = tx.query_row(
+        "SELECT EXISTS(SELECT 1 FROM cart_transfers WHERE transfer_id = ?1)",
+        params![transfer.transfer_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to look up transfer: {}", e))?;
+    if imported {
+        return Err("This cart has already been imported".to_string());
+    }
+
+    let mut lines = Vec::new();
+    let mut missing = Vec::new();
+    for item in &transfer.items {
+        let found = match item.barcode.as_deref() {
+            Some(barcode) => resolve_barcode(&tx, barcode).ok().flatten(),
+            None => None,
+        };
+        match found {
+            Some(found) => lines.push((found.product_id, found.scanned_barcode, item)),
+            None => missing.push(item.name.clone()),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(format!("Products not found on this till: {}", missing.join(", ")));
+    }
+
+    let customer_phone = match &transfer.customer_phone {
+        Some(phone) if ensure_accounts_here(&tx).is_ok() => load_customer(&tx, phone)?.map(|c| c.phone),
+        _ => None,
+    };
+    let now = now_string();
+    tx.execute(
+        "INSERT INTO carts (cart_name, status, added_at, status_changed_at, customer_phone) VALUES (?1, 'parked', ?2, ?2, ?3)",
+        params![transfer.cart_name, now, customer_phone],
+    )
+    .map_err(|e| format!("Failed to create cart: {}", e))?;
+    let cart_id = tx.last_insert_rowid();
+    for (product_id, scanned_barcode, item) in lines {
+        let tax_rate = tax::resolve_rate(&tx, product_id, settings.tax_rate)?;
+        tx.execute(
+            "INSERT INTO cart_items (cart_id, product_id, scanned_barcode, quantity, price, purchasing_type, discount, tax_rate) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![cart_id, product_id, scanned_barcode, item.quantity, item.price, item.purchasing_type, item.discount, tax_rate],
+        )
+        .map_err(|e| format!("Failed to add cart item: {}", e))?;
+    }
+    promotions::apply_promotions(&tx, cart_id)?;
+    tx.execute(
+        "INSERT INTO cart_transfers (transfer_id, cart_id, imported_at) VALUES (?1, ?2, ?3)",
+        params![transfer.transfer_id, cart_id, now],
+    )
+    .map_err(|e| format!("Failed to record transfer: {}", e))?;
+    tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+
+    Ok(Cart {
+        cart_id,
+        cart_name: transfer.cart_name,
+        status: "parked".to_string(),
+        added_at: now,
+        customer_phone,
+    })
+}
+
+commands![
+    export_parked_cart,
+    import_parked_cart,
+];
@@ -0,0 +1,202 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::{command, Window};
+
+use crate::barcode::{gs1_check_digit, normalize};
+use crate::cart::get_db_path;
+use crate::settings::{get_setting, set_setting, Settings};
+
+// GS1 040-049 is reserved for codes used only inside one company; 20-29
+// is taken by the scale's weight and price labels.
+pub const DEFAULT_INTERNAL_PREFIX: &str = "040";
+
+// A product without a usable unit barcode; legacy rows store "nan".
+const MISSING_BARCODE: &str = "(Barcode IS NULL OR TRIM(Barcode) = '' OR Barcode = 'nan')";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewProduct {
+    pub item_name: String,
+    pub barcodes: Vec<String>, // a product may carry several unit barcodes
+    pub category: Option<String>,
+    pub unit: Option<String>,
+    pub retail_price: f64,
+    pub cost: Option<f64>,
+    pub bulk_unit: Option<String>,
+    pub bulk_code: Option<String>,
+    pub bulk_single_conversion: Option<f64>,
+    pub bulk_price: Option<f64>,
+}
+
+/// Rowid and name of the product using `code` as a unit or carton barcode.
+pub fn product_with_barcode(conn: &Connection, code: &str) -> Result<Option<(i64, String)>, String> {
+    conn.query_row(
+        "SELECT rowid, Item_name FROM products
+         WHERE ',' || REPLACE(Barcode, ' ', '') || ',' LIKE '%,' || ?1 || ',%' OR Bulk_code = ?1
+         LIMIT 1",
+        params![code],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to look up barcode: {}", e))
+}
+
+/// Name of the product already using `code` as a unit or carton barcode.
+pub fn barcode_owner(conn: &Connection, code: &str) -> Result<Option<String>, String> {
+    Ok(product_with_barcode(conn, code)?.map(|(_, name)| name))
+}
+
+/// Validates and normalizes every barcode before the product is stored, so a
+/// mistyped check digit is caught at the counter instead of at the next scan.
+#[command]
+pub fn create_product(window: Window, product: NewProduct) -> Result<i64, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    insert_product(&conn, &product)
+}
+
+pub fn insert_product(conn: &Connection, product: &NewProduct) -> Result<i64, String> {
+    if product.item_name.trim().is_empty() {
+        return Err("Product name is required".to_string());
+    }
+    if product.retail_price < 0.0 || product.cost.map_or(false, |c| c < 0.0) || product.bulk_price.map_or(false, |p| p < 0.0) {
+        return Err("Prices cannot be negative".to_string());
+    }
+    let has_bulk = product.bulk_code.is_some() || product.bulk_price.is_some();
+    if has_bulk && !product.bulk_single_conversion.map_or(false, |c| c >= 2.0 && c.fract() == 0.0) {
+        return Err("Bulk items need a whole conversion of at least 2 single units".to_string());
+    }
+
+    let mut codes = Vec::new();
+    for raw in product.barcodes.iter().chain(product.bulk_code.iter()) {
+        let code = normalize(raw)?.code;
+        if codes.contains(&code) {
+            return Err(format!("Barcode {} is entered twice", code));
+        }
+        if let Some(owner) = barcode_owner(conn, &code)? {
+            return Err(format!("Barcode {} already belongs to {}", code, owner));
+        }
+        codes.push(code);
+    }
+    let bulk_code = product.bulk_code.as_ref().and_then(|_| codes.pop());
+    let barcode = if codes.is_empty() { None } else { Some(codes.join(",")) };
+
+    conn.execute(
+        "INSERT INTO products (Barcode, Item_name, Category, Unit, Bulk_unit, Bulk_code, Bulk_single_conversion, Retail_price, Bulk_price, Cost)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            barcode, product.item_name.trim(), product.category, product.unit, product.bulk_unit, bulk_code,
+            product.bulk_single_conversion, product.retail_price, product.bulk_price, product.cost
+        ],
+    )
+    .map_err(|e| format!("Failed to create product: {}", e))?;
+    Ok(conn.last_insert_rowid())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AssignedBarcode {
+    pub product_id: i64,
+    pub name: String,
+    pub barcode: String,
+}
+
+pub fn validate_internal_prefix(prefix: &str) -> Result<(), String> {
+    if !(2..=7).contains(&prefix.len()) || !prefix.chars().all(|c| c.is_ascii_digit()) {
+        return Err("Internal barcode prefix must be 2 to 7 digits".to_string());
+    }
+    if prefix.starts_with('2') {
+        return Err("Internal barcode prefix cannot start with 2, which is used by scale labels".to_string());
+    }
+    Ok(())
+}
+
+/// Gives every product without a unit barcode (or only those in
+/// `product_ids`) the next free EAN-13 under the internal prefix.
+#[command]
+pub fn assign_internal_barcodes(window: Window, product_ids: Option<Vec<i64>>) -> Result<Vec<AssignedBarcode>, String> {
+    let db_path = get_db_path(&window);
+    let mut conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    assign_barcodes(&mut conn, product_ids)
+}
+
+fn assign_barcodes(conn: &mut Connection, product_ids: Option<Vec<i64>>) -> Result<Vec<AssignedBarcode>, String> {
+    let prefix = Settings::load(conn)?.internal_barcode_prefix;
+    validate_internal_prefix(&prefix)?;
+    let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+    let products: Vec<(i64, String)> = {
+        let mut stmt = tx.prepare(&format!("SELECT rowid, Item_name FROM products WHERE {} ORDER BY rowid", MISSING_BARCODE))
+            .map_err(|e| format!("Failed to prepare: {}", e))?;
+        let rows = stmt.query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| format!("Failed to query: {}", e))?
+            .filter_map(|r| r.ok())
+            .filter(|(id, _): &(i64, String)| product_ids.as_ref().map_or(true, |ids| ids.contains(id)))
+            .collect();
+        rows
+    };
+
+    let seq_key = format!("internal_barcode_seq_{}", prefix);
+    let mut seq: u64 = get_setting(&tx, &seq_key)?.and_then(|v| v.parse().ok()).unwrap_or(1);
+    let width = 12 - prefix.len();
+    let limit = 10u64.pow(width as u32);
+    let mut assigned = Vec::new();
+    for (product_id, name) in products {
+        let barcode = loop {
+            if seq >= limit {
+                return Err(format!("Internal barcodes under prefix {} are used up", prefix));
+            }
+            let body = format!("{}{:0width$}", prefix, seq, width = width);
+            seq += 1;
+            let digit = gs1_check_digit(&body);
+            let code = format!("{}{}", body, digit);
+            if barcode_owner(&tx, &code)?.is_none() {
+                break code;
+            }
+        };
+        tx.execute("UPDATE products SET Barcode = ?1 WHERE rowid = ?2", params![barcode, product_id])
+            .map_err(|e| format!("Failed to update product: {}", e))?;
+        assigned.push(AssignedBarcode { product_id, name, barcode });
+    }
+    set_setting(&tx, &seq_key, &seq.to_string())?;
+    tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+    Ok(assigned)
+}
+
+commands![
+    create_product,
+    assign_internal_barcodes,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_barcodes_carry_valid_check_digits() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE products (Barcode VARCHAR, Item_name VARCHAR, Category VARCHAR, Unit VARCHAR, Bulk_unit VARCHAR,
+             Bulk_code VARCHAR, Bulk_single_conversion FLOAT, Retail_price FLOAT, Bulk_price FLOAT, Cost FLOAT)",
+            params![],
+        )
+        .unwrap();
+        for (barcode, name) in [(None, "Rau muống"), (Some("8934588012112"), "Mì"), (Some("nan"), "Bánh mì"), (Some(" "), "Đá")] {
+            conn.execute("INSERT INTO products (Barcode, Item_name) VALUES (?1, ?2)", params![barcode, name]).unwrap();
+        }
+        crate::db::initialize_tables(&conn).unwrap();
+
+        let assigned = assign_barcodes(&mut conn, None).unwrap();
+        let names: Vec<&str> = assigned.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["Rau muống", "Bánh mì", "Đá"]);
+        for a in &assigned {
+            assert_eq!(a.barcode.len(), 13);
+            assert!(a.barcode.starts_with(DEFAULT_INTERNAL_PREFIX));
+            assert_eq!(a.barcode[12..].parse::<u32>().unwrap(), gs1_check_digit(&a.barcode[..12]));
+            assert!(normalize(&a.barcode).is_ok());
+        }
+        // Later runs continue the sequence instead of reusing codes.
+        conn.execute("INSERT INTO products (Item_name) VALUES ('Trứng')", params![]).unwrap();
+        let next = assign_barcodes(&mut conn, None).unwrap();
+        assert_eq!(next.len(), 1);
+        assert!(assigned.iter().all(|a| a.barcode != next[0].barcode));
+    }
+}
@@ -0,0 +1,367 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, Result, Transaction};
+use tauri::{command, Window};
+use chrono::{NaiveDateTime, Utc};
+
+use crate::cart::{get_db_path, now_string};
+use crate::customers::{ensure_accounts_here, load_customer, normalize_phone};
+use crate::db::add_column_if_missing;
+use crate::sales::round_money;
+use crate::shift;
+
+/// Tender method for the part of a sale put on the customer's tab (bán chịu).
+pub const ACCOUNT_TENDER: &str = "account";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CreditEntry {
+    pub entry_id: i64,
+    pub phone: String,
+    pub invoice_id: Option<String>,
+    pub kind: String,   // "sale", "payment" or "refund"
+    pub amount: f64,    // positive for sales, negative for payments and refunds
+    pub method: Option<String>,
+    pub shift_id: Option<i64>,
+    pub note: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CreditBalance {
+    pub phone: String,
+    pub name: String,
+    pub credit_limit: f64,
+    pub balance: f64,
+    pub available: f64,
+}
+
+/// Outstanding debt of one customer by age, oldest sales paid off first.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AgingRow {
+    pub phone: String,
+    pub name: String,
+    pub balance: f64,
+    pub current: f64, // 0-30 days
+    pub days_31_60: f64,
+    pub days_61_90: f64,
+    pub over_90: f64,
+    pub oldest_at: Option<String>,
+}
+
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    // 0 means the customer may not buy on credit.
+    add_column_if_missing(conn, "customers", "credit_limit", "FLOAT NOT NULL DEFAULT 0")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS credit_ledger (
+            entry_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            phone VARCHAR(20) NOT NULL,
+            invoice_id VARCHAR(100),
+            kind VARCHAR(20) NOT NULL,
+            amount FLOAT NOT NULL,
+            method VARCHAR(20),
+            shift_id INTEGER,
+            note TEXT,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (phone) REFERENCES customers(phone)
+        )",
+        params![],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_credit_ledger_phone ON credit_ledger(phone)", params![])?;
+    // Refunds to the tab used to be recorded as negative sales.
+    conn.execute("UPDATE credit_ledger SET kind = 'refund' WHERE kind = 'sale' AND amount < 0", params![])?;
+    Ok(())
+}
+
+pub fn balance_of(conn: &Connection, phone: &str) -> Result<f64, String> {
+    conn.query_row("SELECT COALESCE(SUM(amount), 0) FROM credit_ledger WHERE phone = ?1", params![phone], |row| row.get(0))
+        .map(round_money)
+        .map_err(|e| format!("Failed to total credit: {}", e))
+}
+
+fn load_balance(conn: &Connection, phone: &str) -> Result<CreditBalance, String> {
+    let customer = load_customer(conn, phone)?.ok_or_else(|| format!("Customer {} not found", phone))?;
+    let credit_limit: f64 = conn.query_row("SELECT credit_limit FROM customers WHERE phone = ?1", params![phone], |row| row.get(0))
+        .map_err(|e| format!("Failed to load credit limit: {}", e))?;
+    let balance = balance_of(conn, phone)?;
+    Ok(CreditBalance {
+        phone: customer.phone,
+        name: customer.name,
+        credit_limit,
+        balance,
+        available: round_money((credit_limit - balance).max(0.0)),
+    })
+}
+
+/// Puts the `account` tenders of a completed invoice on the customer's tab.
+/// Fails, and so blocks the payment, when the tab would exceed the credit
+/// limit. Runs inside the payment transaction, after `settle_loyalty`.
+pub fn settle_on_account(tx: &Transaction, invoice_id: &str) -> Result<(), String> {
+    let (phone, change_due, on_account): (Option<String>, f64, f64) = tx.query_row(
+        "SELECT customer_phone, change_due, COALESCE((SELECT SUM(amount) FROM invoice_tenders WHERE invoice_id = ?1 AND method = ?2), 0)
+         FROM invoices WHERE invoice_id = ?1",
+        params![invoice_id, ACCOUNT_TENDER],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )
+    .map_err(|e| format!("Failed to load invoice: {}", e))?;
+    if on_account <= 0.0 {
+        return Ok(());
+    }
+    let phone = phone.ok_or("Attach a customer to sell on account")?;
+    if change_due > 0.0 {
+        return Err("Change cannot be given on a sale put on account".to_string());
+    }
+    let account = load_balance(tx, &phone)?;
+    let new_balance = round_money(account.balance + on_account);
+    if new_balance > account.credit_limit {
+        return Err(format!(
+            "Credit limit exceeded: {} would owe {} (limit {})",
+            account.name, new_balance, account.credit_limit
+        ));
+    }
+    tx.execute(
+        "INSERT INTO credit_ledger (phone, invoice_id, kind, amount, created_at) VALUES (?1, ?2, 'sale', ?3, ?4)",
+        params![phone, invoice_id, round_money(on_account), now_string()],
+    )
+    .map_err(|e| format!("Failed to record credit sale: {}", e))?;
+    Ok(())
+}
+
+#[command]
+pub fn set_credit_limit(window: Window, phone: String, credit_limit: f64) -> Result<CreditBalance, String> {
+    if !credit_limit.is_finite() || credit_limit < 0.0 {
+        return Err("Credit limit cannot be negative".to_string());
+    }
+    let phone = normalize_phone(&phone)?;
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    ensure_accounts_here(&conn)?;
+    let rows = conn.execute("UPDATE customers SET credit_limit = ?1 WHERE phone = ?2", params![round_money(credit_limit), phone])
+        .map_err(|e| format!("Failed to update credit limit: {}", e))?;
+    if rows == 0 {
+        return Err(format!("Customer {} not found", phone));
+    }
+    load_balance(&conn, &phone)
+}
+
+/// Records money paid back against the tab. Cash goes into the drawer, so it
+/// needs an open shift and counts towards that shift's expected cash.
+#[command]
+pub fn record_repayment(window: Window, phone: String, amount: f64, method: Option<String>, note: Option<String>) -> Result<CreditBalance, String> {
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err("Repayment must be positive".to_string());
+    }
+    let phone = normalize_phone(&phone)?;
+    let method = method.unwrap_or_else(|| "cash".to_string());
+    let db_path = get_db_path(&window);
+    let mut conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+    ensure_accounts_here(&tx)?;
+    let shift_id = if method == "cash" {
+        Some(shift::require_open_shift(&tx)?.shift_id)
+    } else {
+        shift::open_shift_row(&tx)?.map(|s| s.shift_id)
+    };
+    let account = load_balance(&tx, &phone)?;
+    if round_money(amount) > account.balance {
+        return Err(format!("{} only owes {}", account.name, account.balance));
+    }
+    tx.execute(
+        "INSERT INTO credit_ledger (phone, kind, amount, method, shift_id, note, created_at) VALUES (?1, 'payment', ?2, ?3, ?4, ?5, ?6)",
+        params![phone, -round_money(amount), method, shift_id, note, now_string()],
+    )
+    .map_err(|e| format!("Failed to record repayment: {}", e))?;
+    let account = load_balance(&tx, &phone)?;
+    tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+    Ok(account)
+}
+
+#[command]
+pub fn credit_balance(window: Window, phone: String) -> Result<CreditBalance, String> {
+    let phone = normalize_phone(&phone)?;
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    load_balance(&conn, &phone)
+}
+
+/// Customers who currently owe money, largest balance first.
+#[command]
+pub fn list_credit_balances(window: Window) -> Result<Vec<CreditBalance>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut stmt = conn.prepare(
+        "SELECT c.phone, c.name, c.credit_limit, ROUND(SUM(l.amount), 2) AS balance
+         FROM credit_ledger l JOIN customers c ON c.phone = l.phone
+         GROUP BY c.phone HAVING balance > 0 ORDER BY balance DESC",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let balances = stmt.query_map(params![], |row| {
+        let credit_limit: f64 = row.get(2)?;
+        let balance: f64 = row.get(3)?;
+        Ok(CreditBalance {
+            phone: row.get(0)?,
+            name: row.get(1)?,
+            credit_limit,
+            balance,
+            available: round_money((credit_limit - balance).max(0.0)),
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(balances)
+}
+
+#[command]
+pub fn credit_ledger(window: Window, phone: String) -> Result<Vec<CreditEntry>, String> {
+    let phone = normalize_phone(&phone)?;
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut stmt = conn.prepare(
+        "SELECT entry_id, phone, invoice_id, kind, amount, method, shift_id, note, created_at
+         FROM credit_ledger WHERE phone = ?1 ORDER BY entry_id",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let entries = stmt.query_map(params![phone], |row| {
+        Ok(CreditEntry {
+            entry_id: row.get(0)?,
+            phone: row.get(1)?,
+            invoice_id: row.get(2)?,
+            kind: row.get(3)?,
+            amount: row.get(4)?,
+            method: row.get(5)?,
+            shift_id: row.get(6)?,
+            note: row.get(7)?,
+            created_at: row.get(8)?,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(entries)
+}
+
+/// Spreads what is still owed over a customer's tab sales, oldest first
+/// paid off by `paid`. Each sale is `(amount, age in days, created_at)`.
+fn age_debt(row: &mut AgingRow, sales: Vec<(f64, i64, String)>, mut paid: f64) {
+    for (amount, age, created_at) in sales {
+        let unpaid = round_money(amount - paid.min(amount));
+        paid = (paid - amount).max(0.0);
+        if unpaid <= 0.0 {
+            continue;
+        }
+        let bucket = match age {
+            ..=30 => &mut row.current,
+            31..=60 => &mut row.days_31_60,
+            61..=90 => &mut row.days_61_90,
+            _ => &mut row.over_90,
+        };
+        *bucket = round_money(*bucket + unpaid);
+        row.balance = round_money(row.balance + unpaid);
+        row.oldest_at.get_or_insert(created_at);
+    }
+}
+
+/// Buckets each customer's unpaid sales by age. Repayments and refunds put
+/// back on the tab settle the oldest sales first, so whatever remains is the
+/// most recent debt.
+#[command]
+pub fn credit_aging(window: Window) -> Result<Vec<AgingRow>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut stmt = conn.prepare(
+        "SELECT c.phone, c.name,
+                COALESCE((SELECT -SUM(p.amount) FROM credit_ledger p WHERE p.phone = c.phone AND p.kind IN ('payment', 'refund')), 0)
+         FROM customers c
+         WHERE EXISTS (SELECT 1 FROM credit_ledger l WHERE l.phone = c.phone AND l.kind = 'sale')",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let customers: Vec<(String, String, f64)> = stmt
+        .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(|e| format!("Failed to query: {}", e))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    let mut sales_stmt = conn.prepare(
+        "SELECT amount, created_at FROM credit_ledger WHERE phone = ?1 AND kind = 'sale' ORDER BY created_at, entry_id",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let now = Utc::now().naive_utc();
+    let mut rows = Vec::new();
+    for (phone, name, paid) in customers {
+        let sales: Vec<(f64, i64, String)> = sales_stmt
+            .query_map(params![phone], |row| Ok((row.get::<_, f64>(0)?, row.get::<_, String>(1)?)))
+            .map_err(|e| format!("Failed to query: {}", e))?
+            .filter_map(|r| r.ok())
+            .map(|(amount, created_at)| {
+                let age = NaiveDateTime::parse_from_str(&created_at, "%Y-%m-%d %H:%M:%S")
+                    .map(|at| (now - at).num_days())
+                    .unwrap_or(0);
+                (amount, age, created_at)
+            })
+            .collect();
+        let mut row = AgingRow {
+            phone, name, balance: 0.0, current: 0.0, days_31_60: 0.0, days_61_90: 0.0, over_90: 0.0, oldest_at: None,
+        };
+        age_debt(&mut row, sales, paid);
+        if row.balance > 0.0 {
+            rows.push(row);
+        }
+    }
+    rows.sort_by(|a, b| b.balance.partial_cmp(&a.balance).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(rows)
+}
+
+commands![
+    set_credit_limit,
+    record_repayment,
+    credit_balance,
+    list_credit_balances,
+    credit_ledger,
+    credit_aging,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aged(sales: &[(f64, i64)], paid: f64) -> AgingRow {
+        let mut row = AgingRow {
+            phone: "0901234567".to_string(),
+            name: "Lan".to_string(),
+            balance: 0.0,
+            current: 0.0,
+            days_31_60: 0.0,
+            days_61_90: 0.0,
+            over_90: 0.0,
+            oldest_at: None,
+        };
+        let sales = sales.iter().map(|(amount, age)| (*amount, *age, format!("{} days ago", age))).collect();
+        age_debt(&mut row, sales, paid);
+        row
+    }
+
+    #[test]
+    fn buckets_unpaid_sales_by_age() {
+        let row = aged(&[(100.0, 120), (200.0, 75), (300.0, 45), (400.0, 3)], 0.0);
+        assert_eq!((row.current, row.days_31_60, row.days_61_90, row.over_90), (400.0, 300.0, 200.0, 100.0));
+        assert_eq!(row.balance, 1000.0);
+        assert_eq!(row.oldest_at.as_deref(), Some("120 days ago"));
+    }
+
+    #[test]
+    fn bucket_edges() {
+        let row = aged(&[(1.0, 91), (2.0, 90), (4.0, 61), (8.0, 60), (16.0, 31), (32.0, 30), (64.0, 0)], 0.0);
+        assert_eq!((row.current, row.days_31_60, row.days_61_90, row.over_90), (96.0, 24.0, 6.0, 1.0));
+    }
+
+    #[test]
+    fn payments_settle_the_oldest_sales_first() {
+        let row = aged(&[(100.0, 120), (200.0, 45), (300.0, 3)], 150.0);
+        assert_eq!((row.current, row.days_31_60, row.over_90), (300.0, 150.0, 0.0));
+        assert_eq!(row.balance, 450.0);
+        assert_eq!(row.oldest_at.as_deref(), Some("45 days ago"));
+
+        let row = aged(&[(100.0, 120), (200.0, 45)], 300.0);
+        assert_eq!(row.balance, 0.0);
+        assert_eq!(row.oldest_at, None);
+    }
+}
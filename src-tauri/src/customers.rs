@@ -0,0 +1,442 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, OptionalExtension, Result, Transaction};
+use tauri::{command, Window};
+
+use crate::cart::{get_db_path, now_string};
+use crate::db::add_column_if_missing;
+use crate::settings::Settings;
+use crate::sync::SyncRole;
+
+/// Tender method that pays with loyalty points; its amount is in VND.
+pub const POINTS_TENDER: &str = "points";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Customer {
+    pub phone: String,
+    pub name: String,
+    pub points: i64,
+    pub note: Option<String>,
+    pub created_at: String,
+}
+
+/// How points are earned and spent.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoyaltyRules {
+    pub enabled: bool,
+    pub spend_per_point: f64, // VND paid (excluding points) for one point
+    pub point_value: f64,     // VND one point is worth when redeemed
+    pub min_redeem_points: i64,
+}
+
+impl Default for LoyaltyRules {
+    fn default() -> Self {
+        LoyaltyRules {
+            enabled: true,
+            spend_per_point: 10000.0,
+            point_value: 100.0,
+            min_redeem_points: 0,
+        }
+    }
+}
+
+impl LoyaltyRules {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.spend_per_point > 0.0) || !(self.point_value > 0.0) {
+            return Err("Loyalty spend per point and point value must be positive".to_string());
+        }
+        if self.min_redeem_points < 0 {
+            return Err("Minimum points to redeem cannot be negative".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CustomerPurchase {
+    pub invoice_id: String,
+    pub paid_at: Option<String>,
+    pub total: f64,
+    pub item_count: i64,
+    pub points_earned: i64,
+    pub points_redeemed: i64,
+}
+
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS customers (
+            phone VARCHAR(20) PRIMARY KEY,
+            name VARCHAR(255) NOT NULL DEFAULT '',
+            points INTEGER NOT NULL DEFAULT 0,
+            note TEXT,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS customer_points (
+            phone VARCHAR(20) NOT NULL,
+            invoice_id VARCHAR(100),
+            points INTEGER NOT NULL,
+            reason VARCHAR(20) NOT NULL,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (phone) REFERENCES customers(phone)
+        )",
+        params![],
+    )?;
+    add_column_if_missing(conn, "carts", "customer_phone", "VARCHAR(20)")?;
+    add_column_if_missing(conn, "invoices", "customer_phone", "VARCHAR(20)")?;
+    add_column_if_missing(conn, "invoices", "points_earned", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "invoices", "points_redeemed", "INTEGER NOT NULL DEFAULT 0")?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_invoices_customer ON invoices(customer_phone)", params![])?;
+    Ok(())
+}
+
+/// Reduces a phone number to its digits, writing +84 numbers in the local
+/// 0-prefixed form so the same customer is never keyed twice.
+pub fn normalize_phone(phone: &str) -> Result<String, String> {
+    let trimmed = phone.trim();
+    let mut digits: String = trimmed.chars().filter(|c| c.is_ascii_digit()).collect();
+    if trimmed.starts_with('+') {
+        match digits.strip_prefix("84") {
+            Some(local) => digits = format!("0{}", local),
+            None => return Err(format!("Invalid phone number {}: only Vietnamese numbers are supported", trimmed)),
+        }
+    }
+    if !(9..=11).contains(&digits.len()) {
+        return Err(format!("Invalid phone number {}", trimmed));
+    }
+    Ok(digits)
+}
+
+pub fn load_customer(conn: &Connection, phone: &str) -> Result<Option<Customer>, String> {
+    conn.query_row(
+        "SELECT phone, name, points, note, created_at FROM customers WHERE phone = ?1",
+        params![phone],
+        |row| {
+            Ok(Customer {
+                phone: row.get(0)?,
+                name: row.get(1)?,
+                points: row.get(2)?,
+                note: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| format!("Failed to load customer: {}", e))
+}
+
+fn add_points(tx: &Transaction, phone: &str, invoice_id: &str, points: i64, reason: &str) -> Result<(), String> {
+    tx.execute(
+        "INSERT INTO customer_points (phone, invoice_id, points, reason, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![phone, invoice_id, points, reason, now_string()],
+    )
+    .map_err(|e| format!("Failed to record points: {}", e))?;
+    tx.execute("UPDATE customers SET points = points + ?1 WHERE phone = ?2", params![points, phone])
+        .map_err(|e| format!("Failed to update points: {}", e))?;
+    Ok(())
+}
+
+/// Links a completed invoice to the cart's customer, takes the points paid
+/// as a tender and credits the points earned on the rest. Runs inside the
+/// payment transaction, after `complete_invoice`.
+pub fn settle_loyalty(tx: &Transaction, cart_id: i64, invoice_id: &str) -> Result<(), String> {
+    let phone: Option<String> = tx.query_row("SELECT customer_phone FROM carts WHERE cart_id = ?1", params![cart_id], |row| row.get(0))
+        .map_err(|e| format!("Failed to load cart: {}", e))?;
+    let (total, points_paid): (f64, f64) = tx.query_row(
+        "SELECT total, COALESCE((SELECT SUM(amount) FROM invoice_tenders WHERE invoice_id = ?1 AND method = ?2), 0)
+         FROM invoices WHERE invoice_id = ?1",
+        params![invoice_id, POINTS_TENDER],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .map_err(|e| format!("Failed to load invoice: {}", e))?;
+
+    let phone = match phone {
+        Some(phone) => phone,
+        None if points_paid > 0.0 => return Err("Attach a customer to pay with points".to_string()),
+        None => return Ok(()),
+    };
+    ensure_accounts_here(tx)?;
+    let rules = Settings::load(tx)?.loyalty;
+    let customer = load_customer(tx, &phone)?.ok_or_else(|| format!("Customer {} not found", phone))?;
+
+    let mut redeemed = 0;
+    if points_paid > 0.0 {
+        if !rules.enabled {
+            return Err("Loyalty points are turned off".to_string());
+        }
+        if points_paid > total {
+            return Err("Points cannot pay more than the invoice total".to_string());
+        }
+        let points = points_paid / rules.point_value;
+        if (points - points.round()).abs() > 1e-6 {
+            return Err(format!("Points pay in steps of {} VND", rules.point_value));
+        }
+        redeemed = points.round() as i64;
+        if redeemed < rules.min_redeem_points {
+            return Err(format!("At least {} points must be redeemed at once", rules.min_redeem_points));
+        }
+        if redeemed > customer.points {
+            return Err(format!("{} has only {} points ({} needed)", customer.name, customer.points, redeemed));
+        }
+        add_points(tx, &phone, invoice_id, -redeemed, "redeem")?;
+    }
+    let earned = if rules.enabled { ((total - points_paid) / rules.spend_per_point).floor().max(0.0) as i64 } else { 0 };
+    if earned > 0 {
+        add_points(tx, &phone, invoice_id, earned, "earn")?;
+    }
+    tx.execute(
+        "UPDATE invoices SET customer_phone = ?1, points_earned = ?2, points_redeemed = ?3 WHERE invoice_id = ?4",
+        params![phone, earned, redeemed, invoice_id],
+    )
+    .map_err(|e| format!("Failed to link customer: {}", e))?;
+    Ok(())
+}
+
+/// Takes back the points earned on the returned share of an invoice and
+/// gives back, as points, the share of its `points` tender that was refunded.
+/// Both follow the invoice's refunds so far, so several partial returns add
+/// up to the whole. Runs inside the return transaction, after the return and
+/// its refund tenders are recorded.
+pub fn reverse_loyalty(tx: &Transaction, invoice_id: &str, return_id: &str) -> Result<(), String> {
+    let (phone, total, earned, redeemed, points_paid): (Option<String>, f64, i64, i64, f64) = tx.query_row(
+        "SELECT customer_phone, total, points_earned, points_redeemed,
+                COALESCE((SELECT SUM(amount) FROM invoice_tenders WHERE invoice_id = ?1 AND method = ?2), 0)
+         FROM invoices WHERE invoice_id = ?1",
+        params![invoice_id, POINTS_TENDER],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+    )
+    .map_err(|e| format!("Failed to load invoice: {}", e))?;
+    let phone = match phone {
+        Some(phone) => phone,
+        None => return Ok(()),
+    };
+    let (refunded, refund, points_refunded, points_refund): (f64, f64, f64, f64) = tx.query_row(
+        "SELECT COALESCE(SUM(r.refund_total), 0),
+                COALESCE(SUM(CASE WHEN r.return_id = ?2 THEN r.refund_total END), 0),
+                COALESCE((SELECT SUM(t.amount) FROM return_tenders t JOIN returns r2 ON r2.return_id = t.return_id
+                          WHERE r2.invoice_id = ?1 AND t.method = ?3), 0),
+                COALESCE((SELECT SUM(t.amount) FROM return_tenders t WHERE t.return_id = ?2 AND t.method = ?3), 0)
+         FROM returns r WHERE r.invoice_id = ?1",
+        params![invoice_id, return_id, POINTS_TENDER],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )
+    .map_err(|e| format!("Failed to load returns: {}", e))?;
+
+    let share = |points: i64, part: f64, whole: f64| {
+        if whole > 0.0 { (points as f64 * (part / whole).min(1.0)).round() as i64 } else { 0 }
+    };
+    let taken_back = share(earned, refunded, total) - share(earned, refunded - refund, total);
+    let given_back = share(redeemed, points_refunded, points_paid) - share(redeemed, points_refunded - points_refund, points_paid);
+    if taken_back != 0 {
+        add_points(tx, &phone, invoice_id, -taken_back, "return")?;
+    }
+    if given_back != 0 {
+        add_points(tx, &phone, invoice_id, given_back, "return")?;
+    }
+    Ok(())
+}
+
+/// Customers, their points and their store credit are not synced between
+/// tills. With sync on they live on the host till; clients refuse to change
+/// them rather than keep a second, diverging copy.
+pub fn ensure_accounts_here(conn: &Connection) -> Result<(), String> {
+    if Settings::load(conn)?.sync.role == SyncRole::Client {
+        return Err("Customer accounts are kept on the sync host till; use that till for loyalty and credit".to_string());
+    }
+    Ok(())
+}
+
+/// Creates the customer or updates their name and note.
+#[command]
+pub fn save_customer(window: Window, phone: String, name: String, note: Option<String>) -> Result<Customer, String> {
+    let phone = normalize_phone(&phone)?;
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    ensure_accounts_here(&conn)?;
+    conn.execute(
+        "INSERT INTO customers (phone, name, note, created_at) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(phone) DO UPDATE SET name = excluded.name, note = excluded.note",
+        params![phone, name.trim(), note, now_string()],
+    )
+    .map_err(|e| format!("Failed to save customer: {}", e))?;
+    load_customer(&conn, &phone)?.ok_or_else(|| "Customer not found".to_string())
+}
+
+#[command]
+pub fn get_customer(window: Window, phone: String) -> Result<Option<Customer>, String> {
+    let phone = normalize_phone(&phone)?;
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    load_customer(&conn, &phone)
+}
+
+/// Customers whose phone or name contains the query.
+#[command]
+pub fn search_customers(window: Window, query: String) -> Result<Vec<Customer>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut stmt = conn.prepare(
+        "SELECT phone, name, points, note, created_at FROM customers WHERE phone LIKE ?1 OR name LIKE ?1 ORDER BY name LIMIT 50",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let customers = stmt.query_map(params![format!("%{}%", query.trim())], |row| {
+        Ok(Customer {
+            phone: row.get(0)?,
+            name: row.get(1)?,
+            points: row.get(2)?,
+            note: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(customers)
+}
+
+/// Attaches a customer to a cart before payment; `None` detaches.
+#[command]
+pub fn attach_customer(window: Window, cart_id: i64, phone: Option<String>) -> Result<Option<Customer>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let customer = match phone {
+        Some(phone) => {
+            let phone = normalize_phone(&phone)?;
+            ensure_accounts_here(&conn)?;
+            Some(load_customer(&conn, &phone)?.ok_or_else(|| format!("Customer {} not found", phone))?)
+        }
+        None => None,
+    };
+    let rows = conn.execute(
+        "UPDATE carts SET customer_phone = ?1 WHERE cart_id = ?2",
+        params![customer.as_ref().map(|c| c.phone.clone()), cart_id],
+    )
+    .map_err(|e| format!("Failed to attach customer: {}", e))?;
+    if rows == 0 {
+        return Err("Cart not found".to_string());
+    }
+    Ok(customer)
+}
+
+/// Paid invoices of a customer, newest first.
+#[command]
+pub fn customer_history(window: Window, phone: String, limit: Option<i64>) -> Result<Vec<CustomerPurchase>, String> {
+    let phone = normalize_phone(&phone)?;
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut stmt = conn.prepare(
+        "SELECT i.invoice_id, i.paid_at, i.total,
+                (SELECT COUNT(*) FROM invoice_items it WHERE it.invoice_id = i.invoice_id),
+                i.points_earned, i.points_redeemed
+         FROM invoices i
+         WHERE i.customer_phone = ?1 AND i.status = 'completed'
+         ORDER BY i.paid_at DESC, i.invoice_id DESC
+         LIMIT ?2",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let purchases = stmt.query_map(params![phone, limit.unwrap_or(50)], |row| {
+        Ok(CustomerPurchase {
+            invoice_id: row.get(0)?,
+            paid_at: row.get(1)?,
+            total: row.get(2)?,
+            item_count: row.get(3)?,
+            points_earned: row.get(4)?,
+            points_redeemed: row.get(5)?,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(purchases)
+}
+
+commands![
+    save_customer,
+    get_customer,
+    search_customers,
+    attach_customer,
+    customer_history,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SALE: &str = "S1_default_storeman_20261018_001";
+    const PHONE: &str = "0901234567";
+
+    /// A paid 250.000 VND sale to a customer holding 1000 points, with
+    /// `points_paid` VND of it paid in points and the rest in cash.
+    fn sale(points_paid: f64) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE products (Barcode VARCHAR, Item_name VARCHAR, Retail_price FLOAT)", params![]).unwrap();
+        crate::db::initialize_tables(&conn).unwrap();
+        conn.execute("INSERT INTO customers (phone, name, points) VALUES (?1, 'Lan', 1000)", params![PHONE]).unwrap();
+        conn.execute("INSERT INTO carts (cart_name, customer_phone) VALUES ('a', ?1)", params![PHONE]).unwrap();
+        conn.execute(
+            "INSERT INTO invoices (invoice_id, cart_id, store_id, storeman_id, subtotal, total, status) VALUES (?1, 1, 'S1', 'default_storeman', 250000, 250000, 'completed')",
+            params![SALE],
+        )
+        .unwrap();
+        conn.execute("INSERT INTO invoice_tenders (invoice_id, method, amount) VALUES (?1, ?2, ?3)", params![SALE, POINTS_TENDER, points_paid]).unwrap();
+        conn.execute("INSERT INTO invoice_tenders (invoice_id, method, amount) VALUES (?1, 'cash', ?2)", params![SALE, 250000.0 - points_paid]).unwrap();
+        conn
+    }
+
+    fn settle(conn: &mut Connection) -> Result<(), String> {
+        let tx = conn.transaction().unwrap();
+        settle_loyalty(&tx, 1, SALE)?;
+        tx.commit().unwrap();
+        Ok(())
+    }
+
+    fn points(conn: &Connection) -> i64 {
+        load_customer(conn, PHONE).unwrap().unwrap().points
+    }
+
+    fn give_back(conn: &mut Connection, n: u32, refund: f64, in_points: f64) {
+        let tx = conn.transaction().unwrap();
+        let return_id = format!("{}_R{:02}", SALE, n);
+        tx.execute(
+            "INSERT INTO returns (return_id, invoice_id, reason, refund_total, restocked, created_at) VALUES (?1, ?2, NULL, ?3, 1, ?4)",
+            params![return_id, SALE, refund, now_string()],
+        )
+        .unwrap();
+        tx.execute("INSERT INTO return_tenders (return_id, method, amount) VALUES (?1, ?2, ?3)", params![return_id, POINTS_TENDER, in_points]).unwrap();
+        tx.execute("INSERT INTO return_tenders (return_id, method, amount) VALUES (?1, 'cash', ?2)", params![return_id, refund - in_points]).unwrap();
+        reverse_loyalty(&tx, SALE, &return_id).unwrap();
+        tx.commit().unwrap();
+    }
+
+    #[test]
+    fn settles_points_paid_and_earned() {
+        let mut conn = sale(20000.0);
+        settle(&mut conn).unwrap();
+        // 200 points redeemed, 23 earned on the 230.000 paid in cash.
+        assert_eq!(points(&conn), 823);
+        let linked: (String, i64, i64) = conn
+            .query_row("SELECT customer_phone, points_earned, points_redeemed FROM invoices WHERE invoice_id = ?1", params![SALE], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(linked, (PHONE.to_string(), 23, 200));
+
+        assert!(settle(&mut sale(15050.0)).unwrap_err().contains("steps of"));
+        assert!(settle(&mut sale(150000.0)).unwrap_err().contains("only 1000 points"));
+    }
+
+    #[test]
+    fn partial_returns_add_up_to_the_whole_sale() {
+        let mut conn = sale(20000.0);
+        settle(&mut conn).unwrap();
+        give_back(&mut conn, 1, 100000.0, 8000.0);
+        assert_eq!(points(&conn), 823 - 9 + 80);
+        give_back(&mut conn, 2, 100000.0, 8000.0);
+        assert_eq!(points(&conn), 894 - 9 + 80);
+        give_back(&mut conn, 3, 50000.0, 4000.0);
+        assert_eq!(points(&conn), 1000);
+        let ledger: i64 = conn.query_row("SELECT SUM(points) FROM customer_points WHERE phone = ?1", params![PHONE], |row| row.get(0)).unwrap();
+        assert_eq!(ledger, 0);
+    }
+}
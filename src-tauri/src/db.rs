@@ -0,0 +1,53 @@
+use rusqlite::{params, Connection, Result};
+
+use crate::{analytics, cart, cart_transfer, credit, customers, journal, promotions, reports, returns, sales, scale, settings, shift, sync, tax};
+
+/// SQLite has no `ADD COLUMN IF NOT EXISTS`; check `table_info` first so
+/// tables created by an older build pick up new columns on startup.
+pub fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map(params![], |row| row.get::<_, String>(1))?
+        .filter_map(|r| r.ok())
+        .any(|name| name.eq_ignore_ascii_case(column));
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), params![])?;
+    }
+    Ok(())
+}
+
+/// Creates or upgrades every module's tables. Runs once at startup, after
+/// the database file has been found or created.
+pub fn initialize_tables(conn: &Connection) -> Result<(), String> {
+    cart::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize database tables: {}", e))?;
+    sales::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize sales tables: {}", e))?;
+    returns::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize returns tables: {}", e))?;
+    shift::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize shift tables: {}", e))?;
+    reports::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize reports tables: {}", e))?;
+    analytics::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize analytics tables: {}", e))?;
+    settings::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize settings tables: {}", e))?;
+    tax::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize tax tables: {}", e))?;
+    promotions::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize promotions tables: {}", e))?;
+    scale::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize scale tables: {}", e))?;
+    customers::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize customers tables: {}", e))?;
+    credit::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize credit tables: {}", e))?;
+    cart_transfer::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize cart_transfer tables: {}", e))?;
+    sync::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize sync tables: {}", e))?;
+    journal::initialize_tables(conn)
+        .map_err(|e| format!("Failed to initialize journal tables: {}", e))?;
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection};
+use chrono::Utc;
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::{command, State, Window};
+
+use crate::backup::{latest_backup, overwrite_with, verify_database, BackupPolicy};
+use crate::cart::{get_db_path, now_string};
+use crate::settings::Settings;
+
+// Rows here only make sense with their cart, so they can be dropped safely.
+const CART_CHILD_TABLES: [&str; 2] = ["cart_items", "cart_discounts"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IntegrityReport {
+    pub checked_at: String,
+    pub ok: bool,                             // nothing left to fix
+    pub corruption: Vec<String>,              // integrity_check messages
+    pub foreign_key_violations: Vec<String>,  // "table row N -> parent"
+    pub orphaned_cart_items: i64,
+    pub actions: Vec<String>,                 // repairs made, in order
+    pub restored_from: Option<String>,
+}
+
+/// Last report, kept for windows that open after the startup check.
+pub struct IntegrityState(pub Mutex<IntegrityReport>);
+
+fn table_exists(conn: &Connection, table: &str) -> Result<bool, String> {
+    conn.query_row("SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)", params![table], |row| row.get(0))
+        .map_err(|e| format!("Failed to read schema: {}", e))
+}
+
+fn pragma_rows(conn: &Connection, pragma: &str) -> Result<Vec<String>, String> {
+    let mut stmt = conn.prepare(pragma).map_err(|e| format!("Failed to run {}: {}", pragma, e))?;
+    let rows = stmt.query_map(params![], |row| row.get(0))
+        .map_err(|e| format!("Failed to run {}: {}", pragma, e))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+/// Reads the database without changing it. `quick_check` runs first; the
+/// slower full `integrity_check` only when it finds something.
+pub fn check(conn: &Connection) -> Result<IntegrityReport, String> {
+    let mut report = IntegrityReport { checked_at: now_string(), ..Default::default() };
+    let quick: Vec<String> = pragma_rows(conn, "PRAGMA quick_check")?.into_iter().filter(|r| r != "ok").collect();
+    if !quick.is_empty() {
+        report.corruption = pragma_rows(conn, "PRAGMA integrity_check")?.into_iter().filter(|r| r != "ok").collect();
+        if report.corruption.is_empty() {
+            report.corruption = quick;
+        }
+    }
+
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check").map_err(|e| format!("Failed to check foreign keys: {}", e))?;
+    report.foreign_key_violations = stmt.query_map(params![], |row| {
+        let table: String = row.get(0)?;
+        let rowid: Option<i64> = row.get(1)?;
+        let parent: String = row.get(2)?;
+        Ok(format!("{} row {} -> {}", table, rowid.map(|r| r.to_string()).unwrap_or_default(), parent))
+    })
+    .map_err(|e| format!("Failed to check foreign keys: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+
+    // Carts removed by the expiry sweep on a build without enforced keys
+    // could leave their items behind.
+    if table_exists(conn, "cart_items")? && table_exists(conn, "carts")? {
+        report.orphaned_cart_items = conn
+            .query_row("SELECT COUNT(*) FROM cart_items WHERE cart_id NOT IN (SELECT cart_id FROM carts)", params![], |row| row.get(0))
+            .map_err(|e| format!("Failed to count orphaned cart items: {}", e))?;
+    }
+    report.ok = report.corruption.is_empty() && report.foreign_key_violations.is_empty() && report.orphaned_cart_items == 0;
+    Ok(report)
+}
+
+fn remove_orphans(conn: &Connection, actions: &mut Vec<String>) -> Result<(), String> {
+    if !table_exists(conn, "carts")? {
+        return Ok(());
+    }
+    for table in CART_CHILD_TABLES {
+        if !table_exists(conn, table)? {
+            continue;
+        }
+        let removed = conn
+            .execute(&format!("DELETE FROM {} WHERE cart_id NOT IN (SELECT cart_id FROM carts)", table), params![])
+            .map_err(|e| format!("Failed to remove orphaned {}: {}", table, e))?;
+        if removed > 0 {
+            actions.push(format!("Removed {} orphaned rows from {}", removed, table));
+        }
+    }
+    Ok(())
+}
+
+/// Keeps the damaged file next to the database before it is overwritten.
+fn restore_latest(db_path: &Path, policy: &BackupPolicy, actions: &mut Vec<String>) -> Result<String, String> {
+    let backup = latest_backup(db_path, policy)?.ok_or("No backup to restore from")?;
+    verify_database(Path::new(&backup.path))?;
+    let aside = db_path.with_extension(format!("db.corrupt-{}", Utc::now().naive_utc().format("%Y%m%d-%H%M%S-%3f")));
+    std::fs::copy(db_path, &aside).map_err(|e| format!("Failed to keep the damaged database: {}", e))?;
+    actions.push(format!("Saved the damaged database as {}", aside.display()));
+    // A file too damaged to open cannot take a page copy; at startup nothing
+    // else holds it, so it is replaced outright.
+    if let Err(e) = overwrite_with(db_path, Path::new(&backup.path)) {
+        std::fs::copy(&backup.path, db_path).map_err(|c| format!("{}; copying the backup failed: {}", e, c))?;
+    }
+    actions.push(format!("Restored {}", backup.file_name));
+    Ok(backup.path)
+}
+
+/// Removes orphaned cart rows and rebuilds indexes; if the file is still
+/// damaged and `allow_restore` is set, restores the latest backup. Other
+/// foreign-key problems are only reported: they involve sales records.
+pub fn repair(db_path: &Path, policy: &BackupPolicy, allow_restore: bool) -> Result<IntegrityReport, String> {
+    let conn = Connection::open(db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut actions = Vec::new();
+    let before = match check(&conn) {
+        Ok(report) => report,
+        Err(e) if allow_restore => {
+            actions.push(format!("Database unreadable: {}", e));
+            drop(conn);
+            let restored_from = restore_latest(db_path, policy, &mut actions)?;
+            return finish(db_path, actions, Some(restored_from));
+        }
+        Err(e) => return Err(e),
+    };
+    if !before.corruption.is_empty() {
+        // Index-only damage is fixed by rebuilding the indexes.
+        match conn.execute_batch("REINDEX") {
+            Ok(()) => actions.push("Rebuilt indexes".to_string()),
+            Err(e) => actions.push(format!("Rebuilding indexes failed: {}", e)),
+        }
+        if !check(&conn)?.corruption.is_empty() && allow_restore {
+            drop(conn);
+            let restored_from = restore_latest(db_path, policy, &mut actions)?;
+            return finish(db_path, actions, Some(restored_from));
+        }
+    }
+    if before.orphaned_cart_items > 0 || !before.foreign_key_violations.is_empty() {
+        remove_orphans(&conn, &mut actions)?;
+    }
+    drop(conn);
+    finish(db_path, actions, None)
+}
+
+fn finish(db_path: &Path, actions: Vec<String>, restored_from: Option<String>) -> Result<IntegrityReport, String> {
+    let conn = Connection::open(db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut report = check(&conn)?;
+    report.actions = actions;
+    report.restored_from = restored_from;
+    Ok(report)
+}
+
+/// Runs in the setup hook before the tables are touched. Never fails: a
+/// database that cannot even be read is reported as corrupt. With
+/// `auto_repair` it also repairs in place, but a backup is only ever
+/// restored through `repair_database`.
+pub fn startup_check(db_path: &Path) -> IntegrityReport {
+    let settings = Connection::open(db_path)
+        .map_err(|e| e.to_string())
+        .and_then(|conn| Settings::load(&conn))
+        .unwrap_or_default();
+    let result = Connection::open(db_path)
+        .map_err(|e| format!("Failed to open DB: {}", e))
+        .and_then(|conn| check(&conn));
+    let report = match result {
+        Ok(report) if report.ok || !settings.auto_repair => Ok(report),
+        Err(e) if !settings.auto_repair => Err(e),
+        _ => repair(db_path, &settings.backup, false),
+    };
+    report.unwrap_or_else(|e| IntegrityReport {
+        checked_at: now_string(),
+        ok: false,
+        corruption: vec![e],
+        ..Default::default()
+    })
+}
+
+#[command]
+pub fn last_integrity_report(state: State<'_, IntegrityState>) -> Result<IntegrityReport, String> {
+    Ok(state.0.lock().unwrap().clone())
+}
+
+#[command]
+pub fn check_database(window: Window, state: State<'_, IntegrityState>) -> Result<IntegrityReport, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let report = check(&conn)?;
+    *state.0.lock().unwrap() = report.clone();
+    Ok(report)
+}
+
+/// Repairs on request; `restore` allows falling back to the latest backup.
+#[command]
+pub fn repair_database(window: Window, state: State<'_, IntegrityState>, restore: bool) -> Result<IntegrityReport, String> {
+    let db_path = get_db_path(&window);
+    let policy = {
+        let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+        Settings::load(&conn).map(|s| s.backup).unwrap_or_default()
+    };
+    let report = repair(&db_path, &policy, restore)?;
+    *state.0.lock().unwrap() = report.clone();
+    let _ = window.emit("integrity-report", report.clone());
+    Ok(report)
+}
+
+commands![
+    last_integrity_report,
+    check_database,
+    repair_database,
+];
@@ -0,0 +1,720 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::Connection;
+use tauri::{command, Window, Manager};
+use tauri::api::path::app_data_dir;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use owned_ttf_parser::Face;
+use printpdf::{Mm, PdfDocument, Pt};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::cart::get_db_path;
+use crate::receipt::{format_money, format_quantity, load_receipt_data, ReceiptData};
+
+const DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="vi">
+<head>
+<meta charset="utf-8">
+<title>Hóa đơn {{invoice_id}}</title>
+<style>
+  @page { size: {{page_size}}; margin: 15mm; }
+  body { font-family: Arial, sans-serif; font-size: 12px; color: #222; }
+  h1 { font-size: 18px; margin: 0; }
+  .store { text-align: center; margin-bottom: 12px; }
+  .meta td { padding: 2px 8px 2px 0; }
+  table.items { width: 100%; border-collapse: collapse; margin-top: 12px; }
+  table.items th, table.items td { border-bottom: 1px solid #ddd; padding: 4px; }
+  .num { text-align: right; }
+  .totals { margin-top: 12px; margin-left: auto; }
+  .totals td { padding: 2px 8px; }
+  .grand td { font-weight: bold; font-size: 14px; }
+</style>
+</head>
+<body>
+<div class="store" style="text-align: center">
+  <h1>{{store_name}}</h1>
+  <div>{{store_address}}</div>
+  <div>{{store_phone}}</div>
+</div>
+<h2 style="text-align: center">HÓA ĐƠN BÁN HÀNG</h2>
+<table class="meta">
+  <tr><td>Số hóa đơn:</td><td>{{invoice_id}}</td></tr>
+  <tr><td>Ngày:</td><td>{{date}}</td></tr>
+  <tr><td>Thu ngân:</td><td>{{cashier}}</td></tr>
+</table>
+<table class="items">
+  <thead><tr><th>#</th><th>Sản phẩm</th><th>ĐVT</th><th class="num">SL</th><th class="num">Đơn giá</th><th class="num">Giảm giá</th><th class="num">Thành tiền</th></tr></thead>
+  <tbody>
+{{items}}
+  </tbody>
+</table>
+<table class="totals">
+  <tr><td>Tạm tính</td><td class="num">{{subtotal}}</td></tr>
+  <tr><td>Giảm giá</td><td class="num">{{discount}}</td></tr>
+{{taxes}}
+  <tr class="grand"><td><strong>Tổng cộng</strong></td><td class="num"><strong>{{total}}</strong></td></tr>
+{{tenders}}
+  <tr><td>Tiền thừa</td><td class="num">{{change}}</td></tr>
+</table>
+<p>{{footer}}</p>
+</body>
+</html>
+"#;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PageSize {
+    A4,
+    A5,
+}
+
+impl PageSize {
+    /// Width and height in PDF points.
+    fn dimensions(self) -> (f64, f64) {
+        match self {
+            PageSize::A4 => (595.0, 842.0),
+            PageSize::A5 => (420.0, 595.0),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Html,
+    Pdf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InvoiceExportOptions {
+    pub page_size: PageSize,
+    pub store_name: String,
+    pub store_address: Option<String>,
+    pub store_phone: Option<String>,
+    pub footer: Option<String>,
+    pub template_path: Option<String>, // falls back to templates/invoice.html, then the built-in layout
+}
+
+fn export_dirs(window: &Window) -> (PathBuf, PathBuf) {
+    let config = window.app_handle().config();
+    let base = app_data_dir(&config).unwrap_or_else(|| PathBuf::from("."));
+    (base.join("invoices"), base.join("templates"))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn load_template(options: &InvoiceExportOptions, template_dir: &Path) -> Result<String, String> {
+    if let Some(path) = &options.template_path {
+        return std::fs::read_to_string(path).map_err(|e| format!("Failed to read template {}: {}", path, e));
+    }
+    let default_path = template_dir.join("invoice.html");
+    if default_path.exists() {
+        return std::fs::read_to_string(&default_path)
+            .map_err(|e| format!("Failed to read template {}: {}", default_path.display(), e));
+    }
+    Ok(DEFAULT_TEMPLATE.to_string())
+}
+
+/// VAT per rate; with tax-inclusive prices the amounts are informational.
+fn tax_rows(data: &ReceiptData) -> Vec<(String, f64)> {
+    let label = if data.prices_include_tax { "Trong đó thuế GTGT" } else { "Thuế GTGT" };
+    data.taxes
+        .iter()
+        .filter(|t| t.tax > 0.0)
+        .map(|t| (format!("{} {}%", label, t.rate), t.tax))
+        .collect()
+}
+
+/// Fills `{{placeholder}}` fields of an HTML template with the sale.
+pub fn render_html(data: &ReceiptData, options: &InvoiceExportOptions, template: &str) -> String {
+    let mut items = String::new();
+    for (i, line) in data.lines.iter().enumerate() {
+        let _ = writeln!(
+            items,
+            "    <tr><td>{}</td><td>{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>",
+            i + 1,
+            escape_html(&line.name),
+            escape_html(&line.unit),
+            format_quantity(line.quantity),
+            format_money(line.price),
+            format_money(line.discount),
+            format_money(line.total),
+        );
+    }
+    let mut tenders = String::new();
+    for tender in &data.tenders {
+        let _ = writeln!(
+            tenders,
+            "  <tr><td>{}</td><td class=\"num\">{}</td></tr>",
+            escape_html(&tender.method),
+            format_money(tender.amount),
+        );
+    }
+    let mut taxes = String::new();
+    for (label, amount) in tax_rows(data) {
+        let _ = writeln!(taxes, "  <tr><td>{}</td><td class=\"num\">{}</td></tr>", escape_html(&label), format_money(amount));
+    }
+    let page_size = match options.page_size {
+        PageSize::A4 => "A4",
+        PageSize::A5 => "A5",
+    };
+
+    template
+        .replace("{{page_size}}", page_size)
+        .replace("{{store_name}}", &escape_html(&options.store_name))
+        .replace("{{store_address}}", &escape_html(options.store_address.as_deref().unwrap_or("")))
+        .replace("{{store_phone}}", &escape_html(options.store_phone.as_deref().unwrap_or("")))
+        .replace("{{footer}}", &escape_html(options.footer.as_deref().unwrap_or("")))
+        .replace("{{invoice_id}}", &escape_html(&data.invoice_id))
+        .replace("{{date}}", &escape_html(&data.paid_at))
+        .replace("{{cashier}}", &escape_html(&data.cashier))
+        .replace("{{items}}", items.trim_end())
+        .replace("{{subtotal}}", &format_money(data.subtotal))
+        .replace("{{discount}}", &format_money(data.discount))
+        .replace("{{taxes}}", taxes.trim_end())
+        .replace("{{total}}", &format_money(data.total))
+        .replace("{{tenders}}", tenders.trim_end())
+        .replace("{{change}}", &format_money(data.change_due))
+}
+
+// Embedded so the PDF prints Vietnamese names exactly; only the glyphs a
+// document uses are written into it.
+const FONT_REGULAR: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+const FONT_BOLD: &[u8] = include_bytes!("../fonts/DejaVuSans-Bold.ttf");
+
+const PDF_MARGIN: f32 = 40.0; // points
+const PDF_FONT_SIZE: f32 = 10.0;
+const PDF_CELL_GAP: f32 = 8.0;
+
+/// One piece of the filled template as the PDF lays it out.
+#[derive(Debug, Clone, PartialEq)]
+enum Block {
+    Text { text: String, bold: bool, center: bool, size: f32 },
+    Table(Vec<Vec<Cell>>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Cell {
+    text: String,
+    bold: bool,
+    right: bool,
+}
+
+struct Element {
+    name: String,
+    bold: bool,
+    center: bool,
+    right: bool,
+    size: f32,
+}
+
+const BLOCK_TAGS: [&str; 20] = [
+    "html", "body", "div", "p", "h1", "h2", "h3", "h4", "h5", "h6", "table", "thead", "tbody", "tfoot", "tr", "ul", "ol", "li", "section", "footer",
+];
+const SKIPPED_TAGS: [&str; 4] = ["head", "style", "script", "title"];
+const VOID_TAGS: [&str; 6] = ["br", "hr", "meta", "link", "img", "input"];
+
+// ASCII lowercasing keeps byte offsets, so they also index into `tag`.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let at = lower.find(&format!("{}=", name))? + name.len() + 1;
+    let quote = tag[at..].chars().next()?;
+    if quote == '"' || quote == '\'' {
+        let value = &tag[at + 1..];
+        Some(value[..value.find(quote)?].to_lowercase())
+    } else {
+        Some(tag[at..].split_whitespace().next()?.to_lowercase())
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let end = rest.find(';').filter(|&e| e <= 10);
+        let decoded = end.and_then(|e| match &rest[1..e] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            n if n.starts_with("#x") || n.starts_with("#X") => u32::from_str_radix(&n[2..], 16).ok().and_then(char::from_u32),
+            n if n.starts_with('#') => n[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        });
+        match (decoded, end) {
+            (Some(c), Some(e)) => {
+                out.push(c);
+                rest = &rest[e + 1..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn clean_text(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").nfc().collect()
+}
+
+/// Reads the filled HTML template into lines and tables, so the PDF follows
+/// the same template as the HTML export. Headings, `th`, `b` and `strong`
+/// print bold; `text-align: center`, `align="center"` and `<center>` center a
+/// line; cells with class `num` or `text-align: right` are right-aligned.
+/// Everything else in the markup, including CSS, is ignored.
+fn template_blocks(html: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut stack: Vec<Element> = Vec::new();
+    let mut line = String::new();
+    let mut line_bold = false;
+    let mut line_size = PDF_FONT_SIZE;
+    let mut table: Option<Vec<Vec<Cell>>> = None;
+    let mut cell: Option<Cell> = None;
+    let mut skipping: Option<String> = None;
+
+    let flush = |blocks: &mut Vec<Block>, line: &mut String, bold: &mut bool, size: &mut f32, stack: &[Element]| {
+        let text = clean_text(line);
+        if !text.is_empty() {
+            let center = stack.iter().any(|e| e.center);
+            blocks.push(Block::Text { text, bold: *bold, center, size: *size });
+        }
+        line.clear();
+        *bold = false;
+        *size = PDF_FONT_SIZE;
+    };
+
+    let mut pos = 0;
+    while pos < html.len() {
+        let open = match html[pos..].find('<') {
+            Some(i) => pos + i,
+            None => html.len(),
+        };
+        let text = &html[pos..open];
+        if skipping.is_none() && !text.trim().is_empty() {
+            let text = decode_entities(text);
+            if let Some(cell) = cell.as_mut() {
+                cell.text.push_str(&text);
+            } else if table.is_none() {
+                line.push_str(&text);
+                line_bold |= stack.iter().any(|e| e.bold);
+                line_size = stack.iter().map(|e| e.size).fold(line_size, f32::max);
+            }
+        } else if skipping.is_none() {
+            if let Some(cell) = cell.as_mut() {
+                cell.text.push(' ');
+            } else if table.is_none() {
+                line.push(' ');
+            }
+        }
+        if open >= html.len() {
+            break;
+        }
+        if html[open..].starts_with("<!--") {
+            pos = html[open..].find("-->").map(|i| open + i + 3).unwrap_or(html.len());
+            continue;
+        }
+        let close = match html[open..].find('>') {
+            Some(i) => open + i,
+            None => break,
+        };
+        let tag = html[open + 1..close].trim();
+        pos = close + 1;
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+
+        if let Some(skipped) = &skipping {
+            if closing && *skipped == name {
+                skipping = None;
+            }
+            continue;
+        }
+        if SKIPPED_TAGS.contains(&name.as_str()) && !closing {
+            skipping = Some(name);
+            continue;
+        }
+        if VOID_TAGS.contains(&name.as_str()) {
+            if name == "br" {
+                match cell.as_mut() {
+                    Some(cell) => cell.text.push(' '),
+                    None => flush(&mut blocks, &mut line, &mut line_bold, &mut line_size, &stack),
+                }
+            }
+            continue;
+        }
+        let is_block = BLOCK_TAGS.contains(&name.as_str());
+        if is_block && cell.is_none() {
+            flush(&mut blocks, &mut line, &mut line_bold, &mut line_size, &stack);
+        }
+
+        if closing {
+            if let Some(index) = stack.iter().rposition(|e| e.name == name) {
+                stack.truncate(index);
+            }
+            match name.as_str() {
+                "td" | "th" => {
+                    if let (Some(rows), Some(done)) = (table.as_mut(), cell.take()) {
+                        if rows.is_empty() {
+                            rows.push(Vec::new());
+                        }
+                        let text = clean_text(&done.text);
+                        rows.last_mut().unwrap().push(Cell { text, ..done });
+                    }
+                }
+                "table" => {
+                    if let Some(rows) = table.take() {
+                        let rows: Vec<Vec<Cell>> = rows.into_iter().filter(|r| !r.is_empty()).collect();
+                        if !rows.is_empty() {
+                            blocks.push(Block::Table(rows));
+                        }
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        let style = attribute(tag, "style").unwrap_or_default().replace(' ', "");
+        let align = attribute(tag, "align").unwrap_or_default();
+        let class = attribute(tag, "class").unwrap_or_default();
+        let size = match name.as_str() {
+            "h1" => 16.0,
+            "h2" => 13.0,
+            "h3" => 11.0,
+            _ => PDF_FONT_SIZE,
+        };
+        stack.push(Element {
+            bold: matches!(name.as_str(), "b" | "strong" | "th" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6"),
+            center: name == "center" || style.contains("text-align:center") || align == "center",
+            right: class.split_whitespace().any(|c| c == "num") || style.contains("text-align:right") || align == "right",
+            size,
+            name: name.clone(),
+        });
+        match name.as_str() {
+            "table" => table = Some(Vec::new()),
+            "tr" => {
+                if let Some(rows) = table.as_mut() {
+                    rows.push(Vec::new());
+                }
+            }
+            "td" | "th" if table.is_some() => {
+                cell = Some(Cell {
+                    text: String::new(),
+                    bold: stack.iter().any(|e| e.bold),
+                    right: stack.last().is_some_and(|e| e.right),
+                });
+            }
+            "b" | "strong" => {
+                if let Some(cell) = cell.as_mut() {
+                    cell.bold = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    flush(&mut blocks, &mut line, &mut line_bold, &mut line_size, &stack);
+    blocks
+}
+
+struct PdfFonts<'a> {
+    regular: Face<'a>,
+    bold: Face<'a>,
+}
+
+impl PdfFonts<'_> {
+    fn face(&self, bold: bool) -> &Face<'_> {
+        if bold { &self.bold } else { &self.regular }
+    }
+
+    fn width(&self, text: &str, bold: bool, size: f32) -> f32 {
+        let face = self.face(bold);
+        let units: f32 = text
+            .chars()
+            .map(|c| face.glyph_index(c).and_then(|g| face.glyph_hor_advance(g)).unwrap_or(face.units_per_em() / 2) as f32)
+            .sum();
+        units * size / face.units_per_em() as f32
+    }
+
+    /// Breaks text into lines no wider than `width`, splitting words that
+    /// do not fit on a line of their own.
+    fn wrap(&self, text: &str, bold: bool, size: f32, width: f32) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut current = String::new();
+        for word in text.split(' ') {
+            let candidate = if current.is_empty() { word.to_string() } else { format!("{} {}", current, word) };
+            if self.width(&candidate, bold, size) <= width {
+                current = candidate;
+                continue;
+            }
+            if !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+            }
+            for c in word.chars() {
+                current.push(c);
+                if self.width(&current, bold, size) > width && current.chars().count() > 1 {
+                    current.pop();
+                    lines.push(std::mem::take(&mut current));
+                    current.push(c);
+                }
+            }
+        }
+        if !current.is_empty() || lines.is_empty() {
+            lines.push(current);
+        }
+        lines
+    }
+
+    /// Natural widths per column, fitted to `available`: narrow columns keep
+    /// their width, wide ones share the rest; spare room goes to the widest
+    /// left-aligned column so totals line up with the right margin.
+    fn column_widths(&self, rows: &[Vec<Cell>], available: f32) -> Vec<f32> {
+        let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        let mut widths = vec![PDF_CELL_GAP; columns];
+        for row in rows {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(self.width(&cell.text, cell.bold, PDF_FONT_SIZE) + PDF_CELL_GAP);
+            }
+        }
+        let total: f32 = widths.iter().sum();
+        if total > available {
+            let fair = available / columns as f32;
+            let fixed: f32 = widths.iter().filter(|w| **w <= fair).sum();
+            let flexible: f32 = total - fixed;
+            let scale = (available - fixed) / flexible;
+            for width in widths.iter_mut().filter(|w| **w > fair) {
+                *width *= scale;
+            }
+        } else if columns > 0 {
+            let is_left = |i: usize| rows.iter().all(|r| r.get(i).is_none_or(|c| !c.right));
+            let stretch = (0..columns)
+                .filter(|&i| is_left(i))
+                .max_by(|&a, &b| widths[a].partial_cmp(&widths[b]).unwrap_or(std::cmp::Ordering::Equal))
+                .unwrap_or(0);
+            widths[stretch] += available - total;
+        }
+        widths
+    }
+}
+
+fn pt(value: f32) -> Mm {
+    Mm::from(Pt(value))
+}
+
+/// Lays the filled template out on A4/A5 pages with the embedded font.
+pub fn render_pdf(data: &ReceiptData, options: &InvoiceExportOptions, template: &str) -> Result<Vec<u8>, String> {
+    let blocks = template_blocks(&render_html(data, options, template));
+    let fonts = PdfFonts {
+        regular: Face::parse(FONT_REGULAR, 0).map_err(|e| format!("Failed to read font: {}", e))?,
+        bold: Face::parse(FONT_BOLD, 0).map_err(|e| format!("Failed to read font: {}", e))?,
+    };
+    let (width, height) = options.page_size.dimensions();
+    let (width, height) = (width as f32, height as f32);
+    let available = width - 2.0 * PDF_MARGIN;
+
+    let (doc, page, layer) = PdfDocument::new(format!("Hóa đơn {}", data.invoice_id), pt(width), pt(height), "Invoice");
+    let regular = doc.add_external_font(FONT_REGULAR).map_err(|e| format!("Failed to embed font: {}", e))?;
+    let bold = doc.add_external_font(FONT_BOLD).map_err(|e| format!("Failed to embed font: {}", e))?;
+    let font = |is_bold: bool| if is_bold { &bold } else { &regular };
+    let mut current = doc.get_page(page).get_layer(layer);
+    let mut y = height - PDF_MARGIN;
+    let mut ensure_room = |y: &mut f32, needed: f32| {
+        if *y - needed < PDF_MARGIN {
+            let (page, layer) = doc.add_page(pt(width), pt(height), "Invoice");
+            current = doc.get_page(page).get_layer(layer);
+            *y = height - PDF_MARGIN;
+        }
+        current.clone()
+    };
+
+    for block in &blocks {
+        match block {
+            Block::Text { text, bold: is_bold, center, size } => {
+                let leading = size * 1.4;
+                for line in fonts.wrap(text, *is_bold, *size, available) {
+                    let layer = ensure_room(&mut y, leading);
+                    y -= leading;
+                    let x = if *center { PDF_MARGIN + (available - fonts.width(&line, *is_bold, *size)) / 2.0 } else { PDF_MARGIN };
+                    layer.use_text(line, *size, pt(x), pt(y + leading - *size), font(*is_bold));
+                }
+            }
+            Block::Table(rows) => {
+                let widths = fonts.column_widths(rows, available);
+                let leading = PDF_FONT_SIZE * 1.4;
+                for row in rows {
+                    let wrapped: Vec<Vec<String>> = row
+                        .iter()
+                        .zip(&widths)
+                        .map(|(cell, w)| fonts.wrap(&cell.text, cell.bold, PDF_FONT_SIZE, w - PDF_CELL_GAP))
+                        .collect();
+                    let row_height = wrapped.iter().map(|l| l.len()).max().unwrap_or(1) as f32 * leading;
+                    let layer = ensure_room(&mut y, row_height);
+                    let mut x = PDF_MARGIN;
+                    for ((cell, lines), w) in row.iter().zip(&wrapped).zip(&widths) {
+                        for (i, line) in lines.iter().enumerate() {
+                            let line_x = if cell.right { x + w - fonts.width(line, cell.bold, PDF_FONT_SIZE) } else { x };
+                            let line_y = y - (i as f32 + 1.0) * leading + (leading - PDF_FONT_SIZE);
+                            layer.use_text(line.clone(), PDF_FONT_SIZE, pt(line_x), pt(line_y), font(cell.bold));
+                        }
+                        x += w;
+                    }
+                    y -= row_height;
+                }
+                y -= leading / 2.0;
+            }
+        }
+    }
+    doc.save_to_bytes().map_err(|e| format!("Failed to write PDF: {}", e))
+}
+
+/// Renders a paid invoice to `invoices/<invoice_id>.<html|pdf>` under the app
+/// data directory and returns the written path.
+#[command]
+pub fn export_invoice(window: Window, invoice_id: String, format: ExportFormat, options: InvoiceExportOptions) -> Result<String, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let data = load_receipt_data(&conn, &invoice_id)?;
+
+    let (out_dir, template_dir) = export_dirs(&window);
+    std::fs::create_dir_all(&out_dir).map_err(|e| format!("Failed to create export directory: {}", e))?;
+    let file_stem: String = invoice_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect();
+
+    let template = load_template(&options, &template_dir)?;
+    let (path, bytes) = match format {
+        ExportFormat::Html => (out_dir.join(format!("{}.html", file_stem)), render_html(&data, &options, &template).into_bytes()),
+        ExportFormat::Pdf => (out_dir.join(format!("{}.pdf", file_stem)), render_pdf(&data, &options, &template)?),
+    };
+    std::fs::write(&path, bytes).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+commands![
+    export_invoice,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::receipt::ReceiptLine;
+    use crate::sales::Tender;
+
+    fn text(text: &str, bold: bool, center: bool, size: f32) -> Block {
+        Block::Text { text: text.to_string(), bold, center, size }
+    }
+
+    fn cell(text: &str, bold: bool, right: bool) -> Cell {
+        Cell { text: text.to_string(), bold, right }
+    }
+
+    #[test]
+    fn reads_lines_and_tables_from_the_template() {
+        let html = r#"<html><head><title>x</title><style>.num { text-align: right; }</style></head>
+<body><div style="text-align: center"><h1>Cà phê &amp; Trà</h1><div>Hà Nội</div></div>
+<!-- comment -->
+<table><tr><th>Sản phẩm</th><th class="num">SL</th></tr>
+<tr><td>Cà phê sữa</td><td class="num">2</td></tr>
+<tr><td><strong>Tổng</strong></td><td class="num">50.000</td></tr></table>
+<p>Cảm ơn<br>quý khách</p></body></html>"#;
+        assert_eq!(
+            template_blocks(html),
+            vec![
+                text("Cà phê & Trà", true, true, 16.0),
+                text("Hà Nội", false, true, PDF_FONT_SIZE),
+                Block::Table(vec![
+                    vec![cell("Sản phẩm", true, false), cell("SL", true, true)],
+                    vec![cell("Cà phê sữa", false, false), cell("2", false, true)],
+                    vec![cell("Tổng", true, false), cell("50.000", false, true)],
+                ]),
+                text("Cảm ơn", false, false, PDF_FONT_SIZE),
+                text("quý khách", false, false, PDF_FONT_SIZE),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_attributes_after_non_ascii_text() {
+        // 'İ' lowercases to three bytes, which used to shift the offsets.
+        let tag = r#"td title="İİİİ" CLASS="Num" align=Right"#;
+        assert_eq!(attribute(tag, "class").as_deref(), Some("num"));
+        assert_eq!(attribute(tag, "align").as_deref(), Some("right"));
+        assert_eq!(attribute(tag, "style"), None);
+    }
+
+    #[test]
+    fn composes_decomposed_vietnamese() {
+        // "phê" typed with a combining circumflex and acute accent.
+        let blocks = template_blocks("<p>Cà phe\u{0302}\u{0301} &#272;&#x1EA1;i</p>");
+        assert_eq!(blocks, vec![text("Cà phế Đại", false, false, PDF_FONT_SIZE)]);
+    }
+
+    #[test]
+    fn wraps_and_fits_columns_to_the_page() {
+        let fonts = PdfFonts { regular: Face::parse(FONT_REGULAR, 0).unwrap(), bold: Face::parse(FONT_BOLD, 0).unwrap() };
+        assert!(fonts.width("Đ", false, 10.0) > 0.0);
+        let lines = fonts.wrap("Cà phê sữa đá thêm đường", false, 10.0, 60.0);
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|l| fonts.width(l, false, 10.0) <= 60.0));
+
+        let rows = vec![vec![cell("Cà phê sữa", false, false), cell("25.000", false, true)]];
+        let widths = fonts.column_widths(&rows, 300.0);
+        assert!((widths.iter().sum::<f32>() - 300.0).abs() < 0.01);
+        assert!(widths[0] > widths[1]);
+    }
+
+    #[test]
+    fn embeds_the_font_in_the_pdf() {
+        let data = ReceiptData {
+            invoice_id: "S1_M1_20250101_001".to_string(),
+            cashier: "M1".to_string(),
+            paid_at: "2025-01-01 10:00:00".to_string(),
+            lines: vec![ReceiptLine {
+                name: "Cà phê sữa".to_string(),
+                unit: "ly".to_string(),
+                quantity: 2.0,
+                price: 25000.0,
+                discount: 0.0,
+                promotions: Vec::new(),
+                total: 50000.0,
+            }],
+            subtotal: 50000.0,
+            discount: 0.0,
+            taxes: Vec::new(),
+            prices_include_tax: true,
+            total: 50000.0,
+            tenders: vec![Tender { method: "cash".to_string(), amount: 50000.0 }],
+            change_due: 0.0,
+        };
+        let options = InvoiceExportOptions {
+            page_size: PageSize::A5,
+            store_name: "Cửa hàng Tạp hóa".to_string(),
+            store_address: None,
+            store_phone: None,
+            footer: Some("Cảm ơn quý khách".to_string()),
+            template_path: None,
+        };
+        let pdf = render_pdf(&data, &options, DEFAULT_TEMPLATE).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+        let raw = String::from_utf8_lossy(&pdf);
+        assert!(raw.contains("FontFile2"));
+        assert!(!raw.contains("Courier"));
+        // A subset, not the whole 700 KB face.
+        assert!(pdf.len() < FONT_REGULAR.len() / 2);
+    }
+}
@@ -0,0 +1,391 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, Result};
+use serde_json::{json, Value};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use tauri::{command, Window};
+
+use crate::cart::get_db_path;
+use crate::catalog::{insert_product, product_with_barcode, NewProduct};
+use crate::barcode::normalize;
+use crate::returns::load_return;
+use crate::sales::load_invoice;
+use crate::settings::{get_setting, set_setting, Settings};
+
+// Rows per exported file; the next export continues where this one stopped.
+const BATCH_SIZE: i64 = 5000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JournalStatus {
+    pub last_seq: i64,
+    pub acked_seq: i64,
+    pub pending: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JournalBatch {
+    pub path: String,
+    pub first_seq: i64,
+    pub last_seq: i64,
+    pub count: i64,
+}
+
+/// One line of a head-office catalog file. Only the fields present are
+/// changed; unknown barcodes become new products when a name and price are given.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CatalogUpdate {
+    pub barcode: String,
+    pub store_id: Option<String>, // limits the line to one store
+    pub item_name: Option<String>,
+    pub category: Option<String>,
+    pub unit: Option<String>,
+    pub retail_price: Option<f64>,
+    pub bulk_price: Option<f64>,
+    pub cost: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CatalogImport {
+    pub updated: i64,
+    pub created: i64,
+    pub skipped: Vec<String>, // "line N: reason"
+}
+
+/// Sales, returns, price changes and stock movements are journaled by
+/// triggers, in the same transaction that commits them. Rows written by
+/// LAN sync belong to the till that made them and are left out, as are
+/// price changes that came from head office in a catalog file.
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS outbound_journal (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            event VARCHAR(20) NOT NULL,
+            entity_key VARCHAR(100) NOT NULL,
+            data TEXT,
+            created_at DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%S', 'now'))
+        )",
+        params![],
+    )?;
+    // Holds a row while a catalog file is applied so the price triggers skip it.
+    conn.execute("CREATE TABLE IF NOT EXISTS journal_applying (flag INTEGER)", params![])?;
+    conn.execute("DELETE FROM journal_applying", params![])?;
+    conn.execute_batch(
+        "DROP TRIGGER IF EXISTS journal_price_insert;
+         DROP TRIGGER IF EXISTS journal_price_update;
+         CREATE TRIGGER IF NOT EXISTS journal_sale AFTER UPDATE OF status ON invoices
+         WHEN NEW.status = 'completed' AND OLD.status <> 'completed' AND NEW.sync_origin IS NULL
+         BEGIN
+            INSERT INTO outbound_journal (event, entity_key) VALUES ('sale', NEW.invoice_id);
+         END;
+         CREATE TRIGGER IF NOT EXISTS journal_return AFTER INSERT ON returns
+         WHEN NEW.sync_origin IS NULL
+         BEGIN
+            INSERT INTO outbound_journal (event, entity_key) VALUES ('return', NEW.return_id);
+         END;
+         CREATE TRIGGER journal_price_insert AFTER INSERT ON products
+         WHEN NOT EXISTS (SELECT 1 FROM sync_applying) AND NOT EXISTS (SELECT 1 FROM journal_applying)
+         BEGIN
+            INSERT INTO outbound_journal (event, entity_key, data) VALUES ('price_change', NEW.rowid, json_object(
+                'barcode', NEW.Barcode, 'item_name', NEW.Item_name,
+                'old', NULL,
+                'new', json_object('retail_price', NEW.Retail_price, 'bulk_price', NEW.Bulk_price, 'cost', NEW.Cost)));
+         END;
+         CREATE TRIGGER journal_price_update AFTER UPDATE OF Retail_price, Bulk_price, Cost ON products
+         WHEN (OLD.Retail_price IS NOT NEW.Retail_price OR OLD.Bulk_price IS NOT NEW.Bulk_price OR OLD.Cost IS NOT NEW.Cost)
+            AND NOT EXISTS (SELECT 1 FROM sync_applying) AND NOT EXISTS (SELECT 1 FROM journal_applying)
+         BEGIN
+            INSERT INTO outbound_journal (event, entity_key, data) VALUES ('price_change', NEW.rowid, json_object(
+                'barcode', NEW.Barcode, 'item_name', NEW.Item_name,
+                'old', json_object('retail_price', OLD.Retail_price, 'bulk_price', OLD.Bulk_price, 'cost', OLD.Cost),
+                'new', json_object('retail_price', NEW.Retail_price, 'bulk_price', NEW.Bulk_price, 'cost', NEW.Cost)));
+         END;
+         CREATE TRIGGER IF NOT EXISTS journal_stock_movement AFTER INSERT ON stock_movements
+         BEGIN
+            INSERT INTO outbound_journal (event, entity_key, data) VALUES ('stock_movement', NEW.movement_id, json_object(
+                'product_id', NEW.product_id, 'purchasing_type', NEW.purchasing_type, 'quantity', NEW.quantity,
+                'reason', NEW.reason, 'reference', NEW.reference));
+         END;",
+    )?;
+    Ok(())
+}
+
+fn load_status(conn: &Connection) -> Result<JournalStatus, String> {
+    let last_seq: i64 = conn.query_row("SELECT COALESCE(MAX(seq), 0) FROM outbound_journal", params![], |row| row.get(0))
+        .map_err(|e| format!("Failed to read journal: {}", e))?;
+    let acked_seq: i64 = get_setting(conn, "journal_acked_seq")?.and_then(|v| v.parse().ok()).unwrap_or(0);
+    Ok(JournalStatus { last_seq, acked_seq, pending: last_seq - acked_seq })
+}
+
+// Sales and returns never change once committed, so their full record is
+// read when the batch is written rather than copied into every journal row.
+fn event_data(conn: &Connection, event: &str, key: &str, data: Option<String>) -> Result<Value, String> {
+    match event {
+        "sale" => serde_json::to_value(load_invoice(conn, key)?).map_err(|e| format!("Failed to encode invoice: {}", e)),
+        "return" => serde_json::to_value(load_return(conn, key)?).map_err(|e| format!("Failed to encode return: {}", e)),
+        _ => Ok(data.and_then(|d| serde_json::from_str(&d).ok()).unwrap_or(Value::Null)),
+    }
+}
+
+/// Writes the next unacknowledged entries to a gzip-compressed JSON Lines
+/// file. Until `ack_journal` is called the same range is exported again, so
+/// a lost upload is simply retried.
+#[command]
+pub fn export_journal(window: Window, dir: Option<String>) -> Result<Option<JournalBatch>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let dir = dir.map(PathBuf::from).unwrap_or_else(|| db_path.with_file_name("journal"));
+    write_batch(&conn, &dir)
+}
+
+fn write_batch(conn: &Connection, dir: &Path) -> Result<Option<JournalBatch>, String> {
+    let store_id = Settings::load(conn)?.store_id;
+    let acked_seq = load_status(conn)?.acked_seq;
+
+    let mut stmt = conn.prepare(
+        "SELECT seq, event, entity_key, data, created_at FROM outbound_journal WHERE seq > ?1 ORDER BY seq LIMIT ?2",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let rows: Vec<(i64, String, String, Option<String>, String)> = stmt.query_map(params![acked_seq, BATCH_SIZE], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    let (first_seq, last_seq) = match (rows.first(), rows.last()) {
+        (Some(first), Some(last)) => (first.0, last.0),
+        _ => return Ok(None),
+    };
+
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let path = dir.join(format!("{}_{:010}_{:010}.jsonl.gz", store_id, first_seq, last_seq));
+    let file = std::fs::File::create(&path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    for (seq, event, key, data, created_at) in &rows {
+        let line = json!({
+            "seq": seq,
+            "store_id": store_id,
+            "event": event,
+            "key": key,
+            "created_at": created_at,
+            "data": event_data(conn, event, key, data.clone())?,
+        });
+        writeln!(encoder, "{}", line).map_err(|e| format!("Failed to write journal: {}", e))?;
+    }
+    encoder.finish().map_err(|e| format!("Failed to write journal: {}", e))?;
+    set_setting(conn, "journal_exported_seq", &last_seq.to_string())?;
+
+    Ok(Some(JournalBatch {
+        path: path.to_string_lossy().to_string(),
+        first_seq,
+        last_seq,
+        count: rows.len() as i64,
+    }))
+}
+
+/// Marks the last exported batch as received by head office. `seq` must be
+/// that batch's `last_seq`, so entries never written to a file stay pending.
+#[command]
+pub fn ack_journal(window: Window, seq: i64) -> Result<JournalStatus, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    acknowledge(&conn, seq)
+}
+
+fn acknowledge(conn: &Connection, seq: i64) -> Result<JournalStatus, String> {
+    let status = load_status(conn)?;
+    let exported: i64 = get_setting(conn, "journal_exported_seq")?.and_then(|v| v.parse().ok()).unwrap_or(0);
+    if exported <= status.acked_seq {
+        return Err("No exported batch is waiting for acknowledgement".to_string());
+    }
+    if seq != exported {
+        return Err(format!("Sequence {} does not end the last exported batch (up to {})", seq, exported));
+    }
+    set_setting(conn, "journal_acked_seq", &seq.to_string())?;
+    load_status(conn)
+}
+
+#[command]
+pub fn journal_status(window: Window) -> Result<JournalStatus, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    load_status(&conn)
+}
+
+fn apply_update(conn: &Connection, update: &CatalogUpdate, summary: &mut CatalogImport) -> Result<(), String> {
+    if [update.retail_price, update.bulk_price, update.cost].iter().flatten().any(|p| *p < 0.0) {
+        return Err("Prices cannot be negative".to_string());
+    }
+    let code = normalize(&update.barcode)?.code;
+    match product_with_barcode(conn, &code)? {
+        Some((product_id, _)) => {
+            conn.execute(
+                "UPDATE products SET
+                    Item_name = COALESCE(?1, Item_name), Category = COALESCE(?2, Category), Unit = COALESCE(?3, Unit),
+                    Retail_price = COALESCE(?4, Retail_price), Bulk_price = COALESCE(?5, Bulk_price), Cost = COALESCE(?6, Cost)
+                 WHERE rowid = ?7",
+                params![update.item_name, update.category, update.unit, update.retail_price, update.bulk_price, update.cost, product_id],
+            )
+            .map_err(|e| format!("Failed to update product: {}", e))?;
+            summary.updated += 1;
+        }
+        None => {
+            let (item_name, retail_price) = match (&update.item_name, update.retail_price) {
+                (Some(name), Some(price)) => (name.clone(), price),
+                _ => return Err(format!("Unknown barcode {} (new products need a name and retail price)", code)),
+            };
+            insert_product(conn, &NewProduct {
+                item_name,
+                barcodes: vec![code],
+                category: update.category.clone(),
+                unit: update.unit.clone(),
+                retail_price,
+                cost: update.cost,
+                bulk_unit: None,
+                bulk_code: None,
+                bulk_single_conversion: None,
+                bulk_price: None,
+            })?;
+            summary.created += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Applies a price/catalog file from head office: JSON Lines, optionally
+/// gzip-compressed. Bad lines are skipped and reported; a file that cannot
+/// be read changes nothing.
+#[command]
+pub fn import_catalog_updates(window: Window, path: String) -> Result<CatalogImport, String> {
+    let bytes = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let mut text = String::new();
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(&bytes[..]).read_to_string(&mut text).map_err(|e| format!("Failed to decompress {}: {}", path, e))?;
+    } else {
+        text = String::from_utf8(bytes).map_err(|e| format!("{} is not UTF-8: {}", path, e))?;
+    }
+
+    let db_path = get_db_path(&window);
+    let mut conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    apply_updates(&mut conn, &text)
+}
+
+fn apply_updates(conn: &mut Connection, text: &str) -> Result<CatalogImport, String> {
+    let store_id = Settings::load(conn)?.store_id;
+    let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+    // Head office already knows these prices; they are not journaled back.
+    tx.execute("INSERT INTO journal_applying (flag) VALUES (1)", params![])
+        .map_err(|e| format!("Failed to start applying: {}", e))?;
+    let mut summary = CatalogImport::default();
+    for (index, line) in BufReader::new(text.as_bytes()).lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read catalog file: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let update: CatalogUpdate = match serde_json::from_str(&line) {
+            Ok(update) => update,
+            Err(e) => {
+                summary.skipped.push(format!("line {}: {}", index + 1, e));
+                continue;
+            }
+        };
+        if update.store_id.as_ref().is_some_and(|s| *s != store_id) {
+            continue;
+        }
+        if let Err(e) = apply_update(&tx, &update, &mut summary) {
+            summary.skipped.push(format!("line {}: {}", index + 1, e));
+        }
+    }
+    tx.execute("DELETE FROM journal_applying", params![]).map_err(|e| format!("Failed to finish applying: {}", e))?;
+    tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+    Ok(summary)
+}
+
+commands![
+    export_journal,
+    ack_journal,
+    journal_status,
+    import_catalog_updates,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE products (Barcode VARCHAR, Item_name VARCHAR, Category VARCHAR, Unit VARCHAR, Bulk_unit VARCHAR,
+             Bulk_code VARCHAR, Bulk_single_conversion FLOAT, Retail_price FLOAT, Bulk_price FLOAT, Cost FLOAT)",
+            params![],
+        )
+        .unwrap();
+        conn.execute("INSERT INTO products (Barcode, Item_name, Retail_price) VALUES ('8934588012112', 'Mì', 4500)", params![]).unwrap();
+        crate::db::initialize_tables(&conn).unwrap();
+        conn
+    }
+
+    fn price(conn: &Connection) -> f64 {
+        conn.query_row("SELECT Retail_price FROM products WHERE Barcode = '8934588012112'", params![], |row| row.get(0)).unwrap()
+    }
+
+    fn set_price(conn: &Connection, price: f64) {
+        conn.execute("UPDATE products SET Retail_price = ?1 WHERE Barcode = '8934588012112'", params![price]).unwrap();
+    }
+
+    #[test]
+    fn exports_until_the_last_batch_is_acknowledged() {
+        let conn = store();
+        let dir = std::env::temp_dir().join(format!("anpos-journal-{}", uuid::Uuid::new_v4().simple()));
+        assert!(acknowledge(&conn, 0).is_err());
+        set_price(&conn, 5000.0);
+        set_price(&conn, 5500.0);
+
+        let batch = write_batch(&conn, &dir).unwrap().unwrap();
+        assert_eq!((batch.first_seq, batch.last_seq, batch.count), (1, 2, 2));
+        let mut text = String::new();
+        GzDecoder::new(std::fs::File::open(&batch.path).unwrap()).read_to_string(&mut text).unwrap();
+        let lines: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["event"], "price_change");
+        assert_eq!(lines[1]["data"]["old"]["retail_price"], 5000.0);
+        assert_eq!(lines[1]["data"]["new"]["retail_price"], 5500.0);
+
+        // A price changed after the export is not acknowledged with it.
+        set_price(&conn, 6000.0);
+        assert!(acknowledge(&conn, 3).is_err());
+        assert!(acknowledge(&conn, 1).is_err());
+        let status = acknowledge(&conn, batch.last_seq).unwrap();
+        assert_eq!((status.acked_seq, status.pending), (2, 1));
+        assert!(acknowledge(&conn, batch.last_seq).is_err());
+
+        let next = write_batch(&conn, &dir).unwrap().unwrap();
+        assert_eq!((next.first_seq, next.last_seq), (3, 3));
+        acknowledge(&conn, 3).unwrap();
+        assert!(write_batch(&conn, &dir).unwrap().is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn catalog_updates_are_not_journaled_back() {
+        let mut conn = store();
+        let text = [
+            r#"{"barcode": "8934588012112", "retail_price": 4800}"#,
+            r#"{"barcode": "96385074", "item_name": "Bánh", "retail_price": 12000}"#,
+            r#"{"barcode": "8934588012112", "store_id": "other", "retail_price": 1}"#,
+            r#"{"barcode": "4006381333931"}"#,
+            "not json",
+        ]
+        .join("\n");
+        let summary = apply_updates(&mut conn, &text).unwrap();
+        assert_eq!((summary.updated, summary.created, summary.skipped.len()), (1, 1, 2));
+        assert_eq!(price(&conn), 4800.0);
+        assert_eq!(load_status(&conn).unwrap().last_seq, 0);
+
+        // Edits made at the till are journaled again afterwards.
+        set_price(&conn, 5000.0);
+        assert_eq!(load_status(&conn).unwrap().last_seq, 1);
+    }
+}
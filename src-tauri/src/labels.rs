@@ -0,0 +1,223 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::{command, State, Window};
+
+use crate::barcode::{normalize, Barcode, Symbology};
+use crate::cart::get_db_path;
+use crate::printer::{PrintJob, PrintQueue};
+use crate::receipt::{format_money, Align, EscPosWriter, PaperWidth, DEFAULT_CODE_PAGE};
+
+// The layout needs room for two lines of name, the barcode and the price.
+const MIN_WIDTH_MM: u32 = 20;
+const MIN_HEIGHT_MM: u32 = 15;
+const MAX_SIZE_MM: u32 = 200;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelFormat {
+    Zpl,    // Zebra and compatible label printers
+    Escpos, // receipt printer, one cut label each
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LabelOptions {
+    pub format: LabelFormat,
+    pub copies: Option<u32>,
+    pub width_mm: Option<u32>,  // ZPL label size, default 50 x 30 mm
+    pub height_mm: Option<u32>,
+    pub dots_per_mm: Option<u32>, // 8 for 203 dpi, 12 for 300 dpi
+    pub paper_width: Option<PaperWidth>, // ESC/POS paper, default 58mm
+    pub code_page: Option<u8>,
+    pub ascii_only: bool,
+}
+
+impl LabelOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        let (width, height) = (self.width_mm.unwrap_or(50), self.height_mm.unwrap_or(30));
+        if !(MIN_WIDTH_MM..=MAX_SIZE_MM).contains(&width) || !(MIN_HEIGHT_MM..=MAX_SIZE_MM).contains(&height) {
+            return Err(format!(
+                "Labels must be {} to {} mm wide and {} to {} mm high",
+                MIN_WIDTH_MM, MAX_SIZE_MM, MIN_HEIGHT_MM, MAX_SIZE_MM
+            ));
+        }
+        if !matches!(self.dots_per_mm.unwrap_or(8), 8 | 12) {
+            return Err("Label printer resolution must be 8 (203 dpi) or 12 (300 dpi) dots per mm".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Label {
+    pub product_id: i64,
+    pub name: String,
+    pub barcode: Barcode,
+    pub price: f64,
+    pub unit: Option<String>,
+}
+
+/// Reads the shelf label for each product: its first unit barcode and retail price.
+pub fn load_labels(conn: &Connection, product_ids: &[i64]) -> Result<Vec<Label>, String> {
+    let mut labels = Vec::new();
+    for &product_id in product_ids {
+        let found: Option<(String, Option<String>, Option<f64>, Option<String>)> = conn
+            .query_row(
+                "SELECT Item_name, Barcode, Retail_price, Unit FROM products WHERE rowid = ?1",
+                params![product_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to load product: {}", e))?;
+        let (name, barcode, price, unit) = found.ok_or_else(|| format!("Product {} not found", product_id))?;
+        let code = barcode
+            .as_deref()
+            .and_then(|b| b.split(',').map(str::trim).find(|c| !c.is_empty() && *c != "nan"))
+            .ok_or_else(|| format!("{} has no barcode; assign one before printing labels", name))?;
+        labels.push(Label {
+            product_id,
+            barcode: normalize(code).map_err(|e| format!("{}: {}", name, e))?,
+            price: price.unwrap_or(0.0),
+            unit,
+            name,
+        });
+    }
+    Ok(labels)
+}
+
+fn price_text(label: &Label) -> String {
+    match &label.unit {
+        Some(unit) if !unit.is_empty() && unit != "nan" => format!("{}đ/{}", format_money(label.price), unit),
+        _ => format!("{}đ", format_money(label.price)),
+    }
+}
+
+// ^ and ~ start ZPL commands, so they cannot appear in field data.
+fn zpl_text(text: &str) -> String {
+    text.replace(['^', '~'], " ")
+}
+
+/// Expects `options` to have passed `validate`.
+pub fn render_zpl(labels: &[Label], options: &LabelOptions) -> Vec<u8> {
+    let dots = options.dots_per_mm.unwrap_or(8);
+    let width = options.width_mm.unwrap_or(50) * dots;
+    let height = options.height_mm.unwrap_or(30) * dots;
+    let copies = options.copies.unwrap_or(1).max(1);
+    let margin = 2 * dots;
+    let mut out = String::new();
+    for label in labels {
+        let barcode = match label.barcode.symbology {
+            // the printer appends the check digit itself
+            Symbology::Ean8 => format!("^B8N,{},Y,N^FD{}^FS", 8 * dots, &label.barcode.code[..7]),
+            Symbology::Code128 => format!("^BCN,{},Y,N^FD{}^FS", 8 * dots, zpl_text(&label.barcode.code)),
+            _ => format!("^BEN,{},Y,N^FD{}^FS", 8 * dots, &label.barcode.code[..12]),
+        };
+        out.push_str("^XA^CI28");
+        out.push_str(&format!("^PW{}^LL{}", width, height));
+        out.push_str(&format!("^FO{m},{m}^A0N,{h},{h}^FB{w},2,0,L^FD{t}^FS", m = margin, h = 3 * dots, w = width - 2 * margin, t = zpl_text(&label.name)));
+        out.push_str(&format!("^FO{},{}^BY2{}", margin, 9 * dots, barcode));
+        out.push_str(&format!("^FO{m},{y}^A0N,{h},{h}^FB{w},1,0,R^FD{t}^FS", m = margin, y = height - 5 * dots, h = 4 * dots, w = width - 2 * margin, t = zpl_text(&price_text(label))));
+        out.push_str(&format!("^PQ{}^XZ\n", copies));
+    }
+    out.into_bytes()
+}
+
+pub fn render_escpos_labels(labels: &[Label], options: &LabelOptions) -> Vec<u8> {
+    let mut w = EscPosWriter::new(
+        options.paper_width.unwrap_or(PaperWidth::Mm58).columns(),
+        options.code_page.unwrap_or(DEFAULT_CODE_PAGE),
+        options.ascii_only,
+    );
+    for label in labels {
+        for _ in 0..options.copies.unwrap_or(1).max(1) {
+            w.align(Align::Center);
+            w.bold(true).line(&label.name).bold(false);
+            w.barcode(&label.barcode);
+            w.double_size(true).line(&price_text(label)).double_size(false);
+            w.feed(3).cut();
+        }
+    }
+    w.into_bytes()
+}
+
+pub fn render_labels_for(conn: &Connection, product_ids: &[i64], options: &LabelOptions) -> Result<Vec<u8>, String> {
+    if product_ids.is_empty() {
+        return Err("No products selected for labels".to_string());
+    }
+    options.validate()?;
+    let labels = load_labels(conn, product_ids)?;
+    Ok(match options.format {
+        LabelFormat::Zpl => render_zpl(&labels, options),
+        LabelFormat::Escpos => render_escpos_labels(&labels, options),
+    })
+}
+
+#[command]
+pub fn render_labels(window: Window, product_ids: Vec<i64>, options: LabelOptions) -> Result<Vec<u8>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    render_labels_for(&conn, &product_ids, &options)
+}
+
+/// Renders shelf labels and queues them on the configured printer.
+#[command]
+pub fn print_labels(window: Window, queue: State<'_, PrintQueue>, product_ids: Vec<i64>, options: LabelOptions) -> Result<PrintJob, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    queue.enqueue(None, render_labels_for(&conn, &product_ids, &options)?)
+}
+
+commands![
+    render_labels,
+    print_labels,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(width_mm: u32, height_mm: u32, dots_per_mm: u32) -> LabelOptions {
+        LabelOptions {
+            format: LabelFormat::Zpl,
+            copies: Some(2),
+            width_mm: Some(width_mm),
+            height_mm: Some(height_mm),
+            dots_per_mm: Some(dots_per_mm),
+            paper_width: None,
+            code_page: None,
+            ascii_only: false,
+        }
+    }
+
+    fn label(code: &str, unit: Option<&str>) -> Label {
+        Label {
+            product_id: 1,
+            name: "Mì Hảo Hảo ^tôm~".to_string(),
+            barcode: normalize(code).unwrap(),
+            price: 4500.0,
+            unit: unit.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn renders_one_zpl_label_per_product() {
+        let zpl = String::from_utf8(render_zpl(&[label("8934588012112", Some("gói")), label("96385074", None)], &options(50, 30, 8))).unwrap();
+        let labels: Vec<&str> = zpl.lines().collect();
+        assert_eq!(labels.len(), 2);
+        assert!(labels[0].starts_with("^XA^CI28^PW400^LL240"));
+        // The printer adds the check digit, and field data carries no ZPL commands.
+        assert!(labels[0].contains("^BEN,64,Y,N^FD893458801211^FS"));
+        assert!(labels[0].contains("^FDMì Hảo Hảo  tôm ^FS"));
+        assert!(labels[0].contains("^FD4.500đ/gói^FS"));
+        assert!(labels[0].ends_with("^PQ2^XZ"));
+        assert!(labels[1].contains("^B8N,64,Y,N^FD9638507^FS"));
+    }
+
+    #[test]
+    fn rejects_label_sizes_the_layout_does_not_fit() {
+        assert!(options(20, 15, 12).validate().is_ok());
+        assert!(options(19, 30, 8).validate().is_err());
+        assert!(options(50, 14, 8).validate().is_err());
+        assert!(options(50, 30, 10).validate().is_err());
+        assert!(options(5000, 30, 8).validate().is_err());
+    }
+}
@@ -0,0 +1,285 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::Connection;
+use tauri::{command, AppHandle, Manager, State, Window};
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::Utc;
+
+use crate::cart::get_db_path;
+use crate::settings::{store_settings, Settings};
+use crate::receipt::{load_receipt_data, render_escpos, ReceiptOptions};
+
+const MAX_ATTEMPTS: u32 = 3;
+const MAX_KEPT_JOBS: usize = 50;
+const TCP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A destination that accepts raw printer bytes (ESC/POS, ZPL, ...).
+pub trait PrinterBackend: Send {
+    fn describe(&self) -> String;
+    fn send(&mut self, data: &[u8]) -> Result<(), String>;
+}
+
+/// A printer exposed as a character device, e.g. `/dev/usb/lp0`.
+pub struct DeviceFileBackend {
+    pub path: PathBuf,
+}
+
+impl PrinterBackend for DeviceFileBackend {
+    fn describe(&self) -> String {
+        format!("device {}", self.path.display())
+    }
+
+    fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut device = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open {}: {}", self.path.display(), e))?;
+        device.write_all(data).map_err(|e| format!("Failed to write to {}: {}", self.path.display(), e))?;
+        device.flush().map_err(|e| format!("Failed to flush {}: {}", self.path.display(), e))
+    }
+}
+
+/// A network printer speaking raw JetDirect, usually on port 9100.
+pub struct TcpBackend {
+    pub host: String,
+    pub port: u16,
+}
+
+impl PrinterBackend for TcpBackend {
+    fn describe(&self) -> String {
+        format!("tcp {}:{}", self.host, self.port)
+    }
+
+    fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|e| format!("Failed to resolve {}: {}", self.host, e))?
+            .next()
+            .ok_or_else(|| format!("No address for {}", self.host))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TCP_TIMEOUT)
+            .map_err(|e| format!("Failed to connect to {}: {}", addr, e))?;
+        stream.set_write_timeout(Some(TCP_TIMEOUT)).map_err(|e| e.to_string())?;
+        stream.write_all(data).map_err(|e| format!("Failed to send to {}: {}", addr, e))?;
+        stream.flush().map_err(|e| format!("Failed to send to {}: {}", addr, e))
+    }
+}
+
+/// Writes every job to its own `.bin` file; used for testing without hardware.
+pub struct SpoolBackend {
+    pub dir: PathBuf,
+}
+
+impl PrinterBackend for SpoolBackend {
+    fn describe(&self) -> String {
+        format!("spool {}", self.dir.display())
+    }
+
+    fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create spool directory: {}", e))?;
+        let path = self.dir.join(format!("{}.bin", Utc::now().format("%Y%m%d_%H%M%S%3f")));
+        std::fs::write(&path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum PrinterConfig {
+    Device { path: String },
+    Tcp { host: String, port: Option<u16> },
+    Spool { dir: String },
+}
+
+/// Used until a printer is configured: jobs land in `spool/` next to the database.
+pub fn default_printer(db_path: &Path) -> PrinterConfig {
+    PrinterConfig::Spool { dir: db_path.with_file_name("spool").to_string_lossy().to_string() }
+}
+
+impl PrinterConfig {
+    pub fn backend(&self) -> Box<dyn PrinterBackend> {
+        match self {
+            PrinterConfig::Device { path } => Box::new(DeviceFileBackend { path: PathBuf::from(path) }),
+            PrinterConfig::Tcp { host, port } => Box::new(TcpBackend { host: host.clone(), port: port.unwrap_or(9100) }),
+            PrinterConfig::Spool { dir } => Box::new(SpoolBackend { dir: PathBuf::from(dir) }),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PrintJob {
+    pub job_id: u64,
+    pub invoice_id: Option<String>,
+    pub status: String, // "queued", "printing", "done", "failed"
+    pub attempts: u32,
+    pub printer: Option<String>,
+    pub error: Option<String>,
+    pub created_at: String,
+    #[serde(skip)]
+    pub data: Vec<u8>,
+}
+
+/// Background print queue. Jobs are sent on a worker thread so a slow or
+/// unplugged printer never blocks checkout; progress is reported through
+/// `print-job-updated` events.
+pub struct PrintQueue {
+    sender: Mutex<Sender<u64>>,
+    jobs: Arc<Mutex<Vec<PrintJob>>>,
+    config: Arc<Mutex<PrinterConfig>>,
+    next_id: AtomicU64,
+}
+
+impl PrintQueue {
+    pub fn start(app: AppHandle, config: PrinterConfig) -> Self {
+        let (sender, receiver) = channel();
+        let jobs = Arc::new(Mutex::new(Vec::new()));
+        let config = Arc::new(Mutex::new(config));
+        let worker_jobs = jobs.clone();
+        let worker_config = config.clone();
+        std::thread::spawn(move || run_worker(app, receiver, worker_jobs, worker_config));
+        PrintQueue {
+            sender: Mutex::new(sender),
+            jobs,
+            config,
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn set_config(&self, config: PrinterConfig) {
+        *self.config.lock().unwrap() = config;
+    }
+
+    pub fn config(&self) -> PrinterConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    pub fn enqueue(&self, invoice_id: Option<String>, data: Vec<u8>) -> Result<PrintJob, String> {
+        let job = PrintJob {
+            job_id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            invoice_id,
+            status: "queued".to_string(),
+            attempts: 0,
+            printer: None,
+            error: None,
+            created_at: Utc::now().naive_utc().format("%Y-%m-%d %H:%M:%S").to_string(),
+            data,
+        };
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            jobs.push(job.clone());
+            let overflow = jobs.len().saturating_sub(MAX_KEPT_JOBS);
+            jobs.drain(..overflow);
+        }
+        self.sender.lock().unwrap().send(job.job_id).map_err(|_| "Print queue has stopped".to_string())?;
+        Ok(job)
+    }
+
+    pub fn retry(&self, job_id: u64) -> Result<(), String> {
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            let job = jobs.iter_mut().find(|j| j.job_id == job_id).ok_or("Print job not found")?;
+            if job.status != "failed" {
+                return Err("Only failed print jobs can be retried".to_string());
+            }
+            job.status = "queued".to_string();
+            job.attempts = 0;
+            job.error = None;
+        }
+        self.sender.lock().unwrap().send(job_id).map_err(|_| "Print queue has stopped".to_string())
+    }
+
+    pub fn jobs(&self) -> Vec<PrintJob> {
+        self.jobs.lock().unwrap().clone()
+    }
+}
+
+fn update_job<F: FnOnce(&mut PrintJob)>(app: &AppHandle, jobs: &Mutex<Vec<PrintJob>>, job_id: u64, f: F) -> Option<Vec<u8>> {
+    let mut jobs = jobs.lock().unwrap();
+    let job = jobs.iter_mut().find(|j| j.job_id == job_id)?;
+    f(job);
+    let _ = app.emit_all("print-job-updated", job.clone());
+    Some(job.data.clone())
+}
+
+fn run_worker(app: AppHandle, receiver: Receiver<u64>, jobs: Arc<Mutex<Vec<PrintJob>>>, config: Arc<Mutex<PrinterConfig>>) {
+    for job_id in receiver {
+        for attempt in 1..=MAX_ATTEMPTS {
+            let mut backend = config.lock().unwrap().backend();
+            let printer = backend.describe();
+            let data = match update_job(&app, &jobs, job_id, |job| {
+                job.status = "printing".to_string();
+                job.attempts = attempt;
+                job.printer = Some(printer);
+            }) {
+                Some(data) => data,
+                None => break, // dropped from the history while waiting
+            };
+            match backend.send(&data) {
+                Ok(()) => {
+                    update_job(&app, &jobs, job_id, |job| {
+                        job.status = "done".to_string();
+                        job.error = None;
+                    });
+                    break;
+                }
+                Err(e) => {
+                    println!("[print_queue] job {} attempt {} failed: {}", job_id, attempt, e);
+                    let last = attempt == MAX_ATTEMPTS;
+                    update_job(&app, &jobs, job_id, |job| {
+                        job.status = if last { "failed" } else { "queued" }.to_string();
+                        job.error = Some(e);
+                    });
+                    if !last {
+                        std::thread::sleep(Duration::from_secs(2 * attempt as u64));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[command]
+pub fn configure_printer(window: Window, queue: State<'_, PrintQueue>, config: PrinterConfig) -> Result<(), String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut settings = Settings::load(&conn)?;
+    settings.printer = Some(config.clone());
+    store_settings(&window, &settings)?;
+    queue.set_config(config);
+    Ok(())
+}
+
+#[command]
+pub fn get_printer_config(queue: State<'_, PrintQueue>) -> Result<PrinterConfig, String> {
+    Ok(queue.config())
+}
+
+/// Renders the receipt and queues it; returns immediately with the job.
+#[command]
+pub fn print_receipt(window: Window, queue: State<'_, PrintQueue>, invoice_id: String, options: ReceiptOptions) -> Result<PrintJob, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let data = load_receipt_data(&conn, &invoice_id)?;
+    queue.enqueue(Some(invoice_id), render_escpos(&data, &options))
+}
+
+#[command]
+pub fn list_print_jobs(queue: State<'_, PrintQueue>) -> Result<Vec<PrintJob>, String> {
+    Ok(queue.jobs())
+}
+
+#[command]
+pub fn retry_print_job(queue: State<'_, PrintQueue>, job_id: u64) -> Result<(), String> {
+    queue.retry(job_id)
+}
+
+commands![
+    configure_printer,
+    get_printer_config,
+    print_receipt,
+    list_print_jobs,
+    retry_print_job,
+];
@@ -0,0 +1,499 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, Result};
+use tauri::{command, Window};
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime};
+
+use crate::cart::get_db_path;
+use crate::sales::round_money;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tier {
+    pub min_quantity: f64,
+    pub price: f64, // unit price once `min_quantity` is reached
+}
+
+/// What a promotion does. Product and category filters are optional; without
+/// either a percentage or amount off applies to every line.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PromotionRule {
+    PercentOff { percent: f64, product_id: Option<i64>, category: Option<String> },
+    AmountOff { amount: f64, product_id: Option<i64>, category: Option<String> }, // per unit
+    BuyXGetY { product_id: i64, buy: i32, get: i32 },
+    MixAndMatch { category: String, quantity: i32, price: f64 },
+    QuantityTier { product_id: i64, tiers: Vec<Tier> },
+    CartThreshold { min_total: f64, percent: Option<f64>, amount: Option<f64> },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Promotion {
+    pub promotion_id: i64,
+    pub name: String,
+    pub rule: PromotionRule,
+    pub active: bool,
+    pub priority: i64, // lower runs first
+    pub starts_at: Option<String>, // local "YYYY-MM-DD HH:MM:SS"
+    pub ends_at: Option<String>,
+    pub days: Option<Vec<u32>>,    // happy hour weekdays, 0 = Sunday
+    pub start_time: Option<String>, // happy hour "HH:MM", local
+    pub end_time: Option<String>,
+}
+
+/// A discount produced by a promotion on one cart line.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CartDiscount {
+    pub cart_id: i64,
+    pub promotion_id: i64,
+    pub name: String,
+    pub item_id: i64, // rowid of the discounted cart_items line
+    pub product_id: i64,
+    pub amount: f64,
+}
+
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS promotions (
+            promotion_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name VARCHAR(100) NOT NULL,
+            rule TEXT NOT NULL,
+            active BOOLEAN NOT NULL DEFAULT 1,
+            priority INTEGER NOT NULL DEFAULT 0,
+            starts_at DATETIME,
+            ends_at DATETIME,
+            days VARCHAR(20),
+            start_time VARCHAR(5),
+            end_time VARCHAR(5)
+        )",
+        params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS cart_discounts (
+            cart_id INTEGER NOT NULL,
+            promotion_id INTEGER NOT NULL,
+            name VARCHAR(100) NOT NULL,
+            item_id INTEGER NOT NULL,
+            product_id INTEGER NOT NULL,
+            amount FLOAT NOT NULL,
+            FOREIGN KEY (cart_id) REFERENCES carts(cart_id),
+            FOREIGN KEY (promotion_id) REFERENCES promotions(promotion_id)
+        )",
+        params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS invoice_discounts (
+            invoice_id VARCHAR(100) NOT NULL,
+            promotion_id INTEGER NOT NULL,
+            name VARCHAR(100) NOT NULL,
+            line_no INTEGER NOT NULL,
+            amount FLOAT NOT NULL,
+            FOREIGN KEY (invoice_id) REFERENCES invoices(invoice_id)
+        )",
+        params![],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_cart_discounts_cart_id ON cart_discounts(cart_id)", params![])?;
+    Ok(())
+}
+
+fn validate(promotion: &Promotion) -> Result<(), String> {
+    if promotion.name.trim().is_empty() {
+        return Err("Promotion name is required".to_string());
+    }
+    let ok = match &promotion.rule {
+        PromotionRule::PercentOff { percent, .. } => *percent > 0.0 && *percent <= 100.0,
+        PromotionRule::AmountOff { amount, .. } => *amount > 0.0,
+        PromotionRule::BuyXGetY { buy, get, .. } => *buy > 0 && *get > 0,
+        PromotionRule::MixAndMatch { quantity, price, .. } => *quantity > 1 && *price >= 0.0,
+        PromotionRule::QuantityTier { tiers, .. } => !tiers.is_empty() && tiers.iter().all(|t| t.min_quantity > 0.0 && t.price >= 0.0),
+        PromotionRule::CartThreshold { min_total, percent, amount } => {
+            *min_total >= 0.0
+                && percent.is_some() != amount.is_some()
+                && percent.map_or(true, |p| p > 0.0 && p <= 100.0)
+                && amount.map_or(true, |a| a > 0.0)
+        }
+    };
+    if !ok {
+        return Err(format!("Invalid parameters for promotion {}", promotion.name));
+    }
+    for time in [&promotion.start_time, &promotion.end_time].into_iter().flatten() {
+        NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| format!("Invalid time {}", time))?;
+    }
+    for at in [&promotion.starts_at, &promotion.ends_at].into_iter().flatten() {
+        NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M:%S").map_err(|_| format!("Invalid date {}", at))?;
+    }
+    if promotion.days.iter().flatten().any(|d| *d > 6) {
+        return Err("Weekdays must be between 0 (Sunday) and 6".to_string());
+    }
+    Ok(())
+}
+
+/// Whether the promotion's date range and happy-hour window include `now`.
+fn in_window(promotion: &Promotion, now: NaiveDateTime) -> bool {
+    let at = now.format("%Y-%m-%d %H:%M:%S").to_string();
+    if promotion.starts_at.as_ref().map_or(false, |s| at < *s) || promotion.ends_at.as_ref().map_or(false, |e| at >= *e) {
+        return false;
+    }
+    if let Some(days) = &promotion.days {
+        if !days.contains(&now.weekday().num_days_from_sunday()) {
+            return false;
+        }
+    }
+    let time = now.format("%H:%M").to_string();
+    match (&promotion.start_time, &promotion.end_time) {
+        (Some(start), Some(end)) if start <= end => time >= *start && time < *end,
+        (Some(start), Some(end)) => time >= *start || time < *end, // past midnight
+        (Some(start), None) => time >= *start,
+        (None, Some(end)) => time < *end,
+        (None, None) => true,
+    }
+}
+
+fn row_to_promotion(row: &rusqlite::Row) -> rusqlite::Result<Promotion> {
+    let rule: String = row.get(2)?;
+    let days: Option<String> = row.get(7)?;
+    Ok(Promotion {
+        promotion_id: row.get(0)?,
+        name: row.get(1)?,
+        rule: serde_json::from_str(&rule)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e)))?,
+        active: row.get(3)?,
+        priority: row.get(4)?,
+        starts_at: row.get(5)?,
+        ends_at: row.get(6)?,
+        days: days.map(|d| d.split(',').filter_map(|x| x.trim().parse().ok()).collect()),
+        start_time: row.get(8)?,
+        end_time: row.get(9)?,
+    })
+}
+
+const PROMOTION_COLUMNS: &str = "promotion_id, name, rule, active, priority, starts_at, ends_at, days, start_time, end_time";
+
+fn load_promotions(conn: &Connection, active_only: bool) -> Result<Vec<Promotion>, String> {
+    let filter = if active_only { "WHERE active = 1" } else { "" };
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM promotions {} ORDER BY priority, promotion_id", PROMOTION_COLUMNS, filter))
+        .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let promotions = stmt.query_map(params![], row_to_promotion)
+        .map_err(|e| format!("Failed to query: {}", e))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(promotions)
+}
+
+struct Line {
+    item_id: i64,
+    product_id: i64,
+    category: Option<String>,
+    purchasing_type: String,
+    quantity: f64,
+    price: f64,
+    remaining: f64, // line amount left after manual and earlier promotion discounts
+}
+
+impl Line {
+    fn matches(&self, product_id: &Option<i64>, category: &Option<String>) -> bool {
+        product_id.map_or(true, |p| p == self.product_id) && category.as_ref().map_or(true, |c| Some(c) == self.category.as_ref())
+    }
+}
+
+/// Spreads `amount` over the given lines in proportion to what is left of
+/// each, never taking a line below zero.
+fn allocate(lines: &[Line], indexes: &[usize], amount: f64) -> Vec<(usize, f64)> {
+    let base: f64 = indexes.iter().map(|&i| lines[i].remaining).sum();
+    if base <= 0.0 || amount <= 0.0 {
+        return Vec::new();
+    }
+    let amount = amount.min(base);
+    indexes.iter().map(|&i| (i, round_money(amount * lines[i].remaining / base))).collect()
+}
+
+fn evaluate_rule(rule: &PromotionRule, lines: &[Line]) -> Vec<(usize, f64)> {
+    // Quantity-based rules count single units only; bulk lines already carry the bulk price.
+    let singles = |product_id: i64| -> Vec<usize> {
+        (0..lines.len()).filter(|&i| lines[i].product_id == product_id && lines[i].purchasing_type == "single").collect()
+    };
+    match rule {
+        PromotionRule::PercentOff { percent, product_id, category } => (0..lines.len())
+            .filter(|&i| lines[i].matches(product_id, category))
+            .map(|i| (i, round_money(lines[i].remaining * percent / 100.0)))
+            .collect(),
+        PromotionRule::AmountOff { amount, product_id, category } => (0..lines.len())
+            .filter(|&i| lines[i].matches(product_id, category))
+            .map(|i| (i, round_money((amount * lines[i].quantity).min(lines[i].remaining))))
+            .collect(),
+        PromotionRule::BuyXGetY { product_id, buy, get } => {
+            let indexes = singles(*product_id);
+            let quantity: f64 = indexes.iter().map(|&i| lines[i].quantity).sum();
+            let free = (quantity / (buy + get) as f64).floor() * *get as f64;
+            match indexes.first() {
+                Some(&first) => allocate(lines, &indexes, free * lines[first].price),
+                None => Vec::new(),
+            }
+        }
+        PromotionRule::MixAndMatch { category, quantity, price } => {
+            let indexes: Vec<usize> = (0..lines.len())
+                .filter(|&i| lines[i].purchasing_type == "single" && lines[i].category.as_ref() == Some(category))
+                .collect();
+            // Bundle the most expensive units first, as the shelf label promises.
+            let mut units: Vec<f64> = indexes.iter().flat_map(|&i| std::iter::repeat(lines[i].price).take(lines[i].quantity.max(0.0).floor() as usize)).collect();
+            units.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+            let saving: f64 = units
+                .chunks(*quantity as usize)
+                .filter(|bundle| bundle.len() == *quantity as usize)
+                .map(|bundle| (bundle.iter().sum::<f64>() - price).max(0.0))
+                .sum();
+            allocate(lines, &indexes, saving)
+        }
+        PromotionRule::QuantityTier { product_id, tiers } => {
+            let indexes = singles(*product_id);
+            let quantity: f64 = indexes.iter().map(|&i| lines[i].quantity).sum();
+            let tier = tiers
+                .iter()
+                .filter(|t| quantity >= t.min_quantity)
+                .max_by(|a, b| a.min_quantity.partial_cmp(&b.min_quantity).unwrap_or(std::cmp::Ordering::Equal));
+            match tier {
+                Some(tier) => indexes
+                    .iter()
+                    .map(|&i| (i, round_money((lines[i].price - tier.price).max(0.0) * lines[i].quantity)))
+                    .collect(),
+                None => Vec::new(),
+            }
+        }
+        PromotionRule::CartThreshold { min_total, percent, amount } => {
+            let total: f64 = lines.iter().map(|l| l.remaining).sum();
+            if total < *min_total {
+                return Vec::new();
+            }
+            let discount = match (percent, amount) {
+                (Some(p), _) => total * p / 100.0,
+                (None, Some(a)) => *a,
+                (None, None) => 0.0,
+            };
+            allocate(lines, &(0..lines.len()).collect::<Vec<_>>(), discount)
+        }
+    }
+}
+
+/// Runs every active promotion against the cart at `now`. Line rules run in
+/// priority order and cart thresholds last, each on what earlier rules left.
+pub fn evaluate(conn: &Connection, cart_id: i64, now: NaiveDateTime) -> Result<Vec<CartDiscount>, String> {
+    let mut stmt = conn.prepare(
+        "SELECT ci.rowid, ci.product_id, p.Category, ci.purchasing_type, ci.quantity, ci.price, COALESCE(ci.discount, 0)
+         FROM cart_items ci LEFT JOIN products p ON p.rowid = ci.product_id
+         WHERE ci.cart_id = ?1 ORDER BY ci.rowid",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let mut lines: Vec<Line> = stmt.query_map(params![cart_id], |row| {
+        let quantity: f64 = row.get(4)?;
+        let price: f64 = row.get(5)?;
+        let discount: f64 = row.get(6)?;
+        Ok(Line {
+            item_id: row.get(0)?,
+            product_id: row.get(1)?,
+            category: row.get(2).ok().flatten(),
+            purchasing_type: row.get(3)?,
+            quantity,
+            price,
+            remaining: (quantity * price - discount).max(0.0),
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+
+    let mut promotions: Vec<Promotion> = load_promotions(conn, true)?.into_iter().filter(|p| in_window(p, now)).collect();
+    promotions.sort_by_key(|p| (matches!(p.rule, PromotionRule::CartThreshold { .. }), p.priority, p.promotion_id));
+
+    let mut discounts = Vec::new();
+    for promotion in &promotions {
+        for (index, amount) in evaluate_rule(&promotion.rule, &lines) {
+            let amount = round_money(amount.min(lines[index].remaining));
+            if amount <= 0.0 {
+                continue;
+            }
+            lines[index].remaining -= amount;
+            discounts.push(CartDiscount {
+                cart_id,
+                promotion_id: promotion.promotion_id,
+                name: promotion.name.clone(),
+                item_id: lines[index].item_id,
+                product_id: lines[index].product_id,
+                amount,
+            });
+        }
+    }
+    Ok(discounts)
+}
+
+/// Re-evaluates the cart's promotions and replaces its discount lines. Called
+/// after every change to the cart contents and again at checkout.
+pub fn apply_promotions(conn: &Connection, cart_id: i64) -> Result<Vec<CartDiscount>, String> {
+    let discounts = evaluate(conn, cart_id, Local::now().naive_local())?;
+    conn.execute("DELETE FROM cart_discounts WHERE cart_id = ?1", params![cart_id])
+        .map_err(|e| format!("Failed to clear cart discounts: {}", e))?;
+    for d in &discounts {
+        conn.execute(
+            "INSERT INTO cart_discounts (cart_id, promotion_id, name, item_id, product_id, amount) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![d.cart_id, d.promotion_id, d.name, d.item_id, d.product_id, d.amount],
+        )
+        .map_err(|e| format!("Failed to record cart discount: {}", e))?;
+    }
+    Ok(discounts)
+}
+
+pub fn load_cart_discounts(conn: &Connection, cart_id: i64) -> Result<Vec<CartDiscount>, String> {
+    let mut stmt = conn.prepare(
+        "SELECT cart_id, promotion_id, name, item_id, product_id, amount FROM cart_discounts WHERE cart_id = ?1 ORDER BY rowid",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let discounts = stmt.query_map(params![cart_id], |row| {
+        Ok(CartDiscount {
+            cart_id: row.get(0)?,
+            promotion_id: row.get(1)?,
+            name: row.get(2)?,
+            item_id: row.get(3)?,
+            product_id: row.get(4)?,
+            amount: row.get(5)?,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(discounts)
+}
+
+#[command]
+pub fn list_cart_discounts(window: Window, cart_id: i64) -> Result<Vec<CartDiscount>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    load_cart_discounts(&conn, cart_id)
+}
+
+#[command]
+pub fn list_promotions(window: Window) -> Result<Vec<Promotion>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    load_promotions(&conn, false)
+}
+
+/// Creates a promotion, or replaces it when `promotion_id` names an existing one.
+#[command]
+pub fn save_promotion(window: Window, promotion: Promotion) -> Result<Promotion, String> {
+    validate(&promotion)?;
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let rule = serde_json::to_string(&promotion.rule).map_err(|e| format!("Failed to encode rule: {}", e))?;
+    let days = promotion.days.as_ref().map(|d| d.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(","));
+    let rows = conn.execute(
+        "UPDATE promotions SET name = ?1, rule = ?2, active = ?3, priority = ?4, starts_at = ?5, ends_at = ?6,
+                days = ?7, start_time = ?8, end_time = ?9
+         WHERE promotion_id = ?10",
+        params![
+            promotion.name, rule, promotion.active, promotion.priority, promotion.starts_at,
+            promotion.ends_at, days, promotion.start_time, promotion.end_time, promotion.promotion_id
+        ],
+    )
+    .map_err(|e| format!("Failed to save promotion: {}", e))?;
+    let mut saved = promotion.clone();
+    if rows == 0 {
+        conn.execute(
+            "INSERT INTO promotions (name, rule, active, priority, starts_at, ends_at, days, start_time, end_time)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                promotion.name, rule, promotion.active, promotion.priority, promotion.starts_at,
+                promotion.ends_at, days, promotion.start_time, promotion.end_time
+            ],
+        )
+        .map_err(|e| format!("Failed to save promotion: {}", e))?;
+        saved.promotion_id = conn.last_insert_rowid();
+    }
+    Ok(saved)
+}
+
+#[command]
+pub fn set_promotion_active(window: Window, promotion_id: i64, active: bool) -> Result<(), String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let rows = conn.execute("UPDATE promotions SET active = ?1 WHERE promotion_id = ?2", params![active, promotion_id])
+        .map_err(|e| format!("Failed to update promotion: {}", e))?;
+    if rows == 0 {
+        return Err("Promotion not found".to_string());
+    }
+    Ok(())
+}
+
+commands![
+    list_promotions,
+    save_promotion,
+    set_promotion_active,
+    list_cart_discounts,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(product_id: i64, category: &str, quantity: f64, price: f64) -> Line {
+        Line {
+            item_id: product_id,
+            product_id,
+            category: Some(category.to_string()),
+            purchasing_type: "single".to_string(),
+            quantity,
+            price,
+            remaining: quantity * price,
+        }
+    }
+
+    #[test]
+    fn allocates_in_proportion_to_what_is_left() {
+        let lines = vec![line(1, "a", 1.0, 30000.0), line(2, "a", 1.0, 10000.0)];
+        assert_eq!(allocate(&lines, &[0, 1], 4000.0), vec![(0, 3000.0), (1, 1000.0)]);
+        // Never more than the lines are worth.
+        assert_eq!(allocate(&lines, &[0, 1], 99000.0), vec![(0, 30000.0), (1, 10000.0)]);
+        assert!(allocate(&lines, &[0], 0.0).is_empty());
+        let mut spent = line(3, "a", 1.0, 5000.0);
+        spent.remaining = 0.0;
+        assert!(allocate(&[spent], &[0], 1000.0).is_empty());
+    }
+
+    #[test]
+    fn buy_x_get_y_discounts_whole_free_units() {
+        let lines = vec![line(1, "a", 5.0, 10000.0), line(2, "b", 3.0, 8000.0)];
+        let rule = PromotionRule::BuyXGetY { product_id: 1, buy: 2, get: 1 };
+        assert_eq!(evaluate_rule(&rule, &lines), vec![(0, 10000.0)]);
+    }
+
+    #[test]
+    fn mix_and_match_bundles_the_most_expensive_units() {
+        let lines = vec![line(1, "drinks", 2.0, 12000.0), line(2, "drinks", 2.0, 8000.0), line(3, "snacks", 1.0, 5000.0)];
+        // 3 for 30.000: one bundle of 12+12+8, the last 8.000 unit pays full price.
+        let rule = PromotionRule::MixAndMatch { category: "drinks".to_string(), quantity: 3, price: 30000.0 };
+        assert_eq!(evaluate_rule(&rule, &lines), vec![(0, 1200.0), (1, 800.0)]);
+    }
+
+    #[test]
+    fn quantity_tier_uses_the_highest_reached_tier() {
+        let lines = vec![line(1, "a", 12.0, 5000.0)];
+        let rule = PromotionRule::QuantityTier {
+            product_id: 1,
+            tiers: vec![Tier { min_quantity: 6.0, price: 4500.0 }, Tier { min_quantity: 12.0, price: 4000.0 }],
+        };
+        assert_eq!(evaluate_rule(&rule, &lines), vec![(0, 12000.0)]);
+    }
+
+    #[test]
+    fn cart_threshold_applies_only_above_the_minimum() {
+        let lines = vec![line(1, "a", 1.0, 150000.0), line(2, "b", 1.0, 50000.0)];
+        let rule = PromotionRule::CartThreshold { min_total: 200000.0, percent: Some(10.0), amount: None };
+        assert_eq!(evaluate_rule(&rule, &lines), vec![(0, 15000.0), (1, 5000.0)]);
+        let rule = PromotionRule::CartThreshold { min_total: 200001.0, percent: None, amount: Some(20000.0) };
+        assert!(evaluate_rule(&rule, &lines).is_empty());
+    }
+
+    #[test]
+    fn bulk_lines_do_not_count_towards_quantity_rules() {
+        let mut bulk = line(1, "a", 3.0, 10000.0);
+        bulk.purchasing_type = "bulk".to_string();
+        let rule = PromotionRule::BuyXGetY { product_id: 1, buy: 2, get: 1 };
+        assert!(evaluate_rule(&rule, &[bulk]).is_empty());
+    }
+}
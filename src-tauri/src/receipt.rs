@@ -0,0 +1,498 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::{command, Window};
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+use crate::barcode::{Barcode, Symbology};
+use crate::cart::get_db_path;
+use crate::sales::{load_invoice, InvoiceDiscount, Tender};
+use crate::tax::TaxSummary;
+
+const ESC: u8 = 0x1B;
+const GS: u8 = 0x1D;
+const LF: u8 = 0x0A;
+
+/// Epson numbering for WPC1258 (Vietnamese). Clones often use another slot,
+/// so the code page can be overridden in `ReceiptOptions`.
+pub const DEFAULT_CODE_PAGE: u8 = 52;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PaperWidth {
+    #[serde(rename = "58mm")]
+    Mm58,
+    #[serde(rename = "80mm")]
+    Mm80,
+}
+
+impl PaperWidth {
+    /// Characters per line in the printer's default font A.
+    pub fn columns(self) -> usize {
+        match self {
+            PaperWidth::Mm58 => 32,
+            PaperWidth::Mm80 => 48,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReceiptOptions {
+    pub paper_width: PaperWidth,
+    pub store_name: String,
+    pub store_address: Option<String>,
+    pub store_phone: Option<String>,
+    pub footer: Option<String>,
+    pub code_page: Option<u8>,
+    pub ascii_only: bool, // strip accents for printers without a Vietnamese code page
+    pub cut: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReceiptLine {
+    pub name: String,
+    pub unit: String,
+    pub quantity: f64,
+    pub price: f64,
+    pub discount: f64, // includes the promotions below
+    pub promotions: Vec<InvoiceDiscount>,
+    pub total: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReceiptData {
+    pub invoice_id: String,
+    pub cashier: String,
+    pub paid_at: String,
+    pub lines: Vec<ReceiptLine>,
+    pub subtotal: f64,
+    pub discount: f64,
+    pub taxes: Vec<TaxSummary>,
+    pub prices_include_tax: bool,
+    pub total: f64,
+    pub tenders: Vec<Tender>,
+    pub change_due: f64,
+}
+
+pub fn load_receipt_data(conn: &Connection, invoice_id: &str) -> Result<ReceiptData, String> {
+    let detail = load_invoice(conn, invoice_id)?;
+    if detail.invoice.status != "completed" {
+        return Err("Receipts can only be printed for paid invoices".to_string());
+    }
+    let mut stmt = conn.prepare("SELECT Item_name, Unit, Bulk_unit FROM products WHERE rowid = ?1")
+        .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let mut lines = Vec::new();
+    for item in &detail.items {
+        let product: Option<(Option<String>, Option<String>, Option<String>)> = stmt
+            .query_row(params![item.product_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .optional()
+            .map_err(|e| format!("Failed to load product: {}", e))?;
+        let (name, unit, bulk_unit) = product.unwrap_or((None, None, None));
+        let unit = if item.purchasing_type == "bulk" { bulk_unit } else { unit };
+        lines.push(ReceiptLine {
+            name: name.unwrap_or_else(|| format!("#{}", item.product_id)),
+            unit: unit.unwrap_or_default(),
+            quantity: item.quantity,
+            price: item.price,
+            discount: item.discount,
+            promotions: detail.discounts.iter().filter(|d| d.line_no == item.line_no).cloned().collect(),
+            total: item.quantity * item.price - item.discount,
+        });
+    }
+    Ok(ReceiptData {
+        invoice_id: detail.invoice.invoice_id,
+        cashier: detail.invoice.storeman_id,
+        paid_at: detail.invoice.paid_at.unwrap_or(detail.invoice.created_at),
+        lines,
+        subtotal: detail.invoice.subtotal,
+        discount: detail.invoice.discount,
+        taxes: detail.taxes,
+        prices_include_tax: detail.invoice.prices_include_tax,
+        total: detail.invoice.total,
+        tenders: detail.tenders,
+        change_due: detail.invoice.change_due,
+    })
+}
+
+/// Formats a quantity with a decimal comma, e.g. `0,35`; whole numbers have no decimals.
+pub fn format_quantity(quantity: f64) -> String {
+    let text = format!("{:.3}", quantity);
+    text.trim_end_matches('0').trim_end_matches('.').replace('.', ",")
+}
+
+/// Formats a VND amount with dot thousands separators, e.g. `25.000`.
+pub fn format_money(amount: f64) -> String {
+    let rounded = amount.round() as i64;
+    let digits = rounded.abs().to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push('.');
+        }
+        out.push(c);
+    }
+    if rounded < 0 {
+        out.insert(0, '-');
+    }
+    out
+}
+
+fn cp1258_byte(c: char) -> Option<u8> {
+    if c.is_ascii() {
+        return Some(c as u8);
+    }
+    let byte = match c {
+        '€' => 0x80,
+        'À' => 0xC0, 'Á' => 0xC1, 'Â' => 0xC2, 'Ă' => 0xC3, 'Ä' => 0xC4, 'Å' => 0xC5, 'Æ' => 0xC6, 'Ç' => 0xC7,
+        'È' => 0xC8, 'É' => 0xC9, 'Ê' => 0xCA, 'Ë' => 0xCB, '\u{0300}' => 0xCC, 'Í' => 0xCD, 'Î' => 0xCE, 'Ï' => 0xCF,
+        'Đ' => 0xD0, 'Ñ' => 0xD1, '\u{0309}' => 0xD2, 'Ó' => 0xD3, 'Ô' => 0xD4, 'Ơ' => 0xD5, 'Ö' => 0xD6, '×' => 0xD7,
+        'Ø' => 0xD8, 'Ù' => 0xD9, 'Ú' => 0xDA, 'Û' => 0xDB, 'Ü' => 0xDC, 'Ư' => 0xDD, '\u{0303}' => 0xDE, 'ß' => 0xDF,
+        'à' => 0xE0, 'á' => 0xE1, 'â' => 0xE2, 'ă' => 0xE3, 'ä' => 0xE4, 'å' => 0xE5, 'æ' => 0xE6, 'ç' => 0xE7,
+        'è' => 0xE8, 'é' => 0xE9, 'ê' => 0xEA, 'ë' => 0xEB, '\u{0301}' => 0xEC, 'í' => 0xED, 'î' => 0xEE, 'ï' => 0xEF,
+        'đ' => 0xF0, 'ñ' => 0xF1, '\u{0323}' => 0xF2, 'ó' => 0xF3, 'ô' => 0xF4, 'ơ' => 0xF5, 'ö' => 0xF6, '÷' => 0xF7,
+        'ø' => 0xF8, 'ù' => 0xF9, 'ú' => 0xFA, 'û' => 0xFB, 'ü' => 0xFC, 'ư' => 0xFD, '₫' => 0xFE, 'ÿ' => 0xFF,
+        _ => return None,
+    };
+    Some(byte)
+}
+
+fn is_tone_mark(c: char) -> bool {
+    matches!(c, '\u{0300}' | '\u{0301}' | '\u{0303}' | '\u{0309}' | '\u{0323}')
+}
+
+/// Encodes text as Windows-1258. Letters without a precomposed slot are
+/// written as base letter plus combining tone mark, as the code page expects.
+pub fn encode_cp1258(text: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    for c in text.nfc() {
+        if let Some(b) = cp1258_byte(c) {
+            out.push(b);
+            continue;
+        }
+        let decomposed: Vec<char> = c.to_string().nfd().collect();
+        let base: String = decomposed.iter().filter(|m| !is_tone_mark(**m)).collect::<String>().nfc().collect();
+        for b in base.chars() {
+            out.push(cp1258_byte(b).unwrap_or(b'?'));
+        }
+        for tone in decomposed.iter().filter(|m| is_tone_mark(**m)) {
+            out.push(cp1258_byte(*tone).unwrap_or(b'?'));
+        }
+    }
+    out
+}
+
+pub fn strip_accents(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .map(|c| match c {
+            'đ' => 'd',
+            'Đ' => 'D',
+            _ => c,
+        })
+        .collect()
+}
+
+/// Printed width in columns; combining marks do not advance the head.
+fn display_width(text: &str) -> usize {
+    text.nfc().filter(|c| !is_combining_mark(*c)).count()
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.nfc().take(width).collect()
+}
+
+#[derive(Clone, Copy)]
+pub enum Align {
+    Left = 0,
+    Center = 1,
+    Right = 2,
+}
+
+/// Minimal ESC/POS command builder.
+pub struct EscPosWriter {
+    buf: Vec<u8>,
+    columns: usize,
+    ascii_only: bool,
+}
+
+impl EscPosWriter {
+    pub fn new(columns: usize, code_page: u8, ascii_only: bool) -> Self {
+        let mut writer = EscPosWriter { buf: Vec::new(), columns, ascii_only };
+        writer.buf.extend_from_slice(&[ESC, b'@']);
+        if !ascii_only {
+            writer.buf.extend_from_slice(&[ESC, b't', code_page]);
+        }
+        writer
+    }
+
+    pub fn align(&mut self, align: Align) -> &mut Self {
+        self.buf.extend_from_slice(&[ESC, b'a', align as u8]);
+        self
+    }
+
+    pub fn bold(&mut self, on: bool) -> &mut Self {
+        self.buf.extend_from_slice(&[ESC, b'E', on as u8]);
+        self
+    }
+
+    pub fn double_size(&mut self, on: bool) -> &mut Self {
+        self.buf.extend_from_slice(&[GS, b'!', if on { 0x11 } else { 0x00 }]);
+        self
+    }
+
+    pub fn text(&mut self, text: &str) -> &mut Self {
+        if self.ascii_only {
+            self.buf.extend(strip_accents(text).chars().map(|c| if c.is_ascii() { c as u8 } else { b'?' }));
+        } else {
+            self.buf.extend(encode_cp1258(text));
+        }
+        self
+    }
+
+    pub fn line(&mut self, text: &str) -> &mut Self {
+        self.text(text);
+        self.buf.push(LF);
+        self
+    }
+
+    /// Left text and right text on one line, padded to the paper width.
+    pub fn columns(&mut self, left: &str, right: &str) -> &mut Self {
+        let right_width = display_width(right);
+        let left = truncate(left, self.columns.saturating_sub(right_width + 1));
+        let padding = self.columns.saturating_sub(display_width(&left) + right_width);
+        let row = format!("{}{}{}", left, " ".repeat(padding), right);
+        self.line(&row)
+    }
+
+    pub fn separator(&mut self) -> &mut Self {
+        let rule = "-".repeat(self.columns);
+        self.line(&rule)
+    }
+
+    pub fn feed(&mut self, lines: u8) -> &mut Self {
+        self.buf.extend_from_slice(&[ESC, b'd', lines]);
+        self
+    }
+
+    /// Prints a barcode with its digits underneath (GS k, function B).
+    pub fn barcode(&mut self, barcode: &Barcode) -> &mut Self {
+        let (system, data) = match barcode.symbology {
+            Symbology::Ean8 => (68, barcode.code.clone()),
+            Symbology::Code128 => (73, format!("{{B{}", barcode.code)),
+            // UPC codes are kept as 13 digits, which print the same as EAN-13
+            _ => (67, barcode.code.clone()),
+        };
+        self.buf.extend_from_slice(&[GS, b'h', 80, GS, b'w', 2, GS, b'H', 2]);
+        self.buf.extend_from_slice(&[GS, b'k', system, data.len() as u8]);
+        self.buf.extend_from_slice(data.as_bytes());
+        self.buf.push(LF);
+        self
+    }
+
+    pub fn cut(&mut self) -> &mut Self {
+        self.buf.extend_from_slice(&[GS, b'V', 0x42, 0x00]);
+        self
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+fn tender_label(method: &str) -> String {
+    match method {
+        "cash" => "Tiền mặt".to_string(),
+        "card" => "Thẻ".to_string(),
+        "qr" => "Chuyển khoản QR".to_string(),
+        "points" => "Điểm thưởng".to_string(),
+        "account" => "Ghi nợ".to_string(),
+        other => other.to_string(),
+    }
+}
+
+pub fn render_escpos(data: &ReceiptData, options: &ReceiptOptions) -> Vec<u8> {
+    let mut w = EscPosWriter::new(
+        options.paper_width.columns(),
+        options.code_page.unwrap_or(DEFAULT_CODE_PAGE),
+        options.ascii_only,
+    );
+
+    w.align(Align::Center).bold(true).double_size(true).line(&options.store_name).double_size(false).bold(false);
+    if let Some(address) = &options.store_address {
+        w.line(address);
+    }
+    if let Some(phone) = &options.store_phone {
+        w.line(&format!("ĐT: {}", phone));
+    }
+    w.feed(1).bold(true).line("HÓA ĐƠN BÁN HÀNG").bold(false).align(Align::Left);
+    w.columns("Số HĐ:", &data.invoice_id);
+    w.columns("Thu ngân:", &data.cashier);
+    w.columns("Ngày:", &data.paid_at);
+    w.separator();
+
+    for line in &data.lines {
+        w.line(&line.name);
+        let qty = if line.unit.is_empty() {
+            format!("  {} x {}", format_quantity(line.quantity), format_money(line.price))
+        } else {
+            format!("  {} {} x {}", format_quantity(line.quantity), line.unit, format_money(line.price))
+        };
+        w.columns(&qty, &format_money(line.quantity * line.price));
+        let manual = line.discount - line.promotions.iter().map(|p| p.amount).sum::<f64>();
+        if manual > 0.005 {
+            w.columns("  Giảm giá", &format!("-{}", format_money(manual)));
+        }
+        for promotion in &line.promotions {
+            w.columns(&format!("  {}", promotion.name), &format!("-{}", format_money(promotion.amount)));
+        }
+    }
+
+    w.separator();
+    w.columns("Tạm tính", &format_money(data.subtotal));
+    if data.discount > 0.0 {
+        w.columns("Giảm giá", &format!("-{}", format_money(data.discount)));
+    }
+    if !data.prices_include_tax {
+        for tax in &data.taxes {
+            w.columns(&format!("Thuế GTGT {}%", tax.rate), &format_money(tax.tax));
+        }
+    }
+    w.bold(true).columns("TỔNG CỘNG", &format_money(data.total)).bold(false);
+    if data.prices_include_tax {
+        for tax in data.taxes.iter().filter(|t| t.tax > 0.0) {
+            w.columns(&format!("  Gồm thuế GTGT {}%", tax.rate), &format_money(tax.tax));
+        }
+    }
+    for tender in &data.tenders {
+        w.columns(&tender_label(&tender.method), &format_money(tender.amount));
+    }
+    if data.change_due > 0.0 {
+        w.columns("Tiền thừa", &format_money(data.change_due));
+    }
+    w.separator();
+
+    w.align(Align::Center);
+    w.line(options.footer.as_deref().unwrap_or("Cảm ơn quý khách!"));
+    w.feed(3);
+    if options.cut {
+        w.cut();
+    }
+    w.into_bytes()
+}
+
+#[command]
+pub fn render_receipt(window: Window, invoice_id: String, options: ReceiptOptions) -> Result<Vec<u8>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let data = load_receipt_data(&conn, &invoice_id)?;
+    Ok(render_escpos(&data, &options))
+}
+
+commands![
+    render_receipt,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ReceiptData {
+        ReceiptData {
+            invoice_id: "S1_M1_20250101_001".to_string(),
+            cashier: "M1".to_string(),
+            paid_at: "2025-01-01 10:00:00".to_string(),
+            lines: vec![ReceiptLine {
+                name: "Cà phê sữa".to_string(),
+                unit: "ly".to_string(),
+                quantity: 2.0,
+                price: 25000.0,
+                discount: 0.0,
+                promotions: Vec::new(),
+                total: 50000.0,
+            }],
+            subtotal: 50000.0,
+            discount: 0.0,
+            taxes: Vec::new(),
+            prices_include_tax: true,
+            total: 50000.0,
+            tenders: vec![Tender { method: "cash".to_string(), amount: 50000.0 }],
+            change_due: 0.0,
+        }
+    }
+
+    fn options(paper_width: PaperWidth, cut: bool) -> ReceiptOptions {
+        ReceiptOptions {
+            paper_width,
+            store_name: "Tạp hóa".to_string(),
+            store_address: None,
+            store_phone: None,
+            footer: None,
+            code_page: None,
+            ascii_only: false,
+            cut,
+        }
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn encodes_precomposed_and_combining_vietnamese() {
+        assert_eq!(
+            encode_cp1258("Cà phê sữa"),
+            vec![b'C', 0xE0, b' ', b'p', b'h', 0xEA, b' ', b's', 0xFD, 0xDE, b'a']
+        );
+        // Decomposed input is normalized first, so it encodes the same way.
+        assert_eq!(encode_cp1258("Ca\u{0300}"), encode_cp1258("Cà"));
+        assert_eq!(encode_cp1258("Việt"), vec![b'V', b'i', 0xEA, 0xF2, b't']);
+        assert_eq!(encode_cp1258("đ₫"), vec![0xF0, 0xFE]);
+    }
+
+    #[test]
+    fn starts_with_init_and_code_page() {
+        let bytes = render_escpos(&sample(), &options(PaperWidth::Mm58, true));
+        assert_eq!(&bytes[..5], &[ESC, b'@', ESC, b't', DEFAULT_CODE_PAGE]);
+
+        let mut ascii = options(PaperWidth::Mm58, true);
+        ascii.ascii_only = true;
+        let bytes = render_escpos(&sample(), &ascii);
+        assert_eq!(&bytes[..3], &[ESC, b'@', ESC]);
+        assert_ne!(bytes[3], b't');
+        assert!(bytes.iter().all(|b| b.is_ascii()));
+    }
+
+    #[test]
+    fn rows_fill_the_paper_width() {
+        for (width, columns) in [(PaperWidth::Mm58, 32), (PaperWidth::Mm80, 48)] {
+            let bytes = render_escpos(&sample(), &options(width, false));
+            let mut rule = vec![b'-'; columns];
+            rule.push(LF);
+            assert!(contains(&bytes, &rule), "{} column rule", columns);
+            assert!(!contains(&bytes, &vec![b'-'; columns + 1]));
+
+            // "Tiền mặt" plus amount, padded so the amount ends at the edge.
+            let mut row = encode_cp1258("Tiền mặt");
+            row.extend(std::iter::repeat_n(b' ', columns - 8 - 6));
+            row.extend_from_slice(b"50.000");
+            row.push(LF);
+            assert!(contains(&bytes, &row), "{} column tender row", columns);
+        }
+    }
+
+    #[test]
+    fn ends_with_feed_and_optional_cut() {
+        let bytes = render_escpos(&sample(), &options(PaperWidth::Mm80, true));
+        assert!(bytes.ends_with(&[ESC, b'd', 3, GS, b'V', 0x42, 0x00]));
+        let bytes = render_escpos(&sample(), &options(PaperWidth::Mm80, false));
+        assert!(bytes.ends_with(&[ESC, b'd', 3]));
+    }
+
+    #[test]
+    fn formats_money_and_quantities() {
+        assert_eq!(format_money(1234567.0), "1.234.567");
+        assert_eq!(format_money(-25000.0), "-25.000");
+        assert_eq!(format_money(999.0), "999");
+        assert_eq!(format_quantity(2.0), "2");
+        assert_eq!(format_quantity(0.25), "0,25");
+    }
+}
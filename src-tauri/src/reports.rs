@@ -0,0 +1,290 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::{command, Window};
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+use crate::cart::get_db_path;
+use crate::sales::round_money;
+use crate::shift::open_shift_row;
+
+const TOP_PRODUCTS: i64 = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TenderTotal {
+    pub method: String,
+    pub amount: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProductTotal {
+    pub product_id: i64,
+    pub name: String,
+    pub quantity: f64,
+    pub revenue: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DailyReport {
+    pub report_type: String, // "X" or "Z"
+    pub z_number: Option<i64>,
+    pub business_day: String,
+    pub generated_at: String,
+    pub invoice_count: i64,
+    pub first_invoice_id: Option<String>,
+    pub last_invoice_id: Option<String>,
+    pub gross_sales: f64,
+    pub discounts: f64,
+    pub net_sales: f64,
+    pub return_count: i64,
+    pub refunds_total: f64,
+    pub net_after_returns: f64,
+    pub tenders: Vec<TenderTotal>,
+    pub top_products: Vec<ProductTotal>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ZReportSummary {
+    pub z_number: i64,
+    pub business_day: String,
+    pub invoice_count: i64,
+    pub net_after_returns: f64,
+    pub created_at: String,
+}
+
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS z_reports (
+            z_number INTEGER PRIMARY KEY AUTOINCREMENT,
+            business_day DATE NOT NULL UNIQUE,
+            invoice_count INTEGER NOT NULL,
+            net_after_returns FLOAT NOT NULL,
+            report_json TEXT NOT NULL,
+            created_at DATETIME NOT NULL
+        )",
+        params![],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_invoices_paid_at ON invoices(paid_at)", params![])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_returns_created_at ON returns(created_at)", params![])?;
+    Ok(())
+}
+
+/// Reports cover today or an earlier day; a day that has not begun cannot be
+/// reported on, let alone closed.
+fn parse_day(business_day: Option<String>) -> Result<NaiveDate, String> {
+    let today = Local::now().date_naive();
+    let day = match business_day {
+        Some(day) => NaiveDate::parse_from_str(&day, "%Y-%m-%d").map_err(|_| format!("Invalid business day: {}", day))?,
+        None => today,
+    };
+    if day > today {
+        return Err(format!("Business day {} has not started yet", day.format("%Y-%m-%d")));
+    }
+    Ok(day)
+}
+
+fn local_midnight_utc(day: NaiveDate) -> NaiveDateTime {
+    let midnight = day.and_hms_opt(0, 0, 0).unwrap();
+    Local.from_local_datetime(&midnight)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc).naive_utc())
+        .unwrap_or(midnight)
+}
+
+/// Stored timestamps are UTC; a business day runs from local midnight to
+/// local midnight.
+pub fn day_bounds(day: NaiveDate) -> (String, String) {
+    let start = local_midnight_utc(day);
+    let end = local_midnight_utc(day.succ_opt().unwrap_or(day));
+    (
+        start.format("%Y-%m-%d %H:%M:%S").to_string(),
+        end.format("%Y-%m-%d %H:%M:%S").to_string(),
+    )
+}
+
+pub fn business_day_of(timestamp: &NaiveDateTime) -> NaiveDate {
+    Utc.from_utc_datetime(timestamp).with_timezone(&Local).date_naive()
+}
+
+/// Fails once the current business day has been closed with a Z-report.
+pub fn ensure_day_open(conn: &Connection) -> Result<(), String> {
+    let today = business_day_of(&Utc::now().naive_utc()).format("%Y-%m-%d").to_string();
+    let closed: Option<i64> = conn.query_row("SELECT z_number FROM z_reports WHERE business_day = ?1", params![today], |row| row.get(0))
+        .optional()
+        .map_err(|e| format!("Failed to check Z-reports: {}", e))?;
+    match closed {
+        Some(z_number) => Err(format!("Business day {} was closed by Z-report #{}", today, z_number)),
+        None => Ok(()),
+    }
+}
+
+pub fn build_report(conn: &Connection, day: NaiveDate, report_type: &str) -> Result<DailyReport, String> {
+    let (start, end) = day_bounds(day);
+    // Sales and returns synced from other tills belong to their own reports.
+    let completed = "status = 'completed' AND paid_at >= ?1 AND paid_at < ?2 AND sync_origin IS NULL";
+
+    let (invoice_count, gross_sales, discounts, net_sales): (i64, f64, f64, f64) = conn.query_row(
+        &format!("SELECT COUNT(*), COALESCE(SUM(subtotal), 0), COALESCE(SUM(discount), 0), COALESCE(SUM(total), 0) FROM invoices WHERE {}", completed),
+        params![start, end],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )
+    .map_err(|e| format!("Failed to total sales: {}", e))?;
+
+    let invoice_id_at = |order: &str| -> Result<Option<String>, String> {
+        conn.query_row(
+            &format!("SELECT invoice_id FROM invoices WHERE {} ORDER BY paid_at {}, invoice_id {} LIMIT 1", completed, order, order),
+            params![start, end],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to find invoice range: {}", e))
+    };
+    let first_invoice_id = invoice_id_at("ASC")?;
+    let last_invoice_id = invoice_id_at("DESC")?;
+
+    let (return_count, refunds_total): (i64, f64) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(refund_total), 0) FROM returns WHERE created_at >= ?1 AND created_at < ?2 AND sync_origin IS NULL",
+        params![start, end],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .map_err(|e| format!("Failed to total returns: {}", e))?;
+
+    // Cash is reported net of change handed back.
+    let mut stmt = conn.prepare(&format!(
+        "SELECT t.method, SUM(t.amount) - CASE WHEN t.method = 'cash'
+                THEN COALESCE((SELECT SUM(change_due) FROM invoices WHERE {}), 0) ELSE 0 END
+         FROM invoice_tenders t JOIN invoices i ON i.invoice_id = t.invoice_id
+         WHERE i.status = 'completed' AND i.paid_at >= ?1 AND i.paid_at < ?2 AND i.sync_origin IS NULL
+         GROUP BY t.method ORDER BY t.method",
+        completed
+    ))
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let tenders = stmt.query_map(params![start, end], |row| {
+        Ok(TenderTotal {
+            method: row.get(0)?,
+            amount: round_money(row.get(1)?),
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+
+    let mut stmt = conn.prepare(
+        "SELECT it.product_id, COALESCE(p.Item_name, ''), SUM(it.quantity), SUM(it.quantity * it.price - it.discount) AS revenue
+         FROM invoice_items it JOIN invoices i ON i.invoice_id = it.invoice_id
+         LEFT JOIN products p ON p.rowid = it.product_id
+         WHERE i.status = 'completed' AND i.paid_at >= ?1 AND i.paid_at < ?2 AND i.sync_origin IS NULL
+         GROUP BY it.product_id ORDER BY revenue DESC LIMIT ?3",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let top_products = stmt.query_map(params![start, end, TOP_PRODUCTS], |row| {
+        Ok(ProductTotal {
+            product_id: row.get(0)?,
+            name: row.get(1)?,
+            quantity: row.get(2)?,
+            revenue: round_money(row.get(3)?),
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+
+    Ok(DailyReport {
+        report_type: report_type.to_string(),
+        z_number: None,
+        business_day: day.format("%Y-%m-%d").to_string(),
+        generated_at: Utc::now().naive_utc().format("%Y-%m-%d %H:%M:%S").to_string(),
+        invoice_count,
+        first_invoice_id,
+        last_invoice_id,
+        gross_sales: round_money(gross_sales),
+        discounts: round_money(discounts),
+        net_sales: round_money(net_sales),
+        return_count,
+        refunds_total: round_money(refunds_total),
+        net_after_returns: round_money(net_sales - refunds_total),
+        tenders,
+        top_products,
+    })
+}
+
+/// Read-only snapshot of a business day (today by default).
+#[command]
+pub fn x_report(window: Window, business_day: Option<String>) -> Result<DailyReport, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    build_report(&conn, parse_day(business_day)?, "X")
+}
+
+/// Closes a business day. The report is stored with the next Z number and a
+/// day can only be closed once; no further payments are accepted that day.
+#[command]
+pub fn z_report(window: Window, business_day: Option<String>) -> Result<DailyReport, String> {
+    let db_path = get_db_path(&window);
+    let mut conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let day = parse_day(business_day)?;
+    let day_str = day.format("%Y-%m-%d").to_string();
+
+    let existing: Option<i64> = tx.query_row("SELECT z_number FROM z_reports WHERE business_day = ?1", params![day_str], |row| row.get(0))
+        .optional()
+        .map_err(|e| format!("Failed to check Z-reports: {}", e))?;
+    if let Some(z_number) = existing {
+        return Err(format!("Business day {} was already closed by Z-report #{}", day_str, z_number));
+    }
+    if let Some(shift) = open_shift_row(&tx)? {
+        return Err(format!("Close shift {} before running the Z-report", shift.shift_id));
+    }
+
+    let mut report = build_report(&tx, day, "Z")?;
+    let report_json = serde_json::to_string(&report).map_err(|e| format!("Failed to serialize report: {}", e))?;
+    tx.execute(
+        "INSERT INTO z_reports (business_day, invoice_count, net_after_returns, report_json, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![day_str, report.invoice_count, report.net_after_returns, report_json, report.generated_at],
+    )
+    .map_err(|e| format!("Failed to store Z-report: {}", e))?;
+    report.z_number = Some(tx.last_insert_rowid());
+    let report_json = serde_json::to_string(&report).map_err(|e| format!("Failed to serialize report: {}", e))?;
+    tx.execute("UPDATE z_reports SET report_json = ?1 WHERE z_number = ?2", params![report_json, report.z_number])
+        .map_err(|e| format!("Failed to store Z-report: {}", e))?;
+    tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+    Ok(report)
+}
+
+#[command]
+pub fn list_z_reports(window: Window) -> Result<Vec<ZReportSummary>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut stmt = conn.prepare("SELECT z_number, business_day, invoice_count, net_after_returns, created_at FROM z_reports ORDER BY z_number DESC")
+        .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let reports = stmt.query_map(params![], |row| {
+        Ok(ZReportSummary {
+            z_number: row.get(0)?,
+            business_day: row.get(1)?,
+            invoice_count: row.get(2)?,
+            net_after_returns: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(reports)
+}
+
+#[command]
+pub fn get_z_report(window: Window, z_number: i64) -> Result<DailyReport, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let json: String = conn.query_row("SELECT report_json FROM z_reports WHERE z_number = ?1", params![z_number], |row| row.get(0))
+        .optional()
+        .map_err(|e| format!("Failed to load Z-report: {}", e))?
+        .ok_or_else(|| format!("Z-report #{} not found", z_number))?;
+    serde_json::from_str(&json).map_err(|e| format!("Failed to read Z-report: {}", e))
+}
+
+commands![
+    x_report,
+    z_report,
+    list_z_reports,
+    get_z_report,
+];
@@ -0,0 +1,364 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, Result};
+use tauri::{command, Window};
+use chrono::Utc;
+
+use crate::cart::get_db_path;
+use crate::credit::ACCOUNT_TENDER;
+use crate::customers::{ensure_accounts_here, reverse_loyalty};
+use crate::reports::ensure_day_open;
+use crate::sales::{load_invoice, round_money, Tender};
+use crate::shift::require_open_shift;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReturnLineRequest {
+    pub line_no: i64,
+    pub quantity: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReturnItem {
+    pub return_id: String,
+    pub line_no: i64,
+    pub product_id: i64,
+    pub quantity: f64,
+    pub purchasing_type: String,
+    pub refund_amount: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReturnRecord {
+    pub return_id: String,
+    pub invoice_id: String,
+    pub reason: Option<String>,
+    pub refund_total: f64,
+    pub restocked: bool,
+    pub created_at: String,
+    pub items: Vec<ReturnItem>,
+    pub tenders: Vec<Tender>, // refund per original tender
+}
+
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS returns (
+            return_id VARCHAR(100) PRIMARY KEY,
+            invoice_id VARCHAR(100) NOT NULL,
+            reason VARCHAR(255),
+            refund_total FLOAT NOT NULL,
+            restocked BOOLEAN NOT NULL DEFAULT 0,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (invoice_id) REFERENCES invoices(invoice_id)
+        )",
+        params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS return_items (
+            return_id VARCHAR(100) NOT NULL,
+            line_no INTEGER NOT NULL,
+            product_id INTEGER NOT NULL,
+            quantity INTEGER NOT NULL,
+            purchasing_type VARCHAR(10) NOT NULL,
+            refund_amount FLOAT NOT NULL,
+            PRIMARY KEY (return_id, line_no),
+            FOREIGN KEY (return_id) REFERENCES returns(return_id)
+        )",
+        params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS stock_movements (
+            movement_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            product_id INTEGER NOT NULL,
+            purchasing_type VARCHAR(10) NOT NULL,
+            quantity INTEGER NOT NULL,
+            reason VARCHAR(20) NOT NULL,
+            reference VARCHAR(100),
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS return_tenders (
+            return_id VARCHAR(100) NOT NULL,
+            method VARCHAR(20) NOT NULL,
+            amount FLOAT NOT NULL,
+            FOREIGN KEY (return_id) REFERENCES returns(return_id)
+        )",
+        params![],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_returns_invoice_id ON returns(invoice_id)", params![])?;
+    // Returns recorded before refunds followed the tenders were paid in cash.
+    conn.execute(
+        "INSERT INTO return_tenders (return_id, method, amount)
+         SELECT r.return_id, 'cash', r.refund_total FROM returns r
+         WHERE NOT EXISTS (SELECT 1 FROM return_tenders t WHERE t.return_id = r.return_id)",
+        params![],
+    )?;
+    Ok(())
+}
+
+/// Splits a refund over the tenders the invoice was paid with, in proportion
+/// to what each kept after change (change is handed out in cash). Rounding
+/// leftovers go to the largest tender.
+pub fn split_refund(refund_total: f64, tenders: &[Tender], change_due: f64) -> Vec<Tender> {
+    let mut paid: Vec<Tender> = Vec::new();
+    for tender in tenders {
+        match paid.iter_mut().find(|t| t.method == tender.method) {
+            Some(t) => t.amount += tender.amount,
+            None => paid.push(tender.clone()),
+        }
+    }
+    let mut change = change_due;
+    if let Some(cash) = paid.iter_mut().find(|t| t.method == "cash") {
+        let taken = change.min(cash.amount);
+        cash.amount -= taken;
+        change -= taken;
+    }
+    if change > 0.0 {
+        if let Some(largest) = paid.iter_mut().max_by(|a, b| a.amount.partial_cmp(&b.amount).unwrap_or(std::cmp::Ordering::Equal)) {
+            largest.amount -= change;
+        }
+    }
+    paid.retain(|t| t.amount > 0.0);
+    let total: f64 = paid.iter().map(|t| t.amount).sum();
+    if total <= 0.0 {
+        return vec![Tender { method: "cash".to_string(), amount: round_money(refund_total) }];
+    }
+
+    let mut shares: Vec<Tender> = paid
+        .iter()
+        .map(|t| Tender { method: t.method.clone(), amount: round_money(refund_total * t.amount / total) })
+        .collect();
+    let remainder = round_money(refund_total - shares.iter().map(|t| t.amount).sum::<f64>());
+    if remainder != 0.0 {
+        if let Some(largest) = shares.iter_mut().max_by(|a, b| a.amount.partial_cmp(&b.amount).unwrap_or(std::cmp::Ordering::Equal)) {
+            largest.amount = round_money(largest.amount + remainder);
+        }
+    }
+    shares.retain(|t| t.amount != 0.0);
+    shares
+}
+
+/// Records a return against a completed invoice. Each line's refund is its
+/// paid amount (after the original line discount, plus tax when prices were
+/// tax-exclusive) pro-rated by quantity. The refund goes back through the
+/// original tenders: cash out of the open shift's drawer, the `account` part
+/// off the customer's tab and the `points` part as loyalty points, while the
+/// points earned on the returned share are taken back.
+#[command]
+pub fn create_return(window: Window, invoice_id: String, lines: Vec<ReturnLineRequest>, reason: Option<String>, restock: bool) -> Result<ReturnRecord, String> {
+    let db_path = get_db_path(&window);
+    let mut conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+    let shift = require_open_shift(&tx)?;
+    ensure_day_open(&tx)?;
+    let detail = load_invoice(&tx, &invoice_id)?;
+    if detail.invoice.status != "completed" {
+        return Err("Only paid invoices can be returned".to_string());
+    }
+    if lines.is_empty() {
+        return Err("No lines selected for return".to_string());
+    }
+
+    let count: i64 = tx.query_row("SELECT COUNT(*) FROM returns WHERE invoice_id = ?1", params![invoice_id], |row| row.get(0))
+        .map_err(|e| format!("Failed to count returns: {}", e))?;
+    let return_id = format!("{}_R{:02}", invoice_id, count + 1);
+    let created_at = Utc::now().naive_utc().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    let mut items = Vec::new();
+    for line in &lines {
+        let sold = detail.items.iter().find(|i| i.line_no == line.line_no)
+            .ok_or_else(|| format!("Invoice has no line {}", line.line_no))?;
+        if line.quantity <= 0.0 {
+            return Err(format!("Return quantity for line {} must be positive", line.line_no));
+        }
+        let already = sold.returned_quantity
+            + items.iter().filter(|i: &&ReturnItem| i.line_no == line.line_no).map(|i| i.quantity).sum::<f64>();
+        if already + line.quantity > sold.quantity + 1e-9 {
+            return Err(format!(
+                "Cannot return {} of line {}: {} sold, {} already returned",
+                line.quantity, line.line_no, sold.quantity, already
+            ));
+        }
+        let mut line_paid = sold.quantity * sold.price - sold.discount;
+        if !detail.invoice.prices_include_tax {
+            line_paid += sold.tax_amount;
+        }
+        let refund_amount = round_money(line_paid * line.quantity / sold.quantity);
+        items.push(ReturnItem {
+            return_id: return_id.clone(),
+            line_no: line.line_no,
+            product_id: sold.product_id,
+            quantity: line.quantity,
+            purchasing_type: sold.purchasing_type.clone(),
+            refund_amount,
+        });
+    }
+    let refund_total = round_money(items.iter().map(|i| i.refund_amount).sum());
+    let tenders = split_refund(refund_total, &detail.tenders, detail.invoice.change_due);
+    let customer_phone: Option<String> = tx.query_row("SELECT customer_phone FROM invoices WHERE invoice_id = ?1", params![invoice_id], |row| row.get(0))
+        .map_err(|e| format!("Failed to load invoice: {}", e))?;
+    if customer_phone.is_some() {
+        ensure_accounts_here(&tx)?;
+    }
+
+    tx.execute(
+        "INSERT INTO returns (return_id, invoice_id, reason, refund_total, restocked, created_at, shift_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![return_id, invoice_id, reason, refund_total, restock, created_at, shift.shift_id],
+    )
+    .map_err(|e| format!("Failed to record return: {}", e))?;
+    for item in &items {
+        tx.execute(
+            "INSERT INTO return_items (return_id, line_no, product_id, quantity, purchasing_type, refund_amount) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![item.return_id, item.line_no, item.product_id, item.quantity, item.purchasing_type, item.refund_amount],
+        )
+        .map_err(|e| format!("Failed to record return item: {}", e))?;
+        if restock {
+            tx.execute(
+                "INSERT INTO stock_movements (product_id, purchasing_type, quantity, reason, reference, created_at) VALUES (?1, ?2, ?3, 'return', ?4, ?5)",
+                params![item.product_id, item.purchasing_type, item.quantity, return_id, created_at],
+            )
+            .map_err(|e| format!("Failed to restock item: {}", e))?;
+        }
+    }
+    for tender in &tenders {
+        tx.execute(
+            "INSERT INTO return_tenders (return_id, method, amount) VALUES (?1, ?2, ?3)",
+            params![return_id, tender.method, tender.amount],
+        )
+        .map_err(|e| format!("Failed to record refund tender: {}", e))?;
+        if tender.method == ACCOUNT_TENDER {
+            let phone = customer_phone.as_deref().ok_or("The invoice was put on account without a customer")?;
+            tx.execute(
+                "INSERT INTO credit_ledger (phone, invoice_id, kind, amount, note, created_at) VALUES (?1, ?2, 'refund', ?3, ?4, ?5)",
+                params![phone, invoice_id, -tender.amount, return_id, created_at],
+            )
+            .map_err(|e| format!("Failed to credit the tab: {}", e))?;
+        }
+    }
+    reverse_loyalty(&tx, &invoice_id, &return_id)?;
+    tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+
+    Ok(ReturnRecord {
+        return_id,
+        invoice_id,
+        reason,
+        refund_total,
+        restocked: restock,
+        created_at,
+        items,
+        tenders,
+    })
+}
+
+fn load_return_items(conn: &Connection, record: &mut ReturnRecord) -> Result<(), String> {
+    let mut stmt = conn.prepare("SELECT return_id, line_no, product_id, quantity, purchasing_type, refund_amount FROM return_items WHERE return_id = ?1 ORDER BY line_no")
+        .map_err(|e| format!("Failed to prepare: {}", e))?;
+    record.items = stmt.query_map(params![record.return_id], |row| {
+        Ok(ReturnItem {
+            return_id: row.get(0)?,
+            line_no: row.get(1)?,
+            product_id: row.get(2)?,
+            quantity: row.get(3)?,
+            purchasing_type: row.get(4)?,
+            refund_amount: row.get(5)?,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    let mut stmt = conn.prepare("SELECT method, amount FROM return_tenders WHERE return_id = ?1 ORDER BY rowid")
+        .map_err(|e| format!("Failed to prepare: {}", e))?;
+    record.tenders = stmt.query_map(params![record.return_id], |row| Ok(Tender { method: row.get(0)?, amount: row.get(1)? }))
+        .map_err(|e| format!("Failed to query: {}", e))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(())
+}
+
+fn load_returns_where(conn: &Connection, filter: &str, key: &str) -> Result<Vec<ReturnRecord>, String> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT return_id, invoice_id, reason, refund_total, restocked, created_at FROM returns WHERE {} = ?1 ORDER BY created_at",
+        filter
+    ))
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let mut returns: Vec<ReturnRecord> = stmt.query_map(params![key], |row| {
+        Ok(ReturnRecord {
+            return_id: row.get(0)?,
+            invoice_id: row.get(1)?,
+            reason: row.get(2)?,
+            refund_total: row.get(3)?,
+            restocked: row.get(4)?,
+            created_at: row.get(5)?,
+            items: Vec::new(),
+            tenders: Vec::new(),
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    for record in returns.iter_mut() {
+        load_return_items(conn, record)?;
+    }
+    Ok(returns)
+}
+
+pub fn load_return(conn: &Connection, return_id: &str) -> Result<Option<ReturnRecord>, String> {
+    Ok(load_returns_where(conn, "return_id", return_id)?.pop())
+}
+
+#[command]
+pub fn list_returns(window: Window, invoice_id: String) -> Result<Vec<ReturnRecord>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    load_returns_where(&conn, "invoice_id", &invoice_id)
+}
+
+commands![
+    create_return,
+    list_returns,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tender(method: &str, amount: f64) -> Tender {
+        Tender { method: method.to_string(), amount }
+    }
+
+    fn amounts(tenders: &[Tender]) -> Vec<(&str, f64)> {
+        tenders.iter().map(|t| (t.method.as_str(), t.amount)).collect()
+    }
+
+    #[test]
+    fn refunds_follow_the_tenders() {
+        let paid = [tender("cash", 60000.0), tender("account", 40000.0)];
+        assert_eq!(amounts(&split_refund(50000.0, &paid, 0.0)), vec![("cash", 30000.0), ("account", 20000.0)]);
+    }
+
+    #[test]
+    fn change_comes_out_of_cash() {
+        // 100k due, paid 50k card + 100k cash, 50k change: cash kept only 50k.
+        let paid = [tender("card", 50000.0), tender("cash", 100000.0)];
+        assert_eq!(amounts(&split_refund(10000.0, &paid, 50000.0)), vec![("card", 5000.0), ("cash", 5000.0)]);
+        // All change from cash leaves nothing to refund in cash.
+        let paid = [tender("cash", 20000.0), tender("qr", 100000.0)];
+        assert_eq!(amounts(&split_refund(10000.0, &paid, 20000.0)), vec![("qr", 10000.0)]);
+    }
+
+    #[test]
+    fn rounding_leftovers_go_to_the_largest_tender() {
+        let paid = [tender("cash", 5.0), tender("card", 3.0), tender("qr", 3.0)];
+        let split = split_refund(10.0, &paid, 0.0);
+        assert_eq!(round_money(split.iter().map(|t| t.amount).sum::<f64>()), 10.0);
+        assert_eq!(amounts(&split), vec![("cash", 4.54), ("card", 2.73), ("qr", 2.73)]);
+    }
+
+    #[test]
+    fn same_method_tenders_are_combined() {
+        let paid = [tender("cash", 10000.0), tender("cash", 10000.0)];
+        assert_eq!(amounts(&split_refund(5000.0, &paid, 0.0)), vec![("cash", 5000.0)]);
+        assert_eq!(amounts(&split_refund(5000.0, &[], 0.0)), vec![("cash", 5000.0)]);
+    }
+}
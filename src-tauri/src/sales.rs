@@ -0,0 +1,377 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, OptionalExtension, Result, Transaction};
+use tauri::{command, Window};
+use chrono::Utc;
+
+use crate::cart::get_db_path;
+use crate::reports::business_day_of;
+use crate::tax::{compute_cart_totals, TaxSummary};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Invoice {
+    pub invoice_id: String,
+    pub cart_id: i64,
+    pub cart_name: String,
+    pub store_id: String,
+    pub storeman_id: String,
+    pub status: String,
+    pub subtotal: f64,
+    pub discount: f64,
+    pub tax_total: f64,
+    pub prices_include_tax: bool,
+    pub total: f64,
+    pub change_due: f64,
+    pub created_at: String,
+    pub paid_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InvoiceItem {
+    pub invoice_id: String,
+    pub line_no: i64,
+    pub product_id: i64,
+    pub scanned_barcode: Option<String>,
+    pub quantity: f64,
+    pub price: f64,
+    pub purchasing_type: String,
+    pub discount: f64,
+    pub tax_rate: f64,
+    pub tax_amount: f64,
+    pub returned_quantity: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tender {
+    pub method: String, // "cash", "card", "qr", ...
+    pub amount: f64,
+}
+
+/// A promotion applied to one invoice line.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InvoiceDiscount {
+    pub promotion_id: i64,
+    pub name: String,
+    pub line_no: i64,
+    pub amount: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InvoiceDetail {
+    pub invoice: Invoice,
+    pub items: Vec<InvoiceItem>,
+    pub tenders: Vec<Tender>,
+    pub taxes: Vec<TaxSummary>,
+    pub discounts: Vec<InvoiceDiscount>,
+}
+
+// The bundled database already ships legacy `sales`/`sale_items` tables with an
+// incompatible layout, so completed carts are persisted as invoices instead.
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS invoices (
+            invoice_id VARCHAR(100) PRIMARY KEY,
+            cart_id INTEGER NOT NULL,
+            cart_name VARCHAR(100) NOT NULL DEFAULT '',
+            store_id VARCHAR(50) NOT NULL,
+            storeman_id VARCHAR(50) NOT NULL,
+            status VARCHAR(20) NOT NULL DEFAULT 'pending',
+            subtotal FLOAT NOT NULL DEFAULT 0,
+            discount FLOAT NOT NULL DEFAULT 0,
+            total FLOAT NOT NULL DEFAULT 0,
+            change_due FLOAT NOT NULL DEFAULT 0,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            paid_at DATETIME
+        )",
+        params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS invoice_items (
+            invoice_id VARCHAR(100) NOT NULL,
+            line_no INTEGER NOT NULL,
+            product_id INTEGER NOT NULL,
+            scanned_barcode VARCHAR(255),
+            quantity INTEGER NOT NULL,
+            price FLOAT NOT NULL,
+            purchasing_type VARCHAR(10) NOT NULL,
+            discount FLOAT DEFAULT 0,
+            PRIMARY KEY (invoice_id, line_no),
+            FOREIGN KEY (invoice_id) REFERENCES invoices(invoice_id)
+        )",
+        params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS invoice_tenders (
+            invoice_id VARCHAR(100) NOT NULL,
+            method VARCHAR(20) NOT NULL,
+            amount FLOAT NOT NULL,
+            FOREIGN KEY (invoice_id) REFERENCES invoices(invoice_id)
+        )",
+        params![],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_invoices_cart_id ON invoices(cart_id)", params![])?;
+    // Last number issued per invoice prefix, so voided or deleted invoices
+    // never cause a number to be reused.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS invoice_sequences (
+            prefix VARCHAR(150) PRIMARY KEY,
+            last_seq INTEGER NOT NULL
+        )",
+        params![],
+    )?;
+    Ok(())
+}
+
+pub fn round_money(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+// The first number under a prefix continues after invoices issued before
+// the counter existed.
+fn next_sequence(tx: &Transaction, prefix: &str) -> Result<i64, String> {
+    let last: Option<i64> = tx.query_row("SELECT last_seq FROM invoice_sequences WHERE prefix = ?1", params![prefix], |row| row.get(0))
+        .optional()
+        .map_err(|e| format!("Failed to read invoice sequence: {}", e))?;
+    let last = match last {
+        Some(last) => last,
+        None => tx.query_row(
+            "SELECT COALESCE(MAX(CAST(SUBSTR(invoice_id, ?2) AS INTEGER)), 0) FROM invoices WHERE invoice_id LIKE ?1 ESCAPE '\\'",
+            params![format!("{}%", escape_like(prefix)), prefix.chars().count() as i64 + 1],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to read invoice numbers: {}", e))?,
+    };
+    tx.execute(
+        "INSERT INTO invoice_sequences (prefix, last_seq) VALUES (?1, ?2)
+         ON CONFLICT(prefix) DO UPDATE SET last_seq = excluded.last_seq",
+        params![prefix, last + 1],
+    )
+    .map_err(|e| format!("Failed to save invoice sequence: {}", e))?;
+    Ok(last + 1)
+}
+
+/// Returns the pending invoice for a cart, creating one with the next daily
+/// sequence number when the cart is checked out for the first time. The
+/// invoice belongs to `shift_id` so the shift cannot close while it is unpaid.
+pub fn open_invoice(tx: &Transaction, cart_id: i64, store_id: &str, storeman_id: &str, shift_id: i64) -> Result<String, String> {
+    let existing: Option<String> = tx.query_row(
+        "SELECT invoice_id FROM invoices WHERE cart_id = ?1 AND status = 'pending'",
+        params![cart_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to look up invoice: {}", e))?;
+    if let Some(invoice_id) = existing {
+        tx.execute("UPDATE invoices SET shift_id = ?1 WHERE invoice_id = ?2", params![shift_id, invoice_id])
+            .map_err(|e| format!("Failed to update invoice: {}", e))?;
+        return Ok(invoice_id);
+    }
+
+    let cart_name: String = tx.query_row("SELECT cart_name FROM carts WHERE cart_id = ?1", params![cart_id], |row| row.get(0))
+        .map_err(|e| format!("Failed to load cart: {}", e))?;
+    let now = Utc::now();
+    // Numbered by business day, which is local like the Z-report.
+    let prefix = format!("{}_{}_{}_", store_id, storeman_id, business_day_of(&now.naive_utc()).format("%Y%m%d"));
+    let seq = next_sequence(tx, &prefix)?;
+    let invoice_id = format!("{}{:03}", prefix, seq);
+    tx.execute(
+        "INSERT INTO invoices (invoice_id, cart_id, cart_name, store_id, storeman_id, status, created_at, shift_id) VALUES (?1, ?2, ?3, ?4, ?5, 'pending', ?6, ?7)",
+        params![invoice_id, cart_id, cart_name, store_id, storeman_id, now.naive_utc().format("%Y-%m-%d %H:%M:%S").to_string(), shift_id],
+    )
+    .map_err(|e| format!("Failed to create invoice: {}", e))?;
+    Ok(invoice_id)
+}
+
+/// Voids the unpaid invoice of a cart that is being deleted, so it neither
+/// blocks closing the shift nor gets picked up by a later cart with the same id.
+pub fn void_pending_invoice(tx: &Transaction, cart_id: i64) -> Result<(), String> {
+    tx.execute("UPDATE invoices SET status = 'void' WHERE cart_id = ?1 AND status = 'pending'", params![cart_id])
+        .map_err(|e| format!("Failed to void invoice: {}", e))?;
+    Ok(())
+}
+
+/// Copies the cart lines and tenders into the pending invoice and marks it
+/// completed under the shift that took the payment. Must run in the same
+/// transaction that deletes the cart. Without explicit tenders the invoice is
+/// treated as paid exactly in cash.
+pub fn complete_invoice(tx: &Transaction, cart_id: i64, tenders: Option<Vec<Tender>>, shift_id: i64) -> Result<String, String> {
+    let invoice_id: String = tx.query_row(
+        "SELECT invoice_id FROM invoices WHERE cart_id = ?1 AND status = 'pending'",
+        params![cart_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to look up invoice: {}", e))?
+    .ok_or_else(|| "Cart has not been checked out".to_string())?;
+
+    let totals = compute_cart_totals(tx, cart_id)?;
+    tx.execute(
+        "INSERT INTO invoice_items (invoice_id, line_no, product_id, scanned_barcode, quantity, price, purchasing_type, discount)
+         SELECT ?1, ROW_NUMBER() OVER (ORDER BY rowid), product_id, scanned_barcode, quantity, price, purchasing_type, COALESCE(discount, 0)
+         FROM cart_items WHERE cart_id = ?2",
+        params![invoice_id, cart_id],
+    )
+    .map_err(|e| format!("Failed to record invoice items: {}", e))?;
+    // Line discounts include promotions so returns and margins see what was paid.
+    for (index, line) in totals.lines.iter().enumerate() {
+        tx.execute(
+            "UPDATE invoice_items SET discount = ?1, tax_rate = ?2, tax_amount = ?3 WHERE invoice_id = ?4 AND line_no = ?5",
+            params![line.discount, line.tax_rate, line.tax, invoice_id, index as i64 + 1],
+        )
+        .map_err(|e| format!("Failed to record line tax: {}", e))?;
+    }
+    for discount in &totals.discounts {
+        let line_no = totals.lines.iter().position(|l| l.item_id == discount.item_id).map(|i| i as i64 + 1).unwrap_or(0);
+        tx.execute(
+            "INSERT INTO invoice_discounts (invoice_id, promotion_id, name, line_no, amount) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![invoice_id, discount.promotion_id, discount.name, line_no, discount.amount],
+        )
+        .map_err(|e| format!("Failed to record promotion: {}", e))?;
+    }
+    for tax in &totals.taxes {
+        tx.execute(
+            "INSERT INTO invoice_taxes (invoice_id, rate, net, tax, gross) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![invoice_id, tax.rate, tax.net, tax.tax, tax.gross],
+        )
+        .map_err(|e| format!("Failed to record tax summary: {}", e))?;
+    }
+    let total = totals.total;
+
+    let tenders = tenders.unwrap_or_else(|| vec![Tender { method: "cash".to_string(), amount: total }]);
+    if tenders.iter().any(|t| t.amount < 0.0) {
+        return Err("Tender amounts cannot be negative".to_string());
+    }
+    let tendered = round_money(tenders.iter().map(|t| t.amount).sum());
+    if tendered < total {
+        return Err(format!("Insufficient payment: {} tendered, {} due", tendered, total));
+    }
+    for tender in &tenders {
+        tx.execute(
+            "INSERT INTO invoice_tenders (invoice_id, method, amount) VALUES (?1, ?2, ?3)",
+            params![invoice_id, tender.method, round_money(tender.amount)],
+        )
+        .map_err(|e| format!("Failed to record tender: {}", e))?;
+    }
+
+    let paid_at = Utc::now().naive_utc().format("%Y-%m-%d %H:%M:%S").to_string();
+    tx.execute(
+        "UPDATE invoices SET status = 'completed', subtotal = ?1, discount = ?2, tax_total = ?3, prices_include_tax = ?4, total = ?5, change_due = ?6, paid_at = ?7, shift_id = ?8
+         WHERE invoice_id = ?9",
+        params![
+            totals.subtotal, totals.discount, totals.tax_total, totals.prices_include_tax, total,
+            round_money(tendered - total), paid_at, shift_id, invoice_id
+        ],
+    )
+    .map_err(|e| format!("Failed to complete invoice: {}", e))?;
+    Ok(invoice_id)
+}
+
+pub fn load_invoice(conn: &Connection, invoice_id: &str) -> Result<InvoiceDetail, String> {
+    let invoice = conn.query_row(
+        "SELECT invoice_id, cart_id, cart_name, store_id, storeman_id, status, subtotal, discount, tax_total, prices_include_tax, total, change_due, created_at, paid_at
+         FROM invoices WHERE invoice_id = ?1",
+        params![invoice_id],
+        |row| {
+            Ok(Invoice {
+                invoice_id: row.get(0)?,
+                cart_id: row.get(1)?,
+                cart_name: row.get(2)?,
+                store_id: row.get(3)?,
+                storeman_id: row.get(4)?,
+                status: row.get(5)?,
+                subtotal: row.get(6)?,
+                discount: row.get(7)?,
+                tax_total: row.get(8)?,
+                prices_include_tax: row.get(9)?,
+                total: row.get(10)?,
+                change_due: row.get(11)?,
+                created_at: row.get(12)?,
+                paid_at: row.get(13)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| format!("Failed to load invoice: {}", e))?
+    .ok_or_else(|| format!("Invoice {} not found", invoice_id))?;
+
+    let mut stmt = conn.prepare(
+        "SELECT i.invoice_id, i.line_no, i.product_id, i.scanned_barcode, i.quantity, i.price, i.purchasing_type, i.discount, i.tax_rate, i.tax_amount,
+                COALESCE((SELECT SUM(ri.quantity) FROM return_items ri JOIN returns r ON r.return_id = ri.return_id
+                          WHERE r.invoice_id = i.invoice_id AND ri.line_no = i.line_no), 0)
+         FROM invoice_items i WHERE i.invoice_id = ?1 ORDER BY i.line_no",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let items = stmt.query_map(params![invoice_id], |row| {
+        Ok(InvoiceItem {
+            invoice_id: row.get(0)?,
+            line_no: row.get(1)?,
+            product_id: row.get(2)?,
+            scanned_barcode: row.get(3).ok(),
+            quantity: row.get(4)?,
+            price: row.get(5)?,
+            purchasing_type: row.get(6)?,
+            discount: row.get(7)?,
+            tax_rate: row.get(8)?,
+            tax_amount: row.get(9)?,
+            returned_quantity: row.get(10)?,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+
+    let mut stmt = conn.prepare("SELECT method, amount FROM invoice_tenders WHERE invoice_id = ?1 ORDER BY rowid")
+        .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let tenders = stmt.query_map(params![invoice_id], |row| {
+        Ok(Tender {
+            method: row.get(0)?,
+            amount: row.get(1)?,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+
+    let mut stmt = conn.prepare("SELECT rate, net, tax, gross FROM invoice_taxes WHERE invoice_id = ?1 ORDER BY rate")
+        .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let taxes = stmt.query_map(params![invoice_id], |row| {
+        Ok(TaxSummary {
+            rate: row.get(0)?,
+            net: row.get(1)?,
+            tax: row.get(2)?,
+            gross: row.get(3)?,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+
+    let mut stmt = conn.prepare("SELECT promotion_id, name, line_no, amount FROM invoice_discounts WHERE invoice_id = ?1 ORDER BY line_no, rowid")
+        .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let discounts = stmt.query_map(params![invoice_id], |row| {
+        Ok(InvoiceDiscount {
+            promotion_id: row.get(0)?,
+            name: row.get(1)?,
+            line_no: row.get(2)?,
+            amount: row.get(3)?,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(InvoiceDetail { invoice, items, tenders, taxes, discounts })
+}
+
+#[command]
+pub fn get_invoice(window: Window, invoice_id: String) -> Result<InvoiceDetail, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    load_invoice(&conn, &invoice_id)
+}
+
+commands![
+    get_invoice,
+];
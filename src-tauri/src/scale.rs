@@ -0,0 +1,139 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, Result};
+use tauri::{command, Window};
+
+use crate::barcode::gs1_check_digit;
+use crate::cart::get_db_path;
+use crate::db::add_column_if_missing;
+
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    add_column_if_missing(conn, "products", "Plu", "VARCHAR(10)")?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_products_plu ON products(Plu)", params![])?;
+    Ok(())
+}
+
+// Units sold by measure; any other unit only takes whole quantities.
+const MEASURE_UNITS: [&str; 10] = ["kg", "g", "gram", "lạng", "l", "lít", "ml", "m", "mét", "cm"];
+
+pub fn is_measure_unit(unit: &str) -> bool {
+    let unit = unit.trim().to_lowercase();
+    MEASURE_UNITS.contains(&unit.as_str())
+}
+
+/// Checks a quantity for a line of the given unit: positive, and whole unless
+/// the unit is a weight or length.
+pub fn validate_quantity(quantity: f64, unit: Option<&str>) -> Result<(), String> {
+    if !quantity.is_finite() || quantity <= 0.0 {
+        return Err("Quantity must be positive".to_string());
+    }
+    if quantity.fract() != 0.0 && !unit.map_or(false, is_measure_unit) {
+        return Err(format!("Items sold by {} need a whole quantity", unit.unwrap_or("unit")));
+    }
+    Ok(())
+}
+
+/// Quantities are kept to grams / millimetres.
+pub fn round_quantity(quantity: f64) -> f64 {
+    (quantity * 1000.0).round() / 1000.0
+}
+
+/// What the value digits of an in-store barcode carry.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddedValue {
+    Weight, // grams
+    Price,  // VND
+}
+
+/// An in-store EAN-13 layout: 2-digit prefix, PLU, value, check digit.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EmbeddedFormat {
+    pub prefix: String,
+    pub value: EmbeddedValue,
+    pub plu_digits: usize, // 4 or 5; the value takes the remaining digits
+}
+
+pub fn default_formats() -> Vec<EmbeddedFormat> {
+    (20..=29)
+        .map(|prefix| EmbeddedFormat {
+            prefix: prefix.to_string(),
+            value: if prefix < 25 { EmbeddedValue::Weight } else { EmbeddedValue::Price },
+            plu_digits: 5,
+        })
+        .collect()
+}
+
+pub fn validate_formats(formats: &[EmbeddedFormat]) -> Result<(), String> {
+    for format in formats {
+        if format.prefix.len() != 2 || !format.prefix.starts_with('2') || !format.prefix.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("In-store barcode prefix {} must be 20-29", format.prefix));
+        }
+        if !(4..=5).contains(&format.plu_digits) {
+            return Err("In-store barcode PLU must have 4 or 5 digits".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EmbeddedCode {
+    pub plu: String,
+    pub value: EmbeddedValue,
+    pub amount: f64, // grams or VND
+}
+
+/// Splits an in-store EAN-13 into PLU and weight or price. Returns `None` for
+/// codes that are not in-store or fail the check digit.
+pub fn parse_embedded(code: &str, formats: &[EmbeddedFormat]) -> Option<EmbeddedCode> {
+    let code = code.trim();
+    if code.len() != 13 || !code.chars().all(|c| c.is_ascii_digit()) || gs1_check_digit(&code[..12]) != code[12..].parse::<u32>().ok()? {
+        return None;
+    }
+    let format = formats.iter().find(|f| code.starts_with(f.prefix.as_str()))?;
+    let plu = code[2..2 + format.plu_digits].trim_start_matches('0');
+    let amount: f64 = code[2 + format.plu_digits..12].parse().ok()?;
+    Some(EmbeddedCode {
+        plu: if plu.is_empty() { "0".to_string() } else { plu.to_string() },
+        value: format.value,
+        amount,
+    })
+}
+
+/// Grams per unit for weighed items; weights on other units are read as
+/// thousandths of the unit.
+pub fn grams_per_unit(unit: &str) -> f64 {
+    match unit.trim().to_lowercase().as_str() {
+        "g" | "gram" => 1.0,
+        "lạng" => 100.0,
+        _ => 1000.0,
+    }
+}
+
+/// Sets the PLU printed into in-store barcodes by the scale; `None` clears it.
+#[command]
+pub fn set_product_plu(window: Window, product_id: i64, plu: Option<String>) -> Result<(), String> {
+    let plu = plu.map(|p| p.trim().trim_start_matches('0').to_string()).filter(|p| !p.is_empty());
+    if plu.as_ref().map_or(false, |p| p.len() > 5 || !p.chars().all(|c| c.is_ascii_digit())) {
+        return Err("PLU must be up to 5 digits".to_string());
+    }
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    if let Some(plu) = &plu {
+        let taken: Option<String> = conn
+            .query_row("SELECT Item_name FROM products WHERE Plu = ?1 AND rowid != ?2", params![plu, product_id], |row| row.get(0))
+            .ok();
+        if let Some(name) = taken {
+            return Err(format!("PLU {} is already used by {}", plu, name));
+        }
+    }
+    let rows = conn.execute("UPDATE products SET Plu = ?1 WHERE rowid = ?2", params![plu, product_id])
+        .map_err(|e| format!("Failed to update product: {}", e))?;
+    if rows == 0 {
+        return Err("Product not found".to_string());
+    }
+    Ok(())
+}
+
+commands![
+    set_product_plu,
+];
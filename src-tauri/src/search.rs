@@ -1,6 +1,13 @@
 use serde::{Serialize, Deserialize};
-use rusqlite::{params, Connection, Result};
-use tauri::{command, Window, Manager};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::{command, Window};
+
+use crate::barcode::normalize;
+use crate::cart::get_db_path;
+use crate::receipt::strip_accents;
+use crate::scale::{grams_per_unit, is_measure_unit, parse_embedded, round_quantity, EmbeddedValue};
+use crate::settings::Settings;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Product {
@@ -13,29 +20,167 @@ pub struct Product {
     pub unit: Option<String>,
 }
 
+/// A product found by an exact barcode scan. Carton codes resolve to the
+/// bulk unit and price.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScanMatch {
+    pub product_id: i64,
+    pub name: String,
+    pub scanned_barcode: String,
+    pub purchasing_type: String, // "single" or "bulk"
+    pub price: f64,
+    pub unit: Option<String>,
+    pub quantity: Option<f64>, // weight read from an in-store barcode
+}
+
+// Barcode cells may hold several comma-separated codes for the same product.
+const CODE_MATCH: &str = "',' || REPLACE({col}, ' ', '') || ',' LIKE '%,' || ?1 || ',%'";
+
+fn find_by_codes(conn: &Connection, keys: &[String], scanned: &str) -> Result<Option<ScanMatch>, String> {
+    for (column, purchasing_type) in [("Barcode", "single"), ("Bulk_code", "bulk")] {
+        let sql = format!(
+            "SELECT rowid, Item_name, Retail_price, Bulk_price, Unit, Bulk_unit FROM products WHERE {} ORDER BY rowid LIMIT 1",
+            CODE_MATCH.replace("{col}", column)
+        );
+        for key in keys {
+            if key.contains(',') || key.contains('%') || key.contains('_') {
+                continue;
+            }
+            let found: Option<(i64, String, Option<f64>, Option<f64>, Option<String>, Option<String>)> = conn
+                .query_row(&sql, params![key], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)))
+                .optional()
+                .map_err(|e| format!("Failed to look up barcode: {}", e))?;
+            if let Some((product_id, name, retail_price, bulk_price, unit, bulk_unit)) = found {
+                let (price, unit) = if purchasing_type == "bulk" { (bulk_price, bulk_unit) } else { (retail_price, unit) };
+                let price = price.filter(|p| *p > 0.0)
+                    .ok_or_else(|| format!("{} has no {} price", name, if purchasing_type == "bulk" { "bulk" } else { "retail" }))?;
+                return Ok(Some(ScanMatch {
+                    product_id,
+                    name,
+                    scanned_barcode: scanned.to_string(),
+                    purchasing_type: purchasing_type.to_string(),
+                    price,
+                    unit,
+                    quantity: None,
+                }));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Resolves a scan against `Barcode` and `Bulk_code`, then in-store codes.
+/// A code failing its check digit is reported as a misread unless the
+/// catalog holds it verbatim (older entries were never validated).
+pub fn resolve_barcode(conn: &Connection, barcode: &str) -> Result<Option<ScanMatch>, String> {
+    let raw = barcode.trim();
+    if raw.is_empty() {
+        return Ok(None);
+    }
+    let code = match normalize(raw) {
+        Ok(code) => code,
+        Err(e) => return find_by_codes(conn, &[raw.to_string()], raw)?.map(Some).ok_or(e),
+    };
+    if let Some(found) = find_by_codes(conn, &code.lookup_keys(), &code.code)? {
+        return Ok(Some(found));
+    }
+    resolve_embedded(conn, &code.code)
+}
+
+/// Looks up an in-store barcode by PLU. Weight codes give the quantity at the
+/// retail price; price codes derive the quantity and keep the label total.
+/// Products sold in count units reject weight labels and price labels that
+/// are not worth a whole number of items.
+fn resolve_embedded(conn: &Connection, code: &str) -> Result<Option<ScanMatch>, String> {
+    let settings = Settings::load(conn)?;
+    let embedded = match parse_embedded(code, &settings.embedded_barcodes) {
+        Some(embedded) => embedded,
+        None => return Ok(None),
+    };
+    let found: Option<(i64, String, Option<f64>, Option<String>)> = conn
+        .query_row(
+            "SELECT rowid, Item_name, Retail_price, Unit FROM products WHERE Plu = ?1 ORDER BY rowid LIMIT 1",
+            params![embedded.plu],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to look up PLU: {}", e))?;
+    let (product_id, name, retail_price, unit) = found.ok_or_else(|| format!("No product with PLU {}", embedded.plu))?;
+    let retail_price = retail_price.filter(|p| *p > 0.0).ok_or_else(|| format!("{} has no retail price", name))?;
+    // Lines in count units take whole quantities only (see `validate_quantity`).
+    let weighed = unit.as_deref().is_some_and(is_measure_unit);
+    let unit_name = unit.as_deref().unwrap_or("unit");
+    let (quantity, price) = match embedded.value {
+        EmbeddedValue::Weight => {
+            if !weighed {
+                return Err(format!("{} is sold by {}, not by weight; check the PLU {} on the scale", name, unit_name, embedded.plu));
+            }
+            let quantity = round_quantity(embedded.amount / grams_per_unit(unit_name));
+            (quantity, retail_price)
+        }
+        EmbeddedValue::Price => {
+            let quantity = round_quantity(embedded.amount / retail_price);
+            if quantity <= 0.0 {
+                return Err(format!("Label price {} is too small for {}", embedded.amount, name));
+            }
+            if !weighed {
+                let count = embedded.amount / retail_price;
+                if (count - count.round()).abs() > 1e-6 {
+                    return Err(format!(
+                        "Label price {} is not a whole number of {} of {} at {}",
+                        embedded.amount, unit_name, name, retail_price
+                    ));
+                }
+                (count.round(), retail_price)
+            } else {
+                (quantity, embedded.amount / quantity)
+            }
+        }
+    };
+    if quantity <= 0.0 {
+        return Err(format!("Label weight for {} is zero", name));
+    }
+    Ok(Some(ScanMatch {
+        product_id,
+        name,
+        scanned_barcode: code.to_string(),
+        purchasing_type: "single".to_string(),
+        price,
+        unit,
+        quantity: Some(quantity),
+    }))
+}
+
 #[command]
-pub fn search_products(window: Window, query: String) -> Result<Vec<Product>, String> {
-    let db_path = tauri::api::path::app_data_dir(&window.app_handle().config())
-        .map(|mut dir| { dir.push("inventory.db"); dir })
-        .expect("Failed to get app data dir");
-    println!("Searching database at: {:?}", db_path);
+pub fn lookup_barcode(window: Window, barcode: String) -> Result<Option<ScanMatch>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    resolve_barcode(&conn, &barcode)
+}
 
-    let conn = Connection::open(&db_path)
-        .map_err(|e| {
-            println!("Database open error: {}", e);
-            format!("Failed to open DB: {}", e)
-        })?;
+/// Matches name and codes without regard to case or Vietnamese accents, so
+/// "mi hao hao" finds "Mì Hảo Hảo". Returns at most 50 products.
+#[command]
+pub fn search_products(window: Window, query: String) -> Result<Vec<Product>, String> {
+    let query = strip_accents(query.trim()).to_lowercase();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    conn.create_scalar_function("fold", 1, FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC, |ctx| {
+        Ok(ctx.get::<Option<String>>(0)?.map(|text| strip_accents(&text).to_lowercase()))
+    })
+    .map_err(|e| format!("Failed to register search function: {}", e))?;
 
     let mut stmt = conn.prepare(
-        "SELECT rowid, Item_name, Barcode, Retail_price, Bulk_price, Bulk_single_conversion, Unit FROM products WHERE Item_name LIKE ?1 OR Barcode LIKE ?1"
+        "SELECT rowid, Item_name, Barcode, Retail_price, Bulk_price, Bulk_single_conversion, Unit FROM products
+         WHERE fold(Item_name) LIKE ?1 OR Barcode LIKE ?1 OR Bulk_code LIKE ?1 LIMIT 50"
     )
-    .map_err(|e| {
-        println!("Prepare statement error: {}", e);
-        format!("Failed to prepare statement: {}", e)
-    })?;
+    .map_err(|e| format!("Failed to prepare statement: {}", e))?;
 
     let products: Vec<_> = stmt.query_map(params![format!("%{}%", query)], |row| {
-        let product = Product {
+        Ok(Product {
             product_id: row.get(0)?,
             name: row.get(1)?,
             barcode: row.get(2)?,
@@ -43,17 +188,16 @@ pub fn search_products(window: Window, query: String) -> Result<Vec<Product>, St
             bulk_price: row.get(4)?,
             bulk_single_conversion: row.get(5)?,
             unit: row.get(6)?,
-        };
-        println!("Found product: {:?}", product);
-        Ok(product)
+        })
     })
-    .map_err(|e| {
-        println!("Query execution error: {}", e);
-        format!("Failed to query: {}", e)
-    })?
+    .map_err(|e| format!("Failed to query: {}", e))?
     .filter_map(|r| r.ok())
     .collect();
 
-    println!("Search returned {} products", products.len());
     Ok(products)
-}
\ No newline at end of file
+}
+
+commands![
+    search_products,
+    lookup_barcode,
+];
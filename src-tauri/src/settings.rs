@@ -0,0 +1,250 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::{command, Manager, State, Window};
+use chrono::Utc;
+
+use crate::backup::BackupPolicy;
+use crate::cart::get_db_path;
+use crate::cart_expiry::{DEFAULT_ACTIVE_TTL_MINUTES, DEFAULT_PARKED_TTL_MINUTES, MAX_ACTIVE_TTL_MINUTES, MAX_PARKED_TTL_MINUTES};
+use crate::catalog::{validate_internal_prefix, DEFAULT_INTERNAL_PREFIX};
+use crate::customers::LoyaltyRules;
+use crate::scale::{default_formats, validate_formats, EmbeddedFormat};
+use crate::printer::{default_printer, PrintQueue, PrinterConfig};
+use crate::sync::SyncConfig;
+
+/// Backend settings. Each field is stored as its own row in `app_settings`,
+/// so settings added by later versions fall back to their defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub store_id: String,
+    pub storeman_id: String,
+    pub store_name: String,
+    pub cart_ttl_active_minutes: i64,
+    pub cart_ttl_parked_minutes: i64,
+    pub printer: Option<PrinterConfig>, // None prints to the spool directory
+    pub tax_rate: f64,                  // percent, for products without a tax class
+    pub prices_include_tax: bool,
+    pub embedded_barcodes: Vec<EmbeddedFormat>, // in-store EAN-13 layouts by prefix
+    pub internal_barcode_prefix: String,        // for codes generated for unlabeled products
+    pub loyalty: LoyaltyRules,
+    // Shared by the store's tills to sign cart transfers and sync. Never sent
+    // to the webview; changed only through `set_terminal_secret`.
+    #[serde(skip)]
+    pub terminal_secret: String,
+    pub sync: SyncConfig,        // applied on the next start
+    pub backup: BackupPolicy,
+    pub auto_repair: bool, // repair a damaged database in place at startup; off reports only
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            store_id: "default_store".to_string(),
+            storeman_id: "default_storeman".to_string(),
+            store_name: String::new(),
+            cart_ttl_active_minutes: DEFAULT_ACTIVE_TTL_MINUTES,
+            cart_ttl_parked_minutes: DEFAULT_PARKED_TTL_MINUTES,
+            printer: None,
+            tax_rate: 10.0,
+            prices_include_tax: true,
+            embedded_barcodes: default_formats(),
+            internal_barcode_prefix: DEFAULT_INTERNAL_PREFIX.to_string(),
+            loyalty: LoyaltyRules::default(),
+            terminal_secret: String::new(),
+            sync: SyncConfig::default(),
+            backup: BackupPolicy::default(),
+            auto_repair: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.store_id.trim().is_empty() {
+            return Err("Store id is required".to_string());
+        }
+        if self.storeman_id.trim().is_empty() {
+            return Err("Storeman id is required".to_string());
+        }
+        if !(1..=MAX_ACTIVE_TTL_MINUTES).contains(&self.cart_ttl_active_minutes) {
+            return Err(format!("Active cart TTL must be between 1 and {} minutes", MAX_ACTIVE_TTL_MINUTES));
+        }
+        if !(1..=MAX_PARKED_TTL_MINUTES).contains(&self.cart_ttl_parked_minutes) {
+            return Err(format!("Parked cart TTL must be between 1 and {} minutes", MAX_PARKED_TTL_MINUTES));
+        }
+        if !(0.0..=100.0).contains(&self.tax_rate) {
+            return Err("Tax rate must be between 0 and 100 percent".to_string());
+        }
+        validate_formats(&self.embedded_barcodes)?;
+        validate_internal_prefix(&self.internal_barcode_prefix)?;
+        self.loyalty.validate()?;
+        self.sync.validate(&self.terminal_secret)?;
+        self.backup.validate()?;
+        match &self.printer {
+            Some(PrinterConfig::Device { path }) if path.trim().is_empty() => Err("Printer device path is required".to_string()),
+            Some(PrinterConfig::Tcp { host, .. }) if host.trim().is_empty() => Err("Printer host is required".to_string()),
+            Some(PrinterConfig::Spool { dir }) if dir.trim().is_empty() => Err("Spool directory is required".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads the stored settings. Missing or unparsable values take their
+    /// default so a bad row never keeps the app from starting.
+    pub fn load(conn: &Connection) -> Result<Settings, String> {
+        let defaults = Settings::default();
+        let printer = match get_setting(conn, "printer")? {
+            Some(json) => serde_json::from_str(&json).ok(),
+            None => None,
+        };
+        Ok(Settings {
+            store_id: get_setting(conn, "store_id")?.unwrap_or(defaults.store_id),
+            storeman_id: get_setting(conn, "storeman_id")?.unwrap_or(defaults.storeman_id),
+            store_name: get_setting(conn, "store_name")?.unwrap_or(defaults.store_name),
+            cart_ttl_active_minutes: get_setting(conn, "cart_ttl_active_minutes")?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.cart_ttl_active_minutes),
+            cart_ttl_parked_minutes: get_setting(conn, "cart_ttl_parked_minutes")?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.cart_ttl_parked_minutes),
+            printer,
+            tax_rate: get_setting(conn, "tax_rate")?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.tax_rate),
+            prices_include_tax: get_setting(conn, "prices_include_tax")?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.prices_include_tax),
+            embedded_barcodes: get_setting(conn, "embedded_barcodes")?
+                .and_then(|v| serde_json::from_str(&v).ok())
+                .unwrap_or(defaults.embedded_barcodes),
+            internal_barcode_prefix: get_setting(conn, "internal_barcode_prefix")?.unwrap_or(defaults.internal_barcode_prefix),
+            loyalty: get_setting(conn, "loyalty")?
+                .and_then(|v| serde_json::from_str(&v).ok())
+                .unwrap_or(defaults.loyalty),
+            terminal_secret: get_setting(conn, "terminal_secret")?.unwrap_or(defaults.terminal_secret),
+            sync: get_setting(conn, "sync")?
+                .and_then(|v| serde_json::from_str(&v).ok())
+                .unwrap_or(defaults.sync),
+            backup: get_setting(conn, "backup")?
+                .and_then(|v| serde_json::from_str(&v).ok())
+                .unwrap_or(defaults.backup),
+            auto_repair: get_setting(conn, "auto_repair")?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.auto_repair),
+        })
+    }
+
+    pub fn save(&self, conn: &mut Connection) -> Result<(), String> {
+        let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+        self.write(&tx)?;
+        tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))
+    }
+
+    /// Writes every setting through `conn`, for callers that need them in
+    /// their own transaction.
+    pub fn write(&self, conn: &Connection) -> Result<(), String> {
+        self.validate()?;
+        let printer = match &self.printer {
+            Some(config) => Some(serde_json::to_string(config).map_err(|e| format!("Failed to encode printer: {}", e))?),
+            None => None,
+        };
+        set_setting(conn, "store_id", self.store_id.trim())?;
+        set_setting(conn, "storeman_id", self.storeman_id.trim())?;
+        set_setting(conn, "store_name", &self.store_name)?;
+        set_setting(conn, "cart_ttl_active_minutes", &self.cart_ttl_active_minutes.to_string())?;
+        set_setting(conn, "cart_ttl_parked_minutes", &self.cart_ttl_parked_minutes.to_string())?;
+        set_setting(conn, "tax_rate", &self.tax_rate.to_string())?;
+        set_setting(conn, "prices_include_tax", &self.prices_include_tax.to_string())?;
+        let embedded = serde_json::to_string(&self.embedded_barcodes).map_err(|e| format!("Failed to encode barcode formats: {}", e))?;
+        set_setting(conn, "embedded_barcodes", &embedded)?;
+        set_setting(conn, "internal_barcode_prefix", &self.internal_barcode_prefix)?;
+        let loyalty = serde_json::to_string(&self.loyalty).map_err(|e| format!("Failed to encode loyalty rules: {}", e))?;
+        set_setting(conn, "loyalty", &loyalty)?;
+        set_setting(conn, "terminal_secret", &self.terminal_secret)?;
+        let sync = serde_json::to_string(&self.sync).map_err(|e| format!("Failed to encode sync settings: {}", e))?;
+        set_setting(conn, "sync", &sync)?;
+        let backup = serde_json::to_string(&self.backup).map_err(|e| format!("Failed to encode backup policy: {}", e))?;
+        set_setting(conn, "backup", &backup)?;
+        set_setting(conn, "auto_repair", &self.auto_repair.to_string())?;
+        match printer {
+            Some(json) => set_setting(conn, "printer", &json)?,
+            None => {
+                conn.execute("DELETE FROM app_settings WHERE key = 'printer'", params![])
+                    .map_err(|e| format!("Failed to save setting printer: {}", e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The bundled database carries a legacy single-row `settings` table owned by
+// the old web build, so backend settings live in their own key/value table.
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS app_settings (
+            key VARCHAR(100) PRIMARY KEY,
+            value TEXT NOT NULL,
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        params![],
+    )?;
+    Ok(())
+}
+
+pub fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>, String> {
+    conn.query_row("SELECT value FROM app_settings WHERE key = ?1", params![key], |row| row.get(0))
+        .optional()
+        .map_err(|e| format!("Failed to read setting {}: {}", key, e))
+}
+
+pub fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<(), String> {
+    let updated_at = Utc::now().naive_utc().format("%Y-%m-%d %H:%M:%S").to_string();
+    conn.execute(
+        "INSERT INTO app_settings (key, value, updated_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+        params![key, value, updated_at],
+    )
+    .map_err(|e| format!("Failed to save setting {}: {}", key, e))?;
+    Ok(())
+}
+
+/// Saves the settings and notifies every window with `settings-changed`.
+pub fn store_settings(window: &Window, settings: &Settings) -> Result<(), String> {
+    let db_path = get_db_path(window);
+    let mut conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    settings.save(&mut conn)?;
+    let _ = window.emit_all("settings-changed", settings.clone());
+    Ok(())
+}
+
+#[command]
+pub fn get_settings(window: Window) -> Result<Settings, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    Settings::load(&conn)
+}
+
+#[command]
+pub fn update_settings(window: Window, queue: State<'_, PrintQueue>, settings: Settings) -> Result<Settings, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut settings = settings;
+    settings.terminal_secret = Settings::load(&conn)?.terminal_secret;
+    store_settings(&window, &settings)?;
+    queue.set_config(settings.printer.clone().unwrap_or_else(|| default_printer(&get_db_path(&window))));
+    Ok(settings)
+}
+
+#[command]
+pub fn set_terminal_secret(window: Window, secret: String) -> Result<(), String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut settings = Settings::load(&conn)?;
+    settings.terminal_secret = secret.trim().to_string();
+    store_settings(&window, &settings)
+}
+
+commands![
+    get_settings,
+    update_settings,
+    set_terminal_secret,
+];
@@ -0,0 +1,338 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use std::path::{Path, PathBuf};
+use tauri::{command, AppHandle, Manager, State, Window};
+use uuid::Uuid;
+
+use crate::cart::{db_path_from_config, get_db_path, now_string};
+use crate::catalog::{insert_product, NewProduct};
+use crate::printer::{default_printer, PrintQueue};
+use crate::settings::{get_setting, initialize_tables as initialize_settings, set_setting, Settings};
+
+// Same layout as the database shipped with the app, so a store that started
+// empty can later be handed the bundled catalog without a migration.
+const LEGACY_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS products (
+        Barcode VARCHAR,
+        Item_name VARCHAR,
+        Category VARCHAR,
+        Unit VARCHAR,
+        Bulk_unit VARCHAR,
+        Bulk_code VARCHAR,
+        Bulk_single_conversion FLOAT,
+        Retail_price FLOAT,
+        Bulk_price FLOAT,
+        Cost FLOAT
+    );
+    CREATE TABLE IF NOT EXISTS users (
+        id TEXT PRIMARY KEY,
+        username TEXT UNIQUE NOT NULL,
+        password TEXT NOT NULL,
+        role TEXT NOT NULL CHECK (role IN ('admin', 'cashier')),
+        last_login DATETIME,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );
+    CREATE INDEX IF NOT EXISTS idx_products_barcode ON products(Barcode);
+    CREATE INDEX IF NOT EXISTS idx_products_item_name ON products(Item_name);
+    CREATE INDEX IF NOT EXISTS idx_products_category ON products(Category COLLATE NOCASE);
+    CREATE INDEX IF NOT EXISTS idx_products_search ON products(Item_name COLLATE NOCASE, Category COLLATE NOCASE);
+    CREATE INDEX IF NOT EXISTS idx_products_category_name ON products(Category, Item_name COLLATE NOCASE);
+";
+
+const CSV_COLUMNS: [&str; 10] = [
+    "barcode", "item_name", "category", "unit", "bulk_unit", "bulk_code",
+    "bulk_single_conversion", "retail_price", "bulk_price", "cost",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetupStatus {
+    pub needs_setup: bool,        // started from an empty database and the wizard has not run
+    pub created_empty: bool,
+    pub completed_at: Option<String>,
+    pub store_id: String,
+    pub store_name: String,
+    pub has_admin: bool,
+    pub product_count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetupRequest {
+    pub store_name: String,
+    pub store_id: String,
+    pub storeman_id: Option<String>, // keeps the current one when None
+    pub admin_username: String,
+    pub admin_password: String,
+    pub seed_csv: Option<String>,    // product list to import into the empty catalog
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CsvSeed {
+    pub created: usize,
+    pub skipped: Vec<String>, // "line N: reason"
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetupResult {
+    pub settings: Settings,
+    pub admin_id: String,
+    pub seed: Option<CsvSeed>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserInfo {
+    pub id: String,
+    pub username: String,
+    pub role: String,
+}
+
+fn table_exists(conn: &Connection, table: &str) -> Result<bool, String> {
+    conn.query_row("SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)", params![table], |row| row.get(0))
+        .map_err(|e| format!("Failed to read schema: {}", e))
+}
+
+/// Creates the tables the bundled database would have provided and marks the
+/// database for the setup wizard. Tables owned by the backend modules are
+/// created by their own `initialize_tables` afterwards.
+fn create_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(LEGACY_SCHEMA).map_err(|e| format!("Failed to create database schema: {}", e))?;
+    initialize_settings(conn).map_err(|e| format!("Failed to initialize settings tables: {}", e))?;
+    set_setting(conn, "created_empty_at", &now_string())
+}
+
+/// Finds or creates the database before anything opens it. Never fails: the
+/// bundled copy is used when present, otherwise an empty database is created,
+/// and anything that still goes wrong is logged for the startup check.
+pub fn prepare_database(app: &AppHandle) -> PathBuf {
+    let db_path = db_path_from_config(&app.config());
+    if let Some(parent) = db_path.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            println!("[setup] Failed to create {}: {}", parent.display(), e);
+        }
+    }
+    if !db_path.exists() {
+        match app.path_resolver().resolve_resource("inventory.db") {
+            Some(resource) => {
+                if let Err(e) = std::fs::copy(&resource, &db_path) {
+                    println!("[setup] Failed to copy the bundled database: {}", e);
+                }
+            }
+            None => println!("[setup] No bundled database, starting with an empty one"),
+        }
+    }
+    if let Err(e) = ensure_schema(&db_path) {
+        println!("[setup] {}", e);
+    }
+    db_path
+}
+
+// An empty or missing file (including an empty bundled copy) gets the schema;
+// a file that cannot be read is left to the integrity check.
+fn ensure_schema(db_path: &Path) -> Result<(), String> {
+    let conn = Connection::open(db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    if !table_exists(&conn, "products")? {
+        create_schema(&conn)?;
+    }
+    Ok(())
+}
+
+// Same cost as the users shipped in the bundled database.
+const BCRYPT_COST: u32 = 10;
+
+fn hash_password(password: &str) -> Result<String, String> {
+    bcrypt::hash(password, BCRYPT_COST).map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+// bcrypt compares in constant time; a malformed hash never matches.
+fn verify_password(stored: &str, password: &str) -> bool {
+    bcrypt::verify(password, stored).unwrap_or(false)
+}
+
+pub fn create_user(conn: &Connection, username: &str, password: &str, role: &str) -> Result<String, String> {
+    let username = username.trim();
+    if username.is_empty() {
+        return Err("Username is required".to_string());
+    }
+    if password.chars().count() < 4 {
+        return Err("Password must be at least 4 characters".to_string());
+    }
+    let taken: bool = conn
+        .query_row("SELECT EXISTS(SELECT 1 FROM users WHERE username = ?1)", params![username], |row| row.get(0))
+        .map_err(|e| format!("Failed to read users: {}", e))?;
+    if taken {
+        return Err(format!("User {} already exists", username));
+    }
+    let id = Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO users (id, username, password, role, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![id, username, hash_password(password)?, role, now_string()],
+    )
+    .map_err(|e| format!("Failed to create user: {}", e))?;
+    Ok(id)
+}
+
+// Splits one CSV line; quoted fields may contain commas and doubled quotes.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+/// Imports products from a CSV whose header uses the `products` column names
+/// (any case). Rows that fail validation are skipped and reported.
+pub fn seed_products(conn: &Connection, path: &Path) -> Result<CsvSeed, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let header: Vec<String> = match lines.next() {
+        Some((_, line)) => split_csv_line(line.trim_start_matches('\u{feff}')).into_iter().map(|h| h.to_lowercase()).collect(),
+        None => return Err("The CSV file is empty".to_string()),
+    };
+    let index: Vec<Option<usize>> = CSV_COLUMNS.iter().map(|c| header.iter().position(|h| h == c)).collect();
+    if index[1].is_none() || index[7].is_none() {
+        return Err("The CSV needs Item_name and Retail_price columns".to_string());
+    }
+
+    let mut seed = CsvSeed::default();
+    for (line_no, line) in lines {
+        let fields = split_csv_line(line);
+        let text = |i: usize| {
+            index[i].and_then(|at| fields.get(at)).filter(|v| !v.is_empty() && *v != "nan").cloned()
+        };
+        let number = |i: usize| -> Result<Option<f64>, String> {
+            text(i).map(|v| v.parse::<f64>().map_err(|_| format!("{} is not a number", v))).transpose()
+        };
+        let product = (|| -> Result<NewProduct, String> {
+            Ok(NewProduct {
+                item_name: text(1).unwrap_or_default(),
+                barcodes: text(0).map(|b| b.split(',').map(str::trim).filter(|c| !c.is_empty()).map(String::from).collect()).unwrap_or_default(),
+                category: text(2),
+                unit: text(3),
+                retail_price: number(7)?.ok_or("Retail price is required")?,
+                cost: number(9)?,
+                bulk_unit: text(4),
+                bulk_code: text(5),
+                bulk_single_conversion: number(6)?,
+                bulk_price: number(8)?,
+            })
+        })();
+        match product.and_then(|p| insert_product(conn, &p)) {
+            Ok(_) => seed.created += 1,
+            Err(e) => seed.skipped.push(format!("line {}: {}", line_no + 1, e)),
+        }
+    }
+    Ok(seed)
+}
+
+fn load_status(conn: &Connection) -> Result<SetupStatus, String> {
+    let settings = Settings::load(conn)?;
+    let created_empty = get_setting(conn, "created_empty_at")?.is_some();
+    let completed_at = get_setting(conn, "setup_completed_at")?;
+    let has_admin = table_exists(conn, "users")?
+        && conn
+            .query_row("SELECT EXISTS(SELECT 1 FROM users WHERE role = 'admin')", params![], |row| row.get(0))
+            .map_err(|e| format!("Failed to read users: {}", e))?;
+    let product_count = conn
+        .query_row("SELECT COUNT(*) FROM products", params![], |row| row.get(0))
+        .map_err(|e| format!("Failed to count products: {}", e))?;
+    Ok(SetupStatus {
+        needs_setup: created_empty && completed_at.is_none(),
+        created_empty,
+        completed_at,
+        store_id: settings.store_id,
+        store_name: settings.store_name,
+        has_admin,
+        product_count,
+    })
+}
+
+#[command]
+pub fn setup_status(window: Window) -> Result<SetupStatus, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    load_status(&conn)
+}
+
+/// Saves the store identity, creates the first admin and optionally seeds the
+/// catalog. Runs once; later changes go through the settings screen.
+#[command]
+pub fn complete_setup(window: Window, queue: State<'_, PrintQueue>, setup: SetupRequest) -> Result<SetupResult, String> {
+    let db_path = get_db_path(&window);
+    let mut conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    if setup.store_name.trim().is_empty() {
+        return Err("Store name is required".to_string());
+    }
+    // The users table is only missing on bundled databases from older builds.
+    conn.execute_batch(LEGACY_SCHEMA).map_err(|e| format!("Failed to create database schema: {}", e))?;
+
+    let mut settings = Settings::load(&conn)?;
+    settings.store_name = setup.store_name.trim().to_string();
+    settings.store_id = setup.store_id.trim().to_string();
+    if let Some(storeman_id) = setup.storeman_id {
+        settings.storeman_id = storeman_id;
+    }
+    settings.validate()?;
+
+    // The admin, the seeded products, the settings and the completion marker
+    // are kept only together, so a failed setup can be retried as a whole.
+    // Only a store that started from an empty database and has not finished
+    // the wizard may be set up; checked under the write lock so two calls
+    // cannot both pass.
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+    if !load_status(&tx)?.needs_setup {
+        return Err("Setup has already been completed".to_string());
+    }
+    let admin_id = create_user(&tx, &setup.admin_username, &setup.admin_password, "admin")?;
+    let seed = match setup.seed_csv.filter(|p| !p.trim().is_empty()) {
+        Some(path) => Some(seed_products(&tx, Path::new(&path))?),
+        None => None,
+    };
+    settings.write(&tx)?;
+    set_setting(&tx, "setup_completed_at", &now_string())?;
+    tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+    let _ = window.emit_all("settings-changed", settings.clone());
+    queue.set_config(settings.printer.clone().unwrap_or_else(|| default_printer(&db_path)));
+    Ok(SetupResult { settings, admin_id, seed })
+}
+
+/// Checks a username and password against `users`; None when they do not match.
+#[command]
+pub fn login(window: Window, username: String, password: String) -> Result<Option<UserInfo>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let user = conn
+        .query_row(
+            "SELECT id, username, role, password FROM users WHERE username = ?1",
+            params![username.trim()],
+            |row| Ok((UserInfo { id: row.get(0)?, username: row.get(1)?, role: row.get(2)? }, row.get::<_, String>(3)?)),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to read users: {}", e))?;
+    match user {
+        Some((info, stored)) if verify_password(&stored, &password) => {
+            conn.execute("UPDATE users SET last_login = ?1 WHERE id = ?2", params![now_string(), info.id])
+                .map_err(|e| format!("Failed to update last login: {}", e))?;
+            Ok(Some(info))
+        }
+        _ => Ok(None),
+    }
+}
+
+commands![
+    setup_status,
+    complete_setup,
+    login,
+];
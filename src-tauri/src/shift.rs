@@ -0,0 +1,237 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::{command, Window};
+use chrono::Utc;
+
+use crate::cart::get_db_path;
+use crate::db::add_column_if_missing;
+use crate::sales::round_money;
+use crate::settings::Settings;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Shift {
+    pub shift_id: i64,
+    pub store_id: String,
+    pub storeman_id: String,
+    pub status: String, // "open" or "closed"
+    pub opening_float: f64,
+    pub opened_at: String,
+    pub counted_cash: Option<f64>,
+    pub expected_cash: Option<f64>,
+    pub over_short: Option<f64>,
+    pub closed_at: Option<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ShiftSummary {
+    pub shift: Shift,
+    pub invoice_count: i64,
+    pub sales_total: f64,
+    pub cash_tendered: f64,
+    pub change_given: f64,
+    pub refunds_total: f64,
+    pub cash_refunded: f64, // the part of the refunds paid out of the drawer
+    pub credit_collected: f64, // cash repayments of customer tabs
+    pub expected_cash: f64,
+}
+
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS shifts (
+            shift_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            store_id VARCHAR(50) NOT NULL,
+            storeman_id VARCHAR(50) NOT NULL,
+            status VARCHAR(10) NOT NULL DEFAULT 'open',
+            opening_float FLOAT NOT NULL DEFAULT 0,
+            opened_at DATETIME NOT NULL,
+            counted_cash FLOAT,
+            expected_cash FLOAT,
+            over_short FLOAT,
+            closed_at DATETIME,
+            note VARCHAR(255)
+        )",
+        params![],
+    )?;
+    add_column_if_missing(conn, "invoices", "shift_id", "INTEGER REFERENCES shifts(shift_id)")?;
+    add_column_if_missing(conn, "returns", "shift_id", "INTEGER REFERENCES shifts(shift_id)")?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_invoices_shift_id ON invoices(shift_id)", params![])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_returns_shift_id ON returns(shift_id)", params![])?;
+    Ok(())
+}
+
+fn row_to_shift(row: &rusqlite::Row) -> rusqlite::Result<Shift> {
+    Ok(Shift {
+        shift_id: row.get(0)?,
+        store_id: row.get(1)?,
+        storeman_id: row.get(2)?,
+        status: row.get(3)?,
+        opening_float: row.get(4)?,
+        opened_at: row.get(5)?,
+        counted_cash: row.get(6)?,
+        expected_cash: row.get(7)?,
+        over_short: row.get(8)?,
+        closed_at: row.get(9)?,
+        note: row.get(10)?,
+    })
+}
+
+const SHIFT_COLUMNS: &str = "shift_id, store_id, storeman_id, status, opening_float, opened_at, counted_cash, expected_cash, over_short, closed_at, note";
+
+pub fn open_shift_row(conn: &Connection) -> Result<Option<Shift>, String> {
+    conn.query_row(
+        &format!("SELECT {} FROM shifts WHERE status = 'open' ORDER BY shift_id DESC LIMIT 1", SHIFT_COLUMNS),
+        params![],
+        row_to_shift,
+    )
+    .optional()
+    .map_err(|e| format!("Failed to load shift: {}", e))
+}
+
+/// The open shift, or an error the UI can show when checkout is attempted
+/// before the drawer has been counted in.
+pub fn require_open_shift(conn: &Connection) -> Result<Shift, String> {
+    open_shift_row(conn)?.ok_or_else(|| "No shift is open. Open a shift before taking payments".to_string())
+}
+
+/// Cash movements of a shift. Change and cash refunds are handed out of the
+/// drawer and tab repayments in cash come in.
+pub fn summarize_shift(conn: &Connection, shift: Shift) -> Result<ShiftSummary, String> {
+    let (invoice_count, sales_total, change_given): (i64, f64, f64) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(total), 0), COALESCE(SUM(change_due), 0) FROM invoices WHERE shift_id = ?1 AND status = 'completed'",
+        params![shift.shift_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )
+    .map_err(|e| format!("Failed to total shift sales: {}", e))?;
+    let cash_tendered: f64 = conn.query_row(
+        "SELECT COALESCE(SUM(t.amount), 0) FROM invoice_tenders t JOIN invoices i ON i.invoice_id = t.invoice_id
+         WHERE i.shift_id = ?1 AND i.status = 'completed' AND t.method = 'cash'",
+        params![shift.shift_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to total shift cash: {}", e))?;
+    let refunds_total: f64 = conn.query_row(
+        "SELECT COALESCE(SUM(refund_total), 0) FROM returns WHERE shift_id = ?1",
+        params![shift.shift_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to total shift refunds: {}", e))?;
+    let cash_refunded: f64 = conn.query_row(
+        "SELECT COALESCE(SUM(t.amount), 0) FROM return_tenders t JOIN returns r ON r.return_id = t.return_id
+         WHERE r.shift_id = ?1 AND t.method = 'cash'",
+        params![shift.shift_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to total shift refunds: {}", e))?;
+    let credit_collected: f64 = conn.query_row(
+        "SELECT COALESCE(-SUM(amount), 0) FROM credit_ledger WHERE shift_id = ?1 AND kind = 'payment' AND method = 'cash'",
+        params![shift.shift_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to total shift repayments: {}", e))?;
+    let expected_cash = round_money(shift.opening_float + cash_tendered - change_given - cash_refunded + credit_collected);
+    Ok(ShiftSummary {
+        shift,
+        invoice_count,
+        sales_total: round_money(sales_total),
+        cash_tendered: round_money(cash_tendered),
+        change_given: round_money(change_given),
+        refunds_total: round_money(refunds_total),
+        cash_refunded: round_money(cash_refunded),
+        credit_collected: round_money(credit_collected),
+        expected_cash,
+    })
+}
+
+/// Store and storeman default to the ones in the settings.
+#[command]
+pub fn open_shift(window: Window, store_id: Option<String>, storeman_id: Option<String>, opening_float: f64) -> Result<Shift, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let settings = Settings::load(&conn)?;
+    let store_id = store_id.unwrap_or(settings.store_id);
+    let storeman_id = storeman_id.unwrap_or(settings.storeman_id);
+    if store_id.trim().is_empty() || storeman_id.trim().is_empty() {
+        return Err("Store and storeman are required to open a shift".to_string());
+    }
+    if opening_float < 0.0 {
+        return Err("Opening float cannot be negative".to_string());
+    }
+    if let Some(shift) = open_shift_row(&conn)? {
+        return Err(format!("Shift {} opened by {} is still open", shift.shift_id, shift.storeman_id));
+    }
+    let opened_at = Utc::now().naive_utc().format("%Y-%m-%d %H:%M:%S").to_string();
+    conn.execute(
+        "INSERT INTO shifts (store_id, storeman_id, status, opening_float, opened_at) VALUES (?1, ?2, 'open', ?3, ?4)",
+        params![store_id, storeman_id, round_money(opening_float), opened_at],
+    )
+    .map_err(|e| format!("Failed to open shift: {}", e))?;
+    Ok(Shift {
+        shift_id: conn.last_insert_rowid(),
+        store_id,
+        storeman_id,
+        status: "open".to_string(),
+        opening_float: round_money(opening_float),
+        opened_at,
+        counted_cash: None,
+        expected_cash: None,
+        over_short: None,
+        closed_at: None,
+        note: None,
+    })
+}
+
+#[command]
+pub fn current_shift(window: Window) -> Result<Option<ShiftSummary>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    match open_shift_row(&conn)? {
+        Some(shift) => summarize_shift(&conn, shift).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Closes the open shift with the cash counted in the drawer; a positive
+/// `over_short` means the drawer holds more than expected.
+#[command]
+pub fn close_shift(window: Window, counted_cash: f64, note: Option<String>) -> Result<ShiftSummary, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    if counted_cash < 0.0 {
+        return Err("Counted cash cannot be negative".to_string());
+    }
+    let shift = require_open_shift(&conn)?;
+    let pending: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM invoices WHERE shift_id = ?1 AND status = 'pending'",
+        params![shift.shift_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to check pending invoices: {}", e))?;
+    if pending > 0 {
+        return Err(format!("{} checkout(s) are still awaiting payment", pending));
+    }
+
+    let mut summary = summarize_shift(&conn, shift)?;
+    let counted_cash = round_money(counted_cash);
+    let over_short = round_money(counted_cash - summary.expected_cash);
+    let closed_at = Utc::now().naive_utc().format("%Y-%m-%d %H:%M:%S").to_string();
+    conn.execute(
+        "UPDATE shifts SET status = 'closed', counted_cash = ?1, expected_cash = ?2, over_short = ?3, closed_at = ?4, note = ?5 WHERE shift_id = ?6",
+        params![counted_cash, summary.expected_cash, over_short, closed_at, note, summary.shift.shift_id],
+    )
+    .map_err(|e| format!("Failed to close shift: {}", e))?;
+
+    summary.shift.status = "closed".to_string();
+    summary.shift.counted_cash = Some(counted_cash);
+    summary.shift.expected_cash = Some(summary.expected_cash);
+    summary.shift.over_short = Some(over_short);
+    summary.shift.closed_at = Some(closed_at);
+    summary.shift.note = note;
+    Ok(summary)
+}
+
+commands![
+    open_shift,
+    current_shift,
+    close_shift,
+];
@@ -0,0 +1,1368 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Result, Transaction};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tauri::{command, AppHandle, Manager, State, Window};
+use chrono::Utc;
+
+use crate::barcode::normalize;
+use crate::cart::{get_db_path, now_string};
+use crate::cart_transfer::{hmac_sha256, signatures_match, to_hex};
+use crate::db::add_column_if_missing;
+use crate::settings::{get_setting, set_setting};
+
+const DEFAULT_SYNC_PORT: u16 = 8787;
+const BATCH_SIZE: i64 = 200;
+const MAX_BODY: usize = 32 * 1024 * 1024;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+const SIGNATURE_HEADER: &str = "x-anpos-signature";
+const TIMESTAMP_HEADER: &str = "x-anpos-timestamp";
+// Signed requests older or newer than this are refused, so a captured one
+// cannot be replayed later. Tills' clocks must agree to within it.
+const MAX_REQUEST_AGE_SECS: i64 = 300;
+
+/// Per-row version vector: terminal id to the number of edits it has made.
+pub type VersionVector = BTreeMap<String, u64>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncRole {
+    Off,
+    Host,   // runs the sync server; the other tills connect to it
+    Client,
+}
+
+/// Read once at startup; changing the role or address needs a restart.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SyncConfig {
+    pub role: SyncRole,
+    pub host: String, // "192.168.1.10:8787", used by clients
+    pub port: u16,    // port the host listens on
+    pub interval_seconds: u64,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        SyncConfig {
+            role: SyncRole::Off,
+            host: String::new(),
+            port: DEFAULT_SYNC_PORT,
+            interval_seconds: 10,
+        }
+    }
+}
+
+impl SyncConfig {
+    pub fn validate(&self, terminal_secret: &str) -> Result<(), String> {
+        if self.role == SyncRole::Off {
+            return Ok(());
+        }
+        if terminal_secret.trim().is_empty() {
+            return Err("Set the shared terminal secret before turning on sync".to_string());
+        }
+        if self.role == SyncRole::Client && self.host.trim().is_empty() {
+            return Err("Sync host address is required".to_string());
+        }
+        if self.port == 0 || !(1..=3600).contains(&self.interval_seconds) {
+            return Err("Sync port and interval must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// One replicated row version. `seq` is the position in the sender's log.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Change {
+    pub seq: i64,
+    pub entity: String, // "product" or "invoice"
+    pub key: String,    // products.Sync_uid or invoices.sync_uid
+    pub origin: String,
+    pub version: VersionVector,
+    pub changed_at: String,
+    pub payload: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncStatus {
+    pub role: SyncRole,
+    pub terminal_id: String,
+    pub last_seq: i64,
+    pub pushed_seq: i64,
+    pub pulled_seq: i64,
+    pub last_sync_at: Option<String>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncConflict {
+    pub conflict_id: i64,
+    pub entity: String,
+    pub key: String,
+    pub kept_origin: String,
+    pub dropped_origin: String,
+    pub dropped_payload: Value,
+    pub created_at: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PushRequest {
+    origin: String,
+    changes: Vec<Change>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PushResponse {
+    applied: usize,
+    conflicts: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PullResponse {
+    changes: Vec<Change>,
+    last_seq: i64,
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Skipped,
+    Applied,
+    Conflict,
+}
+
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_changes (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            entity VARCHAR(20) NOT NULL,
+            entity_key VARCHAR(100) NOT NULL,
+            origin VARCHAR(64) NOT NULL,
+            version TEXT NOT NULL,
+            changed_at DATETIME NOT NULL,
+            payload TEXT NOT NULL
+        )",
+        params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_rows (
+            entity VARCHAR(20) NOT NULL,
+            entity_key VARCHAR(100) NOT NULL,
+            version TEXT NOT NULL,
+            origin VARCHAR(64) NOT NULL,
+            changed_at DATETIME NOT NULL,
+            PRIMARY KEY (entity, entity_key)
+        )",
+        params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_pending (
+            entity VARCHAR(20) NOT NULL,
+            local_id VARCHAR(100) NOT NULL,
+            PRIMARY KEY (entity, local_id)
+        )",
+        params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_conflicts (
+            conflict_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            entity VARCHAR(20) NOT NULL,
+            entity_key VARCHAR(100) NOT NULL,
+            kept_origin VARCHAR(64) NOT NULL,
+            dropped_origin VARCHAR(64) NOT NULL,
+            dropped_payload TEXT NOT NULL,
+            created_at DATETIME NOT NULL
+        )",
+        params![],
+    )?;
+    // Holds a row while remote changes are written so the triggers skip them.
+    conn.execute("CREATE TABLE IF NOT EXISTS sync_applying (flag INTEGER)", params![])?;
+    conn.execute("DELETE FROM sync_applying", params![])?;
+
+    // Products are keyed by their barcodes, which every till that started
+    // from the same catalog agrees on whatever its rowids.
+    add_column_if_missing(conn, "products", "Sync_uid", "VARCHAR(40)")?;
+    conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_products_sync_uid ON products(Sync_uid)", params![])?;
+    assign_product_uids(conn)?;
+    // Invoice numbers are per store and storeman, which tills may share, so
+    // sales travel under a random key instead.
+    add_column_if_missing(conn, "invoices", "sync_uid", "VARCHAR(40)")?;
+    conn.execute("UPDATE invoices SET sync_uid = lower(hex(randomblob(16))) WHERE sync_uid IS NULL", params![])?;
+    conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_invoices_sync_uid ON invoices(sync_uid)", params![])?;
+    add_column_if_missing(conn, "invoices", "sync_origin", "VARCHAR(64)")?;
+    add_column_if_missing(conn, "returns", "sync_origin", "VARCHAR(64)")?;
+
+    // New products are stored with normalized barcodes, so the key can be
+    // derived in SQL; rows without one, or reusing one, get a random key.
+    conn.execute_batch(
+        "DROP TRIGGER IF EXISTS sync_products_insert;
+         CREATE TRIGGER sync_products_insert AFTER INSERT ON products
+         WHEN NOT EXISTS (SELECT 1 FROM sync_applying)
+         BEGIN
+            UPDATE products SET Sync_uid = CASE
+                WHEN NULLIF(TRIM(NEW.Barcode), '') IS NULL OR NEW.Barcode = 'nan'
+                    OR EXISTS (SELECT 1 FROM products WHERE Sync_uid = 'b:' || REPLACE(NEW.Barcode, ' ', ''))
+                THEN lower(hex(randomblob(16)))
+                ELSE 'b:' || REPLACE(NEW.Barcode, ' ', '')
+            END
+            WHERE rowid = NEW.rowid AND Sync_uid IS NULL;
+            INSERT OR IGNORE INTO sync_pending (entity, local_id) VALUES ('product', NEW.rowid);
+         END;
+         CREATE TRIGGER IF NOT EXISTS sync_invoices_insert AFTER INSERT ON invoices
+         WHEN NEW.sync_uid IS NULL
+         BEGIN
+            UPDATE invoices SET sync_uid = lower(hex(randomblob(16))) WHERE invoice_id = NEW.invoice_id;
+         END;
+         CREATE TRIGGER IF NOT EXISTS sync_products_update AFTER UPDATE ON products
+         WHEN NOT EXISTS (SELECT 1 FROM sync_applying)
+         BEGIN
+            INSERT OR IGNORE INTO sync_pending (entity, local_id) VALUES ('product', NEW.rowid);
+         END;
+         CREATE TRIGGER IF NOT EXISTS sync_invoices_completed AFTER UPDATE OF status ON invoices
+         WHEN NEW.status = 'completed' AND NOT EXISTS (SELECT 1 FROM sync_applying)
+         BEGIN
+            INSERT OR IGNORE INTO sync_pending (entity, local_id) VALUES ('invoice', NEW.invoice_id);
+         END;
+         CREATE TRIGGER IF NOT EXISTS sync_returns_insert AFTER INSERT ON returns
+         WHEN NOT EXISTS (SELECT 1 FROM sync_applying)
+         BEGIN
+            INSERT OR IGNORE INTO sync_pending (entity, local_id) VALUES ('invoice', NEW.invoice_id);
+         END;",
+    )?;
+    Ok(())
+}
+
+/// Sync key for a catalog row with the given `Barcode` cell: its codes,
+/// normalized like new products' are.
+fn barcode_uid(barcodes: &str) -> Option<String> {
+    let codes: Vec<String> = barcodes
+        .split(',')
+        .map(str::trim)
+        .filter(|code| !code.is_empty() && *code != "nan")
+        .map(|code| normalize(code).map(|b| b.code).unwrap_or_else(|_| code.to_string()))
+        .collect();
+    if codes.is_empty() { None } else { Some(format!("b:{}", codes.join(","))) }
+}
+
+/// Keys the rows that have none yet: on first run, the whole catalog.
+fn assign_product_uids(conn: &Connection) -> Result<(), rusqlite::Error> {
+    let tx = conn.unchecked_transaction()?;
+    let rows: Vec<(i64, Option<String>)> = {
+        let mut stmt = tx.prepare("SELECT rowid, Barcode FROM products WHERE Sync_uid IS NULL ORDER BY rowid")?;
+        let rows = stmt.query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))?.filter_map(|r| r.ok()).collect();
+        rows
+    };
+    for (rowid, barcode) in rows {
+        let uid = match barcode.as_deref().and_then(barcode_uid) {
+            Some(base) => {
+                // Rows sharing barcodes are told apart by their catalog order.
+                let mut uid = base.clone();
+                let mut n = 1;
+                while tx.query_row("SELECT EXISTS(SELECT 1 FROM products WHERE Sync_uid = ?1)", params![uid], |row| row.get::<_, bool>(0))? {
+                    n += 1;
+                    uid = format!("{}#{}", base, n);
+                }
+                uid
+            }
+            None => uuid::Uuid::new_v4().simple().to_string(),
+        };
+        tx.execute("UPDATE products SET Sync_uid = ?1 WHERE rowid = ?2", params![uid, rowid])?;
+    }
+    tx.commit()
+}
+
+/// This till's id in version vectors, created on first use.
+pub fn terminal_id(conn: &Connection) -> Result<String, String> {
+    if let Some(id) = get_setting(conn, "terminal_id")? {
+        return Ok(id);
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    set_setting(conn, "terminal_id", &id)?;
+    Ok(id)
+}
+
+fn dominates(a: &VersionVector, b: &VersionVector) -> bool {
+    b.iter().all(|(terminal, count)| a.get(terminal).is_some_and(|c| c >= count))
+}
+
+fn merge(a: &VersionVector, b: &VersionVector) -> VersionVector {
+    let mut merged = a.clone();
+    for (terminal, count) in b {
+        let entry = merged.entry(terminal.clone()).or_insert(0);
+        *entry = (*entry).max(*count);
+    }
+    merged
+}
+
+fn sql_to_json(value: SqlValue) -> Value {
+    match value {
+        SqlValue::Null | SqlValue::Blob(_) => Value::Null,
+        SqlValue::Integer(i) => json!(i),
+        SqlValue::Real(f) => json!(f),
+        SqlValue::Text(s) => json!(s),
+    }
+}
+
+fn json_to_sql(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or(0.0)),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+fn select_rows(conn: &Connection, sql: &str, key: &str) -> Result<Vec<Map<String, Value>>, String> {
+    let mut stmt = conn.prepare(sql).map_err(|e| format!("Failed to prepare: {}", e))?;
+    let names: Vec<String> = stmt.column_names().iter().map(|n| n.to_string()).collect();
+    let rows = stmt.query_map(params![key], |row| {
+        let mut object = Map::new();
+        for (i, name) in names.iter().enumerate() {
+            object.insert(name.clone(), sql_to_json(row.get(i)?));
+        }
+        Ok(object)
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(rows)
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>, String> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table)).map_err(|e| format!("Failed to prepare: {}", e))?;
+    let columns = stmt.query_map(params![], |row| row.get(1))
+        .map_err(|e| format!("Failed to query: {}", e))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(columns)
+}
+
+/// Inserts a row from another till, keeping only columns this schema knows.
+fn insert_row(conn: &Connection, table: &str, row: &Map<String, Value>) -> Result<(), String> {
+    insert_row_with(conn, "INSERT", table, row)
+}
+
+fn update_row(conn: &Connection, table: &str, row: &Map<String, Value>, key_column: &str, key: SqlValue) -> Result<(), String> {
+    let known = table_columns(conn, table)?;
+    let columns: Vec<&String> = row.keys().filter(|c| known.contains(c)).collect();
+    let sql = format!(
+        "UPDATE {} SET {} WHERE {} = ?{}",
+        table,
+        columns.iter().enumerate().map(|(i, c)| format!("\"{}\" = ?{}", c, i + 1)).collect::<Vec<_>>().join(", "),
+        key_column,
+        columns.len() + 1
+    );
+    let mut values: Vec<SqlValue> = columns.iter().map(|c| json_to_sql(&row[*c])).collect();
+    values.push(key);
+    conn.execute(&sql, params_from_iter(values)).map_err(|e| format!("Failed to update {}: {}", table, e))?;
+    Ok(())
+}
+
+fn invoice_exists(conn: &Connection, invoice_id: &str) -> Result<bool, String> {
+    conn.query_row("SELECT EXISTS(SELECT 1 FROM invoices WHERE invoice_id = ?1)", params![invoice_id], |row| row.get(0))
+        .map_err(|e| format!("Failed to look up invoice: {}", e))
+}
+
+fn insert_row_with(conn: &Connection, verb: &str, table: &str, row: &Map<String, Value>) -> Result<(), String> {
+    let known = table_columns(conn, table)?;
+    let columns: Vec<&String> = row.keys().filter(|c| known.contains(c)).collect();
+    let sql = format!(
+        "{} INTO {} ({}) VALUES ({})",
+        verb,
+        table,
+        columns.iter().map(|c| format!("\"{}\"", c)).collect::<Vec<_>>().join(", "),
+        (1..=columns.len()).map(|i| format!("?{}", i)).collect::<Vec<_>>().join(", ")
+    );
+    conn.execute(&sql, params_from_iter(columns.iter().map(|c| json_to_sql(&row[*c]))))
+        .map_err(|e| format!("Failed to write {}: {}", table, e))?;
+    Ok(())
+}
+
+/// Snapshot of a row for the change log, or `None` if it no longer exists.
+/// Customer accounts are not replicated; see `customers::ensure_accounts_here`.
+fn build_payload(conn: &Connection, entity: &str, local_id: &str) -> Result<Option<(String, Value)>, String> {
+    match entity {
+        "product" => {
+            let mut rows = select_rows(conn, "SELECT * FROM products WHERE rowid = ?1", local_id)?;
+            let row = match rows.pop() {
+                Some(row) => row,
+                None => return Ok(None),
+            };
+            let key = row.get("Sync_uid").and_then(|v| v.as_str()).map(str::to_string);
+            Ok(key.map(|key| (key, Value::Object(row))))
+        }
+        "invoice" => {
+            let invoice = match select_rows(conn, "SELECT * FROM invoices WHERE invoice_id = ?1", local_id)?.pop() {
+                Some(invoice) => invoice,
+                None => return Ok(None),
+            };
+            let key = invoice.get("sync_uid").and_then(|v| v.as_str()).map(str::to_string);
+            // Product rowids differ between tills; items travel with the product key.
+            let items = select_rows(
+                conn,
+                "SELECT it.*, p.Sync_uid AS product_uid FROM invoice_items it LEFT JOIN products p ON p.rowid = it.product_id
+                 WHERE it.invoice_id = ?1 ORDER BY it.line_no",
+                local_id,
+            )?;
+            let mut payload = Map::new();
+            payload.insert("invoice".to_string(), Value::Object(invoice));
+            payload.insert("items".to_string(), Value::from(items.into_iter().map(Value::Object).collect::<Vec<_>>()));
+            for (name, table) in [("tenders", "invoice_tenders"), ("taxes", "invoice_taxes"), ("discounts", "invoice_discounts"), ("returns", "returns")] {
+                let rows = select_rows(conn, &format!("SELECT * FROM {} WHERE invoice_id = ?1", table), local_id)?;
+                payload.insert(name.to_string(), Value::from(rows.into_iter().map(Value::Object).collect::<Vec<_>>()));
+            }
+            let return_items = select_rows(
+                conn,
+                "SELECT ri.*, p.Sync_uid AS product_uid FROM return_items ri JOIN returns r ON r.return_id = ri.return_id
+                 LEFT JOIN products p ON p.rowid = ri.product_id WHERE r.invoice_id = ?1",
+                local_id,
+            )?;
+            payload.insert("return_items".to_string(), Value::from(return_items.into_iter().map(Value::Object).collect::<Vec<_>>()));
+            let return_tenders = select_rows(
+                conn,
+                "SELECT rt.* FROM return_tenders rt JOIN returns r ON r.return_id = rt.return_id WHERE r.invoice_id = ?1",
+                local_id,
+            )?;
+            payload.insert("return_tenders".to_string(), Value::from(return_tenders.into_iter().map(Value::Object).collect::<Vec<_>>()));
+            Ok(key.map(|key| (key, Value::Object(payload))))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn load_version(conn: &Connection, entity: &str, key: &str) -> Result<Option<(VersionVector, String, String)>, String> {
+    let row: Option<(String, String, String)> = conn.query_row(
+        "SELECT version, origin, changed_at FROM sync_rows WHERE entity = ?1 AND entity_key = ?2",
+        params![entity, key],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to load row version: {}", e))?;
+    Ok(row.map(|(version, origin, changed_at)| (serde_json::from_str(&version).unwrap_or_default(), origin, changed_at)))
+}
+
+fn store_version(conn: &Connection, change: &Change) -> Result<(), String> {
+    let version = serde_json::to_string(&change.version).map_err(|e| format!("Failed to encode version: {}", e))?;
+    conn.execute(
+        "INSERT INTO sync_rows (entity, entity_key, version, origin, changed_at) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(entity, entity_key) DO UPDATE SET version = excluded.version, origin = excluded.origin, changed_at = excluded.changed_at",
+        params![change.entity, change.key, version, change.origin, change.changed_at],
+    )
+    .map_err(|e| format!("Failed to store row version: {}", e))?;
+    Ok(())
+}
+
+/// Appends a change to this till's log and returns its sequence number.
+fn append_change(conn: &Connection, change: &Change) -> Result<i64, String> {
+    let version = serde_json::to_string(&change.version).map_err(|e| format!("Failed to encode version: {}", e))?;
+    conn.execute(
+        "INSERT INTO sync_changes (entity, entity_key, origin, version, changed_at, payload) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![change.entity, change.key, change.origin, version, change.changed_at, change.payload.to_string()],
+    )
+    .map_err(|e| format!("Failed to log change: {}", e))?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Turns rows touched since the last call into log entries, bumping this
+/// till's counter in each row's version vector.
+pub fn record_local_changes(conn: &mut Connection) -> Result<usize, String> {
+    let terminal = terminal_id(conn)?;
+    let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+    // Products a sale refers to go out first, so the other tills can link
+    // its lines even when the product was never edited here.
+    tx.execute(
+        "INSERT OR IGNORE INTO sync_pending (entity, local_id)
+         SELECT DISTINCT 'product', p.rowid FROM sync_pending sp
+         JOIN invoice_items it ON it.invoice_id = sp.local_id
+         JOIN products p ON p.rowid = it.product_id
+         WHERE sp.entity = 'invoice'
+           AND NOT EXISTS (SELECT 1 FROM sync_rows r WHERE r.entity = 'product' AND r.entity_key = p.Sync_uid)",
+        params![],
+    )
+    .map_err(|e| format!("Failed to queue products: {}", e))?;
+    let pending: Vec<(String, String)> = {
+        let mut stmt = tx.prepare("SELECT entity, local_id FROM sync_pending ORDER BY entity = 'invoice'").map_err(|e| format!("Failed to prepare: {}", e))?;
+        let rows = stmt.query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| format!("Failed to query: {}", e))?
+            .filter_map(|r| r.ok())
+            .collect();
+        rows
+    };
+    let mut recorded = 0;
+    for (entity, local_id) in pending {
+        if let Some((key, payload)) = build_payload(&tx, &entity, &local_id)? {
+            let mut version = load_version(&tx, &entity, &key)?.map(|(v, _, _)| v).unwrap_or_default();
+            *version.entry(terminal.clone()).or_insert(0) += 1;
+            let change = Change { seq: 0, entity: entity.clone(), key, origin: terminal.clone(), version, changed_at: now_string(), payload };
+            append_change(&tx, &change)?;
+            store_version(&tx, &change)?;
+            recorded += 1;
+        }
+        tx.execute("DELETE FROM sync_pending WHERE entity = ?1 AND local_id = ?2", params![entity, local_id])
+            .map_err(|e| format!("Failed to clear pending change: {}", e))?;
+    }
+    tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+    Ok(recorded)
+}
+
+/// Swaps the sender's `product_uid` for this till's product rowid. The
+/// sender's rowid means nothing here, so a line whose product is unknown
+/// fails the batch until the product has arrived.
+fn local_product(tx: &Transaction, row: &Value) -> Result<Map<String, Value>, String> {
+    let mut row = row.as_object().cloned().unwrap_or_default();
+    let uid = row.remove("product_uid").and_then(|v| v.as_str().map(str::to_string))
+        .ok_or("Synced line without a product key")?;
+    let rowid: i64 = tx.query_row("SELECT rowid FROM products WHERE Sync_uid = ?1", params![uid], |row| row.get(0))
+        .optional()
+        .map_err(|e| format!("Failed to look up product: {}", e))?
+        .ok_or_else(|| format!("Product {} has not synced yet", uid))?;
+    row.insert("product_id".to_string(), json!(rowid));
+    Ok(row)
+}
+
+/// This till's number for the sale synced as `uid`. A sale from another till
+/// whose number is already taken here is stored with the owner's terminal id
+/// appended, so both sales are kept.
+fn local_invoice_id(tx: &Transaction, uid: &str, remote_id: &str, owner: &str) -> Result<String, String> {
+    let existing: Option<String> = tx.query_row("SELECT invoice_id FROM invoices WHERE sync_uid = ?1", params![uid], |row| row.get(0))
+        .optional()
+        .map_err(|e| format!("Failed to look up invoice: {}", e))?;
+    if let Some(invoice_id) = existing {
+        return Ok(invoice_id);
+    }
+    if !invoice_exists(tx, remote_id)? {
+        return Ok(remote_id.to_string());
+    }
+    Ok(format!("{}_{}", remote_id, owner.chars().take(8).collect::<String>()))
+}
+
+/// Return ids start with the invoice number, which may differ between tills.
+fn local_return_id(row: &mut Map<String, Value>, remote_id: &str, invoice_id: &str) {
+    let renamed = row.get("return_id").and_then(|v| v.as_str())
+        .and_then(|id| id.strip_prefix(remote_id))
+        .map(|rest| format!("{}{}", invoice_id, rest));
+    if let Some(return_id) = renamed {
+        row.insert("return_id".to_string(), json!(return_id));
+    }
+}
+
+/// Returns only ever accumulate, so they are merged even when the invoice
+/// itself conflicts. Each return belongs to the till that took it.
+fn merge_returns(tx: &Transaction, change: &Change, terminal: &str) -> Result<(), String> {
+    let payload = match change.payload.as_object() {
+        Some(payload) => payload,
+        None => return Ok(()),
+    };
+    let invoice_id: String = tx.query_row("SELECT invoice_id FROM invoices WHERE sync_uid = ?1", params![change.key], |row| row.get(0))
+        .map_err(|e| format!("Failed to look up invoice: {}", e))?;
+    let remote_id = payload.get("invoice").and_then(|v| v.get("invoice_id")).and_then(|v| v.as_str()).unwrap_or(&invoice_id).to_string();
+    for row in payload.get("returns").and_then(|v| v.as_array()).into_iter().flatten() {
+        let mut row = row.as_object().cloned().unwrap_or_default();
+        let owner = row.get("sync_origin").and_then(|v| v.as_str()).unwrap_or(&change.origin).to_string();
+        if owner == terminal {
+            continue;
+        }
+        local_return_id(&mut row, &remote_id, &invoice_id);
+        row.insert("invoice_id".to_string(), json!(invoice_id));
+        row.insert("shift_id".to_string(), Value::Null);
+        row.insert("sync_origin".to_string(), json!(owner));
+        insert_row_with(tx, "INSERT OR IGNORE", "returns", &row)?;
+    }
+    for item in payload.get("return_items").and_then(|v| v.as_array()).into_iter().flatten() {
+        let mut item = local_product(tx, item)?;
+        local_return_id(&mut item, &remote_id, &invoice_id);
+        insert_row_with(tx, "INSERT OR IGNORE", "return_items", &item)?;
+    }
+    // Refund tenders have no key of their own, so a return's are copied once.
+    let mut copy: HashMap<String, bool> = HashMap::new();
+    for row in payload.get("return_tenders").and_then(|v| v.as_array()).into_iter().flatten() {
+        let mut row = row.as_object().cloned().unwrap_or_default();
+        local_return_id(&mut row, &remote_id, &invoice_id);
+        let return_id = match row.get("return_id").and_then(|v| v.as_str()) {
+            Some(return_id) => return_id.to_string(),
+            None => continue,
+        };
+        let fresh = match copy.get(&return_id) {
+            Some(fresh) => *fresh,
+            None => {
+                let exists: bool = tx.query_row("SELECT EXISTS(SELECT 1 FROM return_tenders WHERE return_id = ?1)", params![return_id], |row| row.get(0))
+                    .map_err(|e| format!("Failed to look up refund tenders: {}", e))?;
+                copy.insert(return_id, !exists);
+                !exists
+            }
+        };
+        if fresh {
+            insert_row_with(tx, "INSERT", "return_tenders", &row)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_payload(tx: &Transaction, change: &Change, terminal: &str) -> Result<(), String> {
+    match change.entity.as_str() {
+        "product" => {
+            let row = change.payload.as_object().ok_or("Product change without a row")?;
+            let rowid: Option<i64> = tx.query_row("SELECT rowid FROM products WHERE Sync_uid = ?1", params![change.key], |row| row.get(0))
+                .optional()
+                .map_err(|e| format!("Failed to look up product: {}", e))?;
+            match rowid {
+                Some(rowid) => update_row(tx, "products", row, "rowid", SqlValue::Integer(rowid))?,
+                None => insert_row(tx, "products", row)?,
+            }
+        }
+        "invoice" => {
+            let payload = change.payload.as_object().ok_or("Invoice change without rows")?;
+            let mut invoice = payload.get("invoice").and_then(|v| v.as_object()).cloned().ok_or("Invoice change without an invoice")?;
+            // The till that rang the sale owns it; a return made elsewhere
+            // must not move the sale out of the owner's shift and reports.
+            let owner = invoice.get("sync_origin").and_then(|v| v.as_str()).unwrap_or(&change.origin).to_string();
+            let remote_id = invoice.get("invoice_id").and_then(|v| v.as_str()).ok_or("Invoice change without a number")?.to_string();
+            let invoice_id = local_invoice_id(tx, &change.key, &remote_id, &owner)?;
+            let local_shift: Option<i64> = tx.query_row("SELECT shift_id FROM invoices WHERE invoice_id = ?1", params![invoice_id], |row| row.get(0))
+                .optional()
+                .map_err(|e| format!("Failed to look up invoice: {}", e))?
+                .flatten();
+            // Returns reference the invoice row, so it is updated in place.
+            for table in ["invoice_discounts", "invoice_taxes", "invoice_tenders", "invoice_items"] {
+                tx.execute(&format!("DELETE FROM {} WHERE invoice_id = ?1", table), params![invoice_id])
+                    .map_err(|e| format!("Failed to replace invoice: {}", e))?;
+            }
+            invoice.insert("invoice_id".to_string(), json!(invoice_id));
+            if owner == terminal {
+                invoice.insert("shift_id".to_string(), json!(local_shift));
+                invoice.insert("sync_origin".to_string(), Value::Null);
+            } else {
+                invoice.insert("shift_id".to_string(), Value::Null);
+                invoice.insert("sync_origin".to_string(), json!(owner));
+            }
+            if invoice_exists(tx, &invoice_id)? {
+                update_row(tx, "invoices", &invoice, "invoice_id", SqlValue::Text(invoice_id.clone()))?;
+            } else {
+                insert_row(tx, "invoices", &invoice)?;
+            }
+            for item in payload.get("items").and_then(|v| v.as_array()).into_iter().flatten() {
+                let mut item = local_product(tx, item)?;
+                item.insert("invoice_id".to_string(), json!(invoice_id));
+                insert_row(tx, "invoice_items", &item)?;
+            }
+            for (name, table) in [("tenders", "invoice_tenders"), ("taxes", "invoice_taxes"), ("discounts", "invoice_discounts")] {
+                for row in payload.get(name).and_then(|v| v.as_array()).into_iter().flatten() {
+                    let mut row = row.as_object().cloned().unwrap_or_default();
+                    row.insert("invoice_id".to_string(), json!(invoice_id));
+                    insert_row(tx, table, &row)?;
+                }
+            }
+            merge_returns(tx, change, terminal)?;
+        }
+        other => return Err(format!("Unknown sync entity {}", other)),
+    }
+    Ok(())
+}
+
+/// Applies a change from another till. Newer versions replace the row;
+/// concurrent edits are a conflict: products keep the later edit, invoices
+/// always keep the local copy, and the losing version is saved in
+/// `sync_conflicts`. The host relays everything it applies to the others.
+fn apply_change(tx: &Transaction, change: &Change, terminal: &str, relay: bool) -> Result<Outcome, String> {
+    let local = load_version(tx, &change.entity, &change.key)?;
+    let (local_version, local_origin, local_changed_at) = local.unwrap_or_default();
+    if dominates(&local_version, &change.version) {
+        return Ok(Outcome::Skipped);
+    }
+    tx.execute("INSERT INTO sync_applying (flag) VALUES (1)", params![])
+        .map_err(|e| format!("Failed to start applying: {}", e))?;
+    let result = if dominates(&change.version, &local_version) {
+        write_payload(tx, change, terminal)?;
+        store_version(tx, change)?;
+        if relay {
+            append_change(tx, change)?;
+        }
+        Outcome::Applied
+    } else {
+        let remote_wins = change.entity == "product"
+            && (change.changed_at.as_str(), change.origin.as_str()) > (local_changed_at.as_str(), local_origin.as_str());
+        let (kept_origin, dropped_origin, dropped_payload) = if remote_wins {
+            let local_row = tx.query_row("SELECT rowid FROM products WHERE Sync_uid = ?1", params![change.key], |row| row.get::<_, i64>(0))
+                .optional()
+                .map_err(|e| format!("Failed to look up product: {}", e))?;
+            let dropped = match local_row {
+                Some(rowid) => build_payload(tx, "product", &rowid.to_string())?.map(|(_, p)| p).unwrap_or(Value::Null),
+                None => Value::Null,
+            };
+            write_payload(tx, change, terminal)?;
+            (change.origin.clone(), local_origin.clone(), dropped)
+        } else {
+            if change.entity == "invoice" {
+                merge_returns(tx, change, terminal)?;
+            }
+            (local_origin.clone(), change.origin.clone(), change.payload.clone())
+        };
+        tx.execute(
+            "INSERT INTO sync_conflicts (entity, entity_key, kept_origin, dropped_origin, dropped_payload, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![change.entity, change.key, kept_origin, dropped_origin, dropped_payload.to_string(), now_string()],
+        )
+        .map_err(|e| format!("Failed to record conflict: {}", e))?;
+        // The merged version supersedes both sides, so every till converges on it.
+        let payload = if remote_wins {
+            change.payload.clone()
+        } else {
+            let local_id = match change.entity.as_str() {
+                "product" => tx.query_row("SELECT rowid FROM products WHERE Sync_uid = ?1", params![change.key], |row| row.get::<_, i64>(0))
+                    .map(|id| id.to_string())
+                    .map_err(|e| format!("Failed to look up product: {}", e))?,
+                _ => tx.query_row("SELECT invoice_id FROM invoices WHERE sync_uid = ?1", params![change.key], |row| row.get::<_, String>(0))
+                    .map_err(|e| format!("Failed to look up invoice: {}", e))?,
+            };
+            build_payload(tx, &change.entity, &local_id)?.map(|(_, p)| p).unwrap_or(Value::Null)
+        };
+        let resolved = Change {
+            seq: 0,
+            entity: change.entity.clone(),
+            key: change.key.clone(),
+            origin: if remote_wins { change.origin.clone() } else { terminal.to_string() },
+            version: merge(&local_version, &change.version),
+            changed_at: if remote_wins { change.changed_at.clone() } else { local_changed_at },
+            payload,
+        };
+        append_change(tx, &resolved)?;
+        store_version(tx, &resolved)?;
+        Outcome::Conflict
+    };
+    tx.execute("DELETE FROM sync_applying", params![]).map_err(|e| format!("Failed to finish applying: {}", e))?;
+    Ok(result)
+}
+
+fn apply_batch(conn: &mut Connection, changes: &[Change], relay: bool) -> Result<PushResponse, String> {
+    // Local edits not yet in the log must get their version first, or a
+    // remote change would overwrite them instead of conflicting.
+    record_local_changes(conn)?;
+    let terminal = terminal_id(conn)?;
+    let tx = conn.transaction().map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let mut response = PushResponse { applied: 0, conflicts: 0 };
+    for change in changes {
+        match apply_change(&tx, change, &terminal, relay)? {
+            Outcome::Applied => response.applied += 1,
+            Outcome::Conflict => response.conflicts += 1,
+            Outcome::Skipped => {}
+        }
+    }
+    tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+    Ok(response)
+}
+
+fn load_changes(conn: &Connection, since: i64, origin: Option<&str>) -> Result<Vec<Change>, String> {
+    let mut stmt = conn.prepare(
+        "SELECT seq, entity, entity_key, origin, version, changed_at, payload FROM sync_changes
+         WHERE seq > ?1 AND (?2 IS NULL OR origin = ?2) ORDER BY seq LIMIT ?3",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let changes = stmt.query_map(params![since, origin, BATCH_SIZE], |row| {
+        let version: String = row.get(4)?;
+        let payload: String = row.get(6)?;
+        Ok(Change {
+            seq: row.get(0)?,
+            entity: row.get(1)?,
+            key: row.get(2)?,
+            origin: row.get(3)?,
+            version: serde_json::from_str(&version).unwrap_or_default(),
+            changed_at: row.get(5)?,
+            payload: serde_json::from_str(&payload).unwrap_or(Value::Null),
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(changes)
+}
+
+fn last_seq(conn: &Connection) -> Result<i64, String> {
+    conn.query_row("SELECT COALESCE(MAX(seq), 0) FROM sync_changes", params![], |row| row.get(0))
+        .map_err(|e| format!("Failed to read change log: {}", e))
+}
+
+fn sign_request(secret: &str, method: &str, path: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut message = format!("{} {}\n{}\n", method, path, timestamp).into_bytes();
+    message.extend_from_slice(body);
+    to_hex(&hmac_sha256(secret.as_bytes(), &message))
+}
+
+/// Wakes long-polling clients when the host's log grows.
+#[derive(Default)]
+struct ChangeSignal {
+    seq: Mutex<i64>,
+    cond: Condvar,
+}
+
+impl ChangeSignal {
+    fn notify(&self, seq: i64) {
+        *self.seq.lock().unwrap() = seq;
+        self.cond.notify_all();
+    }
+
+    fn wait_past(&self, since: i64, timeout: Duration) {
+        let guard = self.seq.lock().unwrap();
+        let _ = self.cond.wait_timeout_while(guard, timeout, |seq| *seq <= since);
+    }
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+fn read_request(stream: &TcpStream) -> Result<HttpRequest, String> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|e| e.to_string())?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().ok_or("Empty request")?.to_string();
+    let path = parts.next().ok_or("Missing path")?.to_string();
+    let mut headers = HashMap::new();
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).map_err(|e| e.to_string())?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    let length: usize = headers.get("content-length").and_then(|v| v.parse().ok()).unwrap_or(0);
+    if length > MAX_BODY {
+        return Err("Request too large".to_string());
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+    Ok(HttpRequest { method, path, headers, body })
+}
+
+fn write_response(mut stream: &TcpStream, status: u16, body: &str) {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        _ => "Internal Server Error",
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, reason, body.len(), body
+    );
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.flush();
+}
+
+fn query_param(path: &str, name: &str) -> Option<String> {
+    let query = path.split_once('?')?.1;
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        if key == name { Some(value.to_string()) } else { None }
+    })
+}
+
+fn handle_request(db_path: &Path, secret: &str, signal: &ChangeSignal, request: HttpRequest) -> Result<String, (u16, String)> {
+    let signature = request.headers.get(SIGNATURE_HEADER).cloned().unwrap_or_default();
+    let timestamp = request.headers.get(TIMESTAMP_HEADER).cloned().unwrap_or_default();
+    if !signatures_match(&sign_request(secret, &request.method, &request.path, &timestamp, &request.body), &signature) {
+        return Err((401, "Bad signature".to_string()));
+    }
+    let sent_at: i64 = timestamp.parse().map_err(|_| (401, "Bad timestamp".to_string()))?;
+    if (Utc::now().timestamp() - sent_at).abs() > MAX_REQUEST_AGE_SECS {
+        return Err((401, "Request expired; check that the tills' clocks agree".to_string()));
+    }
+    let internal = |e: String| (500, e);
+    let mut conn = open_db(db_path).map_err(|e| (500, e))?;
+    let route = request.path.split('?').next().unwrap_or("");
+    match (request.method.as_str(), route) {
+        ("POST", "/sync/push") => {
+            let push: PushRequest = serde_json::from_slice(&request.body).map_err(|e| (400, format!("Bad push: {}", e)))?;
+            let response = apply_batch(&mut conn, &push.changes, true).map_err(internal)?;
+            signal.notify(last_seq(&conn).map_err(internal)?);
+            serde_json::to_string(&response).map_err(|e| internal(e.to_string()))
+        }
+        ("GET", "/sync/pull") => {
+            let since: i64 = query_param(&request.path, "since").and_then(|v| v.parse().ok()).unwrap_or(0);
+            let wait: u64 = query_param(&request.path, "wait").and_then(|v| v.parse().ok()).unwrap_or(0).min(60);
+            if wait > 0 && last_seq(&conn).map_err(internal)? <= since {
+                signal.wait_past(since, Duration::from_secs(wait));
+            }
+            let changes = load_changes(&conn, since, None).map_err(internal)?;
+            let last_seq = changes.last().map(|c| c.seq).unwrap_or(since);
+            serde_json::to_string(&PullResponse { changes, last_seq }).map_err(|e| internal(e.to_string()))
+        }
+        ("GET", "/sync/status") => {
+            let terminal = terminal_id(&conn).map_err(internal)?;
+            Ok(json!({ "terminal_id": terminal, "last_seq": last_seq(&conn).map_err(internal)? }).to_string())
+        }
+        _ => Err((404, "Not found".to_string())),
+    }
+}
+
+fn http_request(host: &str, method: &str, path: &str, body: &[u8], secret: &str, timeout: Duration) -> Result<Vec<u8>, String> {
+    let addr = host.to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+        .next()
+        .ok_or_else(|| format!("No address for {}", host))?;
+    let mut stream = TcpStream::connect_timeout(&addr, HTTP_TIMEOUT).map_err(|e| format!("Failed to connect to {}: {}", host, e))?;
+    stream.set_read_timeout(Some(timeout)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT)).map_err(|e| e.to_string())?;
+    let timestamp = Utc::now().timestamp().to_string();
+    let head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}: {}\r\n{}: {}\r\nConnection: close\r\n\r\n",
+        method, path, host, body.len(), TIMESTAMP_HEADER, timestamp, SIGNATURE_HEADER, sign_request(secret, method, path, &timestamp, body)
+    );
+    stream.write_all(head.as_bytes()).and_then(|_| stream.write_all(body)).map_err(|e| format!("Failed to send to {}: {}", host, e))?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).map_err(|e| format!("Failed to read from {}: {}", host, e))?;
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").ok_or("Malformed response from sync host")?;
+    let status_line = String::from_utf8_lossy(&response[..split]).lines().next().unwrap_or("").to_string();
+    let body = response[split + 4..].to_vec();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        return Err(format!("Sync host answered {}: {}", status_line, String::from_utf8_lossy(&body)));
+    }
+    Ok(body)
+}
+
+// The workers and the server share the database with the UI commands.
+fn open_db(db_path: &Path) -> Result<Connection, String> {
+    let conn = Connection::open(db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    conn.busy_timeout(Duration::from_secs(5)).map_err(|e| format!("Failed to configure DB: {}", e))?;
+    Ok(conn)
+}
+
+fn read_seq(conn: &Connection, key: &str) -> Result<i64, String> {
+    Ok(get_setting(conn, key)?.and_then(|v| v.parse().ok()).unwrap_or(0))
+}
+
+/// One round for a client: record, push everything it has not sent, then
+/// pull (waiting up to `wait` seconds for news) and apply.
+fn client_round(db_path: &Path, host: &str, secret: &str, wait: u64) -> Result<(i64, i64), String> {
+    let mut conn = open_db(db_path)?;
+    record_local_changes(&mut conn)?;
+    let terminal = terminal_id(&conn)?;
+    let mut pushed = read_seq(&conn, "sync_pushed_seq")?;
+    loop {
+        let changes = load_changes(&conn, pushed, None)?;
+        let last = match changes.last() {
+            Some(change) => change.seq,
+            None => break,
+        };
+        let body = serde_json::to_vec(&PushRequest { origin: terminal.clone(), changes }).map_err(|e| e.to_string())?;
+        http_request(host, "POST", "/sync/push", &body, secret, HTTP_TIMEOUT)?;
+        pushed = last;
+        set_setting(&conn, "sync_pushed_seq", &pushed.to_string())?;
+    }
+    let mut pulled = read_seq(&conn, "sync_pulled_seq")?;
+    let mut wait = wait;
+    loop {
+        let path = format!("/sync/pull?since={}&wait={}", pulled, wait);
+        let body = http_request(host, "GET", &path, &[], secret, HTTP_TIMEOUT + Duration::from_secs(wait))?;
+        let response: PullResponse = serde_json::from_slice(&body).map_err(|e| format!("Bad pull response: {}", e))?;
+        if response.changes.is_empty() {
+            break;
+        }
+        apply_batch(&mut conn, &response.changes, false)?;
+        pulled = response.last_seq;
+        set_setting(&conn, "sync_pulled_seq", &pulled.to_string())?;
+        wait = 0;
+    }
+    // Conflict resolutions are logged locally and go up on the next round.
+    Ok((pushed, pulled))
+}
+
+/// Managed state exposing the sync worker's progress.
+pub struct SyncHandle {
+    status: Arc<Mutex<SyncStatus>>,
+    stop: Arc<AtomicBool>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl SyncHandle {
+    fn new(status: SyncStatus) -> Self {
+        SyncHandle { status: Arc::new(Mutex::new(status)), stop: Arc::new(AtomicBool::new(false)), workers: Mutex::new(Vec::new()) }
+    }
+
+    pub fn status(&self) -> SyncStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Stops the server and worker loops and waits for their current round.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+        for worker in self.workers.lock().unwrap().drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Sleeps for `duration`, returning early once `stop` is set.
+fn pause(stop: &AtomicBool, duration: Duration) {
+    let step = Duration::from_millis(100);
+    let mut slept = Duration::ZERO;
+    while slept < duration && !stop.load(Ordering::SeqCst) {
+        std::thread::sleep(step.min(duration - slept));
+        slept += step;
+    }
+}
+
+/// Starts the host server or the client loop for `config`. `on_status` is
+/// called after every round.
+pub fn start_sync<F>(db_path: PathBuf, config: SyncConfig, secret: String, on_status: F) -> Result<SyncHandle, String>
+where
+    F: Fn(&SyncStatus) + Send + Sync + 'static,
+{
+    let conn = open_db(&db_path)?;
+    let handle = SyncHandle::new(SyncStatus {
+        role: config.role,
+        terminal_id: terminal_id(&conn)?,
+        last_seq: last_seq(&conn)?,
+        pushed_seq: read_seq(&conn, "sync_pushed_seq")?,
+        pulled_seq: read_seq(&conn, "sync_pulled_seq")?,
+        last_sync_at: None,
+        last_error: None,
+    });
+    drop(conn);
+    if config.role == SyncRole::Off {
+        return Ok(handle);
+    }
+    config.validate(&secret)?;
+    let on_status = Arc::new(on_status);
+    let interval = Duration::from_secs(config.interval_seconds);
+    let (status, stop) = (handle.status.clone(), handle.stop.clone());
+    let mut workers = handle.workers.lock().unwrap();
+
+    match config.role {
+        SyncRole::Host => {
+            let listener = TcpListener::bind(("0.0.0.0", config.port)).map_err(|e| format!("Failed to listen on port {}: {}", config.port, e))?;
+            // Polled, so the server notices `stop` between connections.
+            listener.set_nonblocking(true).map_err(|e| format!("Failed to configure port {}: {}", config.port, e))?;
+            let signal = Arc::new(ChangeSignal::default());
+            {
+                let (db_path, secret, signal, stop) = (db_path.clone(), secret.clone(), signal.clone(), stop.clone());
+                workers.push(std::thread::spawn(move || {
+                    while !stop.load(Ordering::SeqCst) {
+                        let stream = match listener.accept() {
+                            Ok((stream, _)) => stream,
+                            Err(_) => {
+                                pause(&stop, Duration::from_millis(100));
+                                continue;
+                            }
+                        };
+                        let _ = stream.set_nonblocking(false);
+                        let (db_path, secret, signal) = (db_path.clone(), secret.clone(), signal.clone());
+                        std::thread::spawn(move || {
+                            let _ = stream.set_read_timeout(Some(HTTP_TIMEOUT));
+                            match read_request(&stream) {
+                                Ok(request) => match handle_request(&db_path, &secret, &signal, request) {
+                                    Ok(body) => write_response(&stream, 200, &body),
+                                    Err((code, error)) => write_response(&stream, code, &json!({ "error": error }).to_string()),
+                                },
+                                Err(error) => write_response(&stream, 400, &json!({ "error": error }).to_string()),
+                            }
+                        });
+                    }
+                }));
+            }
+            // The host's own sales and price edits enter the log here.
+            workers.push(std::thread::spawn(move || while !stop.load(Ordering::SeqCst) {
+                let result = open_db(&db_path)
+                    .and_then(|mut conn| record_local_changes(&mut conn).and_then(|_| last_seq(&conn)));
+                {
+                    let mut status = status.lock().unwrap();
+                    match result {
+                        Ok(seq) => {
+                            signal.notify(seq);
+                            status.last_seq = seq;
+                            status.last_sync_at = Some(now_string());
+                            status.last_error = None;
+                        }
+                        Err(e) => status.last_error = Some(e),
+                    }
+                    on_status(&status);
+                }
+                pause(&stop, interval);
+            }));
+        }
+        SyncRole::Client => {
+            let host = config.host.trim().to_string();
+            workers.push(std::thread::spawn(move || while !stop.load(Ordering::SeqCst) {
+                let result = client_round(&db_path, &host, &secret, config.interval_seconds);
+                let failed = result.is_err();
+                {
+                    let mut status = status.lock().unwrap();
+                    match result {
+                        Ok((pushed, pulled)) => {
+                            status.pushed_seq = pushed;
+                            status.pulled_seq = pulled;
+                            status.last_sync_at = Some(now_string());
+                            status.last_error = None;
+                        }
+                        Err(e) => status.last_error = Some(e),
+                    }
+                    if let Ok(conn) = open_db(&db_path) {
+                        status.last_seq = last_seq(&conn).unwrap_or(status.last_seq);
+                    }
+                    on_status(&status);
+                }
+                // A successful pull already waited on the host; back off after errors.
+                if failed {
+                    pause(&stop, interval);
+                }
+            }));
+        }
+        SyncRole::Off => {}
+    }
+    drop(workers);
+    Ok(handle)
+}
+
+/// Starts sync for the app and reports every round as `sync-status`.
+pub fn spawn_sync(app: AppHandle, db_path: PathBuf, config: SyncConfig, secret: String) -> SyncHandle {
+    let role = config.role;
+    let emitter = app.clone();
+    match start_sync(db_path, config, secret, move |status| {
+        let _ = emitter.emit_all("sync-status", status.clone());
+    }) {
+        Ok(handle) => handle,
+        Err(e) => SyncHandle::new(SyncStatus {
+            role,
+            terminal_id: String::new(),
+            last_seq: 0,
+            pushed_seq: 0,
+            pulled_seq: 0,
+            last_sync_at: None,
+            last_error: Some(e),
+        }),
+    }
+}
+
+#[command]
+pub fn sync_status(handle: State<'_, SyncHandle>) -> Result<SyncStatus, String> {
+    Ok(handle.status())
+}
+
+#[command]
+pub fn list_sync_conflicts(window: Window) -> Result<Vec<SyncConflict>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut stmt = conn.prepare(
+        "SELECT conflict_id, entity, entity_key, kept_origin, dropped_origin, dropped_payload, created_at
+         FROM sync_conflicts ORDER BY conflict_id DESC LIMIT 200",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let conflicts = stmt.query_map(params![], |row| {
+        let payload: String = row.get(5)?;
+        Ok(SyncConflict {
+            conflict_id: row.get(0)?,
+            entity: row.get(1)?,
+            key: row.get(2)?,
+            kept_origin: row.get(3)?,
+            dropped_origin: row.get(4)?,
+            dropped_payload: serde_json::from_str(&payload).unwrap_or(Value::Null),
+            created_at: row.get(6)?,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(conflicts)
+}
+
+commands![
+    sync_status,
+    list_sync_conflicts,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector(counts: &[(&str, u64)]) -> VersionVector {
+        counts.iter().map(|(terminal, count)| (terminal.to_string(), *count)).collect()
+    }
+
+    #[test]
+    fn dominance_follows_every_counter() {
+        let a = vector(&[("a", 2), ("b", 1)]);
+        assert!(dominates(&a, &vector(&[("a", 1)])));
+        assert!(dominates(&a, &a));
+        assert!(dominates(&a, &VersionVector::new()));
+        assert!(!dominates(&a, &vector(&[("a", 1), ("b", 2)])));
+        assert!(!dominates(&a, &vector(&[("c", 1)])));
+        assert!(!dominates(&VersionVector::new(), &a));
+    }
+
+    #[test]
+    fn merge_keeps_the_higher_counter() {
+        let a = vector(&[("a", 2), ("b", 1)]);
+        let b = vector(&[("b", 3), ("c", 1)]);
+        let merged = merge(&a, &b);
+        assert_eq!(merged, vector(&[("a", 2), ("b", 3), ("c", 1)]));
+        assert_eq!(merged, merge(&b, &a));
+        assert!(dominates(&merged, &a) && dominates(&merged, &b));
+    }
+
+    #[test]
+    fn product_keys_come_from_normalized_barcodes() {
+        assert_eq!(barcode_uid("036000291452, 96385074").as_deref(), Some("b:0036000291452,96385074"));
+        assert_eq!(barcode_uid("SKU-1").as_deref(), Some("b:SKU-1"));
+        assert_eq!(barcode_uid("nan"), None);
+        assert_eq!(barcode_uid(" "), None);
+    }
+
+    const SALE: &str = "S1_default_storeman_20261018_001";
+
+    /// A till database holding the legacy catalog in the given order.
+    fn till(catalog: &[(&str, &str, f64)]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("anpos-sync-{}.db", uuid::Uuid::new_v4().simple()));
+        let conn = Connection::open(&path).unwrap();
+        conn.execute(
+            "CREATE TABLE products (Barcode VARCHAR, Item_name VARCHAR, Category VARCHAR, Unit VARCHAR, Bulk_unit VARCHAR,
+             Bulk_code VARCHAR, Bulk_single_conversion FLOAT, Retail_price FLOAT, Bulk_price FLOAT, Cost FLOAT)",
+            params![],
+        )
+        .unwrap();
+        for (barcode, name, price) in catalog {
+            conn.execute("INSERT INTO products (Barcode, Item_name, Retail_price) VALUES (?1, ?2, ?3)", params![barcode, name, price]).unwrap();
+        }
+        crate::db::initialize_tables(&conn).unwrap();
+        path
+    }
+
+    fn product_id(conn: &Connection, barcode: &str) -> i64 {
+        conn.query_row("SELECT rowid FROM products WHERE Barcode = ?1", params![barcode], |row| row.get(0)).unwrap()
+    }
+
+    fn price(conn: &Connection, barcode: &str) -> f64 {
+        conn.query_row("SELECT Retail_price FROM products WHERE Barcode = ?1", params![barcode], |row| row.get(0)).unwrap()
+    }
+
+    fn set_price(conn: &Connection, barcode: &str, price: f64) {
+        conn.execute("UPDATE products SET Retail_price = ?1 WHERE Barcode = ?2", params![price, barcode]).unwrap();
+    }
+
+    fn sell(conn: &Connection, barcode: &str, amount: f64) {
+        conn.execute(
+            "INSERT INTO invoices (invoice_id, cart_id, store_id, storeman_id, subtotal, total) VALUES (?1, 1, 'S1', 'default_storeman', ?2, ?2)",
+            params![SALE, amount],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO invoice_items (invoice_id, line_no, product_id, quantity, price, purchasing_type) VALUES (?1, 1, ?2, 1, ?3, 'single')",
+            params![SALE, product_id(conn, barcode), amount],
+        )
+        .unwrap();
+        conn.execute("INSERT INTO invoice_tenders (invoice_id, method, amount) VALUES (?1, 'cash', ?2)", params![SALE, amount]).unwrap();
+        conn.execute("UPDATE invoices SET status = 'completed', paid_at = ?1 WHERE invoice_id = ?2", params![now_string(), SALE]).unwrap();
+    }
+
+    /// Invoice number, barcode of its product and total, for every sale.
+    fn sales(conn: &Connection) -> Vec<(String, String, f64)> {
+        let mut stmt = conn.prepare(
+            "SELECT i.invoice_id, p.Barcode, i.total FROM invoices i
+             JOIN invoice_items it ON it.invoice_id = i.invoice_id JOIN products p ON p.rowid = it.product_id
+             WHERE i.status = 'completed' ORDER BY i.invoice_id",
+        )
+        .unwrap();
+        let rows = stmt.query_map(params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap().map(|r| r.unwrap()).collect();
+        rows
+    }
+
+    #[test]
+    fn host_and_client_converge() {
+        let host_db = till(&[("111", "Trà", 10000.0), ("222", "Bánh", 20000.0)]);
+        // Same catalog, other rowids.
+        let client_db = till(&[("222", "Bánh", 20000.0), ("111", "Trà", 10000.0)]);
+        let (host, client) = (Connection::open(&host_db).unwrap(), Connection::open(&client_db).unwrap());
+        assert_ne!(product_id(&host, "111"), product_id(&client, "111"));
+
+        // Both tills ring up the same invoice number and edit the same price.
+        set_price(&host, "111", 12000.0);
+        set_price(&host, "222", 21000.0);
+        set_price(&client, "222", 22000.0);
+        sell(&host, "111", 12000.0);
+        sell(&client, "222", 22000.0);
+
+        let port = TcpListener::bind(("127.0.0.1", 0)).unwrap().local_addr().unwrap().port();
+        let config = |role, host: String| SyncConfig { role, host, port, interval_seconds: 1 };
+        let host_sync = start_sync(host_db.clone(), config(SyncRole::Host, String::new()), "secret".to_string(), |_| {}).unwrap();
+        let client_sync = start_sync(client_db.clone(), config(SyncRole::Client, format!("127.0.0.1:{}", port)), "secret".to_string(), |_| {}).unwrap();
+
+        let converged = || {
+            let (host_sales, client_sales) = (sales(&host), sales(&client));
+            host_sales.len() == 2
+                && client_sales.len() == 2
+                && price(&client, "111") == 12000.0
+                && price(&host, "222") == price(&client, "222")
+        };
+        let deadline = std::time::Instant::now() + Duration::from_secs(30);
+        while !converged() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(200));
+        }
+        assert!(converged(), "host {:?} client {:?}", sales(&host), sales(&client));
+
+        // Neither sale is dropped: the remote one is renumbered with its
+        // owner's terminal id and its line points at the local product.
+        let suffix = |conn: &Connection| terminal_id(conn).unwrap().chars().take(8).collect::<String>();
+        assert_eq!(sales(&host), vec![
+            (SALE.to_string(), "111".to_string(), 12000.0),
+            (format!("{}_{}", SALE, suffix(&client)), "222".to_string(), 22000.0),
+        ]);
+        assert_eq!(sales(&client), vec![
+            (SALE.to_string(), "222".to_string(), 22000.0),
+            (format!("{}_{}", SALE, suffix(&host)), "111".to_string(), 12000.0),
+        ]);
+
+        // The concurrent price edit is a recorded conflict, not a silent loss.
+        let conflicts: i64 = [&host, &client]
+            .iter()
+            .map(|conn| conn.query_row("SELECT COUNT(*) FROM sync_conflicts WHERE entity = 'product'", params![], |row| row.get::<_, i64>(0)).unwrap())
+            .sum();
+        assert!(conflicts >= 1);
+        assert!([21000.0, 22000.0].contains(&price(&host, "222")));
+
+        client_sync.stop();
+        host_sync.stop();
+        drop((host, client));
+        std::fs::remove_file(&host_db).unwrap();
+        std::fs::remove_file(&client_db).unwrap();
+    }
+
+    #[test]
+    fn host_refuses_unsigned_and_stale_requests() {
+        let db = till(&[]);
+        let signal = ChangeSignal::default();
+        let request = |secret: &str, sent_at: i64| {
+            let timestamp = sent_at.to_string();
+            let signature = sign_request(secret, "GET", "/sync/status", &timestamp, &[]);
+            HttpRequest {
+                method: "GET".to_string(),
+                path: "/sync/status".to_string(),
+                headers: HashMap::from([(SIGNATURE_HEADER.to_string(), signature), (TIMESTAMP_HEADER.to_string(), timestamp)]),
+                body: Vec::new(),
+            }
+        };
+        let now = Utc::now().timestamp();
+        assert!(handle_request(&db, "secret", &signal, request("secret", now)).is_ok());
+        assert_eq!(handle_request(&db, "secret", &signal, request("other", now)).unwrap_err().0, 401);
+        assert_eq!(handle_request(&db, "secret", &signal, request("secret", now - MAX_REQUEST_AGE_SECS - 60)).unwrap_err().0, 401);
+        let mut tampered = request("secret", now);
+        tampered.headers.insert(TIMESTAMP_HEADER.to_string(), (now + 1).to_string());
+        assert_eq!(handle_request(&db, "secret", &signal, tampered).unwrap_err().0, 401);
+        std::fs::remove_file(&db).unwrap();
+    }
+}
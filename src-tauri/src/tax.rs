@@ -0,0 +1,305 @@
+use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::{command, Window};
+
+use crate::cart::get_db_path;
+use crate::db::add_column_if_missing;
+use crate::promotions::{load_cart_discounts, CartDiscount};
+use crate::sales::round_money;
+use crate::settings::Settings;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaxClass {
+    pub code: String,
+    pub name: String,
+    pub rate: f64, // percent
+}
+
+/// Tax collected at one rate.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TaxSummary {
+    pub rate: f64,
+    pub net: f64,
+    pub tax: f64,
+    pub gross: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CartLineTotal {
+    pub item_id: i64,
+    pub product_id: i64,
+    pub purchasing_type: String,
+    pub quantity: f64,
+    pub price: f64,
+    pub discount: f64, // manual discount plus promotions
+    pub promotion_discount: f64,
+    pub tax_rate: f64,
+    pub net: f64,
+    pub tax: f64,
+    pub gross: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CartTotals {
+    pub cart_id: i64,
+    pub prices_include_tax: bool,
+    pub lines: Vec<CartLineTotal>,
+    pub discounts: Vec<CartDiscount>,
+    pub taxes: Vec<TaxSummary>,
+    pub subtotal: f64,
+    pub discount: f64,
+    pub tax_total: f64,
+    pub total: f64,
+}
+
+// Vietnamese VAT rates; shops may add their own classes.
+const DEFAULT_CLASSES: [(&str, &str, f64); 4] = [
+    ("VAT0", "VAT 0%", 0.0),
+    ("VAT5", "VAT 5%", 5.0),
+    ("VAT8", "VAT 8%", 8.0),
+    ("VAT10", "VAT 10%", 10.0),
+];
+
+pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tax_classes (
+            code VARCHAR(20) PRIMARY KEY,
+            name VARCHAR(100) NOT NULL,
+            rate FLOAT NOT NULL
+        )",
+        params![],
+    )?;
+    for (code, name, rate) in DEFAULT_CLASSES {
+        conn.execute(
+            "INSERT OR IGNORE INTO tax_classes (code, name, rate) VALUES (?1, ?2, ?3)",
+            params![code, name, rate],
+        )?;
+    }
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS category_tax_classes (
+            category VARCHAR(100) PRIMARY KEY,
+            tax_class VARCHAR(20) NOT NULL,
+            FOREIGN KEY (tax_class) REFERENCES tax_classes(code)
+        )",
+        params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS invoice_taxes (
+            invoice_id VARCHAR(100) NOT NULL,
+            rate FLOAT NOT NULL,
+            net FLOAT NOT NULL,
+            tax FLOAT NOT NULL,
+            gross FLOAT NOT NULL,
+            PRIMARY KEY (invoice_id, rate),
+            FOREIGN KEY (invoice_id) REFERENCES invoices(invoice_id)
+        )",
+        params![],
+    )?;
+    add_column_if_missing(conn, "products", "Tax_class", "VARCHAR(20)")?;
+    add_column_if_missing(conn, "cart_items", "tax_rate", "FLOAT")?;
+    add_column_if_missing(conn, "invoice_items", "tax_rate", "FLOAT NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "invoice_items", "tax_amount", "FLOAT NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "invoices", "tax_total", "FLOAT NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "invoices", "prices_include_tax", "BOOLEAN NOT NULL DEFAULT 1")?;
+    Ok(())
+}
+
+/// The rate for a product: its own tax class, else its category's, else the
+/// default rate from the settings.
+pub fn resolve_rate(conn: &Connection, product_id: i64, default_rate: f64) -> Result<f64, String> {
+    let rate: Option<Option<f64>> = conn.query_row(
+        "SELECT COALESCE(
+                (SELECT t.rate FROM tax_classes t WHERE t.code = p.Tax_class),
+                (SELECT t.rate FROM category_tax_classes c JOIN tax_classes t ON t.code = c.tax_class WHERE c.category = p.Category))
+         FROM products p WHERE p.rowid = ?1",
+        params![product_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| format!("Failed to look up tax class: {}", e))?;
+    Ok(rate.flatten().unwrap_or(default_rate))
+}
+
+/// Splits a line amount (after discount) into net, tax and gross. Inclusive
+/// prices already contain the tax; exclusive prices get it added on top.
+pub fn split_tax(amount: f64, rate: f64, prices_include_tax: bool) -> (f64, f64, f64) {
+    if prices_include_tax {
+        let tax = round_money(amount * rate / (100.0 + rate));
+        (round_money(amount - tax), tax, round_money(amount))
+    } else {
+        let tax = round_money(amount * rate / 100.0);
+        (round_money(amount), tax, round_money(amount + tax))
+    }
+}
+
+/// Groups `(rate, net, tax, gross)` lines per rate, lowest rate first.
+pub fn summarize(lines: &[(f64, f64, f64, f64)]) -> Vec<TaxSummary> {
+    let mut taxes: Vec<TaxSummary> = Vec::new();
+    for &(rate, net, tax, gross) in lines {
+        match taxes.iter_mut().find(|t| t.rate == rate) {
+            Some(t) => {
+                t.net = round_money(t.net + net);
+                t.tax = round_money(t.tax + tax);
+                t.gross = round_money(t.gross + gross);
+            }
+            None => taxes.push(TaxSummary { rate, net, tax, gross }),
+        }
+    }
+    taxes.sort_by(|a, b| a.rate.partial_cmp(&b.rate).unwrap_or(std::cmp::Ordering::Equal));
+    taxes
+}
+
+pub fn compute_cart_totals(conn: &Connection, cart_id: i64) -> Result<CartTotals, String> {
+    let settings = Settings::load(conn)?;
+    let mut stmt = conn.prepare(
+        "SELECT rowid, product_id, purchasing_type, quantity, price, COALESCE(discount, 0), tax_rate FROM cart_items WHERE cart_id = ?1 ORDER BY rowid",
+    )
+    .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let rows: Vec<(i64, i64, String, f64, f64, f64, Option<f64>)> = stmt
+        .query_map(params![cart_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?)))
+        .map_err(|e| format!("Failed to query: {}", e))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    let discounts = load_cart_discounts(conn, cart_id)?;
+
+    let mut lines = Vec::new();
+    for (item_id, product_id, purchasing_type, quantity, price, discount, tax_rate) in rows {
+        let tax_rate = match tax_rate {
+            Some(rate) => rate,
+            None => resolve_rate(conn, product_id, settings.tax_rate)?,
+        };
+        let promotion_discount = round_money(discounts.iter().filter(|d| d.item_id == item_id).map(|d| d.amount).sum());
+        let discount = round_money(discount + promotion_discount);
+        let (net, tax, gross) = split_tax(quantity * price - discount, tax_rate, settings.prices_include_tax);
+        lines.push(CartLineTotal {
+            item_id, product_id, purchasing_type, quantity, price, discount, promotion_discount, tax_rate, net, tax, gross,
+        });
+    }
+    let taxes = summarize(&lines.iter().map(|l| (l.tax_rate, l.net, l.tax, l.gross)).collect::<Vec<_>>());
+    let subtotal = round_money(lines.iter().map(|l| l.quantity * l.price).sum());
+    let discount = round_money(lines.iter().map(|l| l.discount).sum());
+    let tax_total = round_money(lines.iter().map(|l| l.tax).sum());
+    let total = round_money(lines.iter().map(|l| l.gross).sum());
+    Ok(CartTotals {
+        cart_id,
+        prices_include_tax: settings.prices_include_tax,
+        lines,
+        discounts,
+        taxes,
+        subtotal,
+        discount,
+        tax_total,
+        total,
+    })
+}
+
+#[command]
+pub fn cart_totals(window: Window, cart_id: i64) -> Result<CartTotals, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    compute_cart_totals(&conn, cart_id)
+}
+
+#[command]
+pub fn list_tax_classes(window: Window) -> Result<Vec<TaxClass>, String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    let mut stmt = conn.prepare("SELECT code, name, rate FROM tax_classes ORDER BY rate, code")
+        .map_err(|e| format!("Failed to prepare: {}", e))?;
+    let classes = stmt.query_map(params![], |row| {
+        Ok(TaxClass {
+            code: row.get(0)?,
+            name: row.get(1)?,
+            rate: row.get(2)?,
+        })
+    })
+    .map_err(|e| format!("Failed to query: {}", e))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(classes)
+}
+
+fn require_class(conn: &Connection, tax_class: &str) -> Result<(), String> {
+    let exists: bool = conn.query_row("SELECT EXISTS(SELECT 1 FROM tax_classes WHERE code = ?1)", params![tax_class], |row| row.get(0))
+        .map_err(|e| format!("Failed to look up tax class: {}", e))?;
+    if !exists {
+        return Err(format!("Unknown tax class {}", tax_class));
+    }
+    Ok(())
+}
+
+/// Assigns a tax class to a product; `None` falls back to its category.
+#[command]
+pub fn set_product_tax_class(window: Window, product_id: i64, tax_class: Option<String>) -> Result<(), String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    if let Some(code) = &tax_class {
+        require_class(&conn, code)?;
+    }
+    let rows = conn.execute("UPDATE products SET Tax_class = ?1 WHERE rowid = ?2", params![tax_class, product_id])
+        .map_err(|e| format!("Failed to update product: {}", e))?;
+    if rows == 0 {
+        return Err("Product not found".to_string());
+    }
+    Ok(())
+}
+
+/// Assigns a tax class to a category; `None` falls back to the default rate.
+#[command]
+pub fn set_category_tax_class(window: Window, category: String, tax_class: Option<String>) -> Result<(), String> {
+    let db_path = get_db_path(&window);
+    let conn = Connection::open(&db_path).map_err(|e| format!("Failed to open DB: {}", e))?;
+    match tax_class {
+        Some(code) => {
+            require_class(&conn, &code)?;
+            conn.execute(
+                "INSERT INTO category_tax_classes (category, tax_class) VALUES (?1, ?2)
+                 ON CONFLICT(category) DO UPDATE SET tax_class = excluded.tax_class",
+                params![category, code],
+            )
+        }
+        None => conn.execute("DELETE FROM category_tax_classes WHERE category = ?1", params![category]),
+    }
+    .map_err(|e| format!("Failed to update category: {}", e))?;
+    Ok(())
+}
+
+commands![
+    cart_totals,
+    list_tax_classes,
+    set_product_tax_class,
+    set_category_tax_class,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_inclusive_prices() {
+        assert_eq!(split_tax(110000.0, 10.0, true), (100000.0, 10000.0, 110000.0));
+        assert_eq!(split_tax(54000.0, 8.0, true), (50000.0, 4000.0, 54000.0));
+        // Tax is rounded first and the net takes the remainder.
+        assert_eq!(split_tax(10.0, 8.0, true), (9.26, 0.74, 10.0));
+        assert_eq!(split_tax(25000.0, 0.0, true), (25000.0, 0.0, 25000.0));
+    }
+
+    #[test]
+    fn adds_tax_to_exclusive_prices() {
+        assert_eq!(split_tax(100000.0, 10.0, false), (100000.0, 10000.0, 110000.0));
+        assert_eq!(split_tax(9.99, 5.0, false), (9.99, 0.5, 10.49));
+    }
+
+    #[test]
+    fn summarizes_per_rate_lowest_first() {
+        let taxes = summarize(&[
+            (10.0, 100.0, 10.0, 110.0),
+            (5.0, 200.0, 10.0, 210.0),
+            (10.0, 50.0, 5.0, 55.0),
+        ]);
+        let rows: Vec<(f64, f64, f64, f64)> = taxes.iter().map(|t| (t.rate, t.net, t.tax, t.gross)).collect();
+        assert_eq!(rows, vec![(5.0, 200.0, 10.0, 210.0), (10.0, 150.0, 15.0, 165.0)]);
+        assert!(summarize(&[]).is_empty());
+    }
+}
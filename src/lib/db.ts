@@ -372,6 +372,148 @@ export async function listCartItems(cartId: number): Promise<any[]> {
     return await invoke('list_cart_items', { cart_id: cartId });
 }
 
-export async function cleanupExpiredCarts(ttlMinutes: number): Promise<void> {
-    return await invoke('cleanup_expired_carts', { ttl_minutes: ttlMinutes });
-}
\ No newline at end of file
+export async function cleanupExpiredCarts(ttlMinutes?: number): Promise<number[]> {
+    return await invoke('cleanup_expired_carts', { ttl_minutes: ttlMinutes ?? null });
+}
+export async function getSettings(): Promise<any> {
+    return await invoke('get_settings');
+}
+
+export async function updateSettings(settings: any): Promise<any> {
+    return await invoke('update_settings', { settings });
+}
+
+export async function setTerminalSecret(secret: string): Promise<void> {
+    return await invoke('set_terminal_secret', { secret });
+}
+
+export async function cartTotals(cartId: number): Promise<any> {
+    return await invoke('cart_totals', { cart_id: cartId });
+}
+
+export async function listCartDiscounts(cartId: number): Promise<any[]> {
+    return await invoke('list_cart_discounts', { cart_id: cartId });
+}
+
+export async function addScannedItem(cartId: number, barcode: string, quantity?: number): Promise<any> {
+    return await invoke('add_scanned_item', { cart_id: cartId, barcode, quantity: quantity ?? null });
+}
+
+export async function validateBarcode(barcode: string): Promise<any> {
+    return await invoke('validate_barcode', { barcode });
+}
+
+export async function createProduct(product: any): Promise<number> {
+    return await invoke('create_product', { product });
+}
+
+export async function assignInternalBarcodes(productIds?: number[]): Promise<any[]> {
+    return await invoke('assign_internal_barcodes', { product_ids: productIds ?? null });
+}
+
+export async function printLabels(productIds: number[], options: any): Promise<any> {
+    return await invoke('print_labels', { product_ids: productIds, options });
+}
+
+export async function saveCustomer(phone: string, name: string, note?: string): Promise<any> {
+    return await invoke('save_customer', { phone, name, note: note ?? null });
+}
+
+export async function attachCustomer(cartId: number, phone: string | null): Promise<any> {
+    return await invoke('attach_customer', { cart_id: cartId, phone });
+}
+
+export async function customerHistory(phone: string, limit?: number): Promise<any[]> {
+    return await invoke('customer_history', { phone, limit: limit ?? null });
+}
+
+export async function setCreditLimit(phone: string, creditLimit: number): Promise<any> {
+    return await invoke('set_credit_limit', { phone, credit_limit: creditLimit });
+}
+
+export async function recordRepayment(phone: string, amount: number, method?: string, note?: string): Promise<any> {
+    return await invoke('record_repayment', { phone, amount, method: method ?? null, note: note ?? null });
+}
+
+export async function listCreditBalances(): Promise<any[]> {
+    return await invoke('list_credit_balances');
+}
+
+export async function creditAging(): Promise<any[]> {
+    return await invoke('credit_aging');
+}
+
+export async function exportParkedCart(cartId: number): Promise<{ json: string; qr: string | null }> {
+    return await invoke('export_parked_cart', { cart_id: cartId });
+}
+
+export async function importParkedCart(payload: string): Promise<any> {
+    return await invoke('import_parked_cart', { payload });
+}
+
+export async function syncStatus(): Promise<any> {
+    return await invoke('sync_status');
+}
+
+export async function listSyncConflicts(): Promise<any[]> {
+    return await invoke('list_sync_conflicts');
+}
+
+export async function journalStatus(): Promise<any> {
+    return await invoke('journal_status');
+}
+
+export async function exportJournal(dir?: string): Promise<any | null> {
+    return await invoke('export_journal', { dir });
+}
+
+export async function ackJournal(seq: number): Promise<any> {
+    return await invoke('ack_journal', { seq });
+}
+
+export async function importCatalogUpdates(path: string): Promise<any> {
+    return await invoke('import_catalog_updates', { path });
+}
+
+export async function backupNow(): Promise<any> {
+    return await invoke('backup_now');
+}
+
+export async function listBackups(): Promise<any[]> {
+    return await invoke('list_backups');
+}
+
+export async function restoreBackup(path: string): Promise<any> {
+    return await invoke('restore_backup', { path });
+}
+
+export async function lastIntegrityReport(): Promise<any> {
+    return await invoke('last_integrity_report');
+}
+
+export async function checkDatabase(): Promise<any> {
+    return await invoke('check_database');
+}
+
+export async function repairDatabase(restore: boolean): Promise<any> {
+    return await invoke('repair_database', { restore });
+}
+
+export async function setupStatus(): Promise<any> {
+    return await invoke('setup_status');
+}
+
+export async function completeSetup(setup: {
+    store_name: string;
+    store_id: string;
+    storeman_id?: string | null;
+    admin_username: string;
+    admin_password: string;
+    seed_csv?: string | null;
+}): Promise<any> {
+    return await invoke('complete_setup', { setup });
+}
+
+export async function verifyLogin(username: string, password: string): Promise<any> {
+    return await invoke('login', { username, password });
+}